@@ -0,0 +1,222 @@
+//! Approval token issuance and validation
+//!
+//! Tokens are minted by `PolicyService.RequestApproval` once the user has
+//! confirmed an action, and are bound to a fingerprint of that exact action.
+//! Each token can be presented once and expires after the configured TTL.
+
+use chrono::{DateTime, Duration, Utc};
+use sha2::{Sha256, Digest};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+use crate::error::{McpError, McpResult};
+
+/// The action an approval token is bound to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApprovalAction {
+    /// Service performing the action (e.g. "file", "command")
+    pub service: String,
+    /// Operation within the service (e.g. "create", "execute")
+    pub operation: String,
+    /// Operation arguments, in order
+    pub arguments: Vec<String>,
+    /// Paths the action affects
+    pub paths: Vec<PathBuf>,
+    /// Hash of the previewed change the action must make, if any
+    pub preview_hash: Option<String>,
+}
+
+impl ApprovalAction {
+    pub fn new(service: &str, operation: &str) -> Self {
+        Self {
+            service: service.to_string(),
+            operation: operation.to_string(),
+            arguments: Vec::new(),
+            paths: Vec::new(),
+            preview_hash: None,
+        }
+    }
+
+    pub fn with_arguments<I, S>(mut self, arguments: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.arguments.extend(arguments.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn with_paths<I, P>(mut self, paths: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        self.paths.extend(paths.into_iter().map(|p| p.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Bind the action to a previewed change; an empty hash leaves it unbound
+    pub fn with_preview_hash(mut self, hash: &str) -> Self {
+        self.preview_hash = Some(hash.trim().to_lowercase()).filter(|h| !h.is_empty());
+        self
+    }
+
+    /// Compute a stable fingerprint of the normalized action
+    pub fn fingerprint(&self) -> String {
+        let mut paths: Vec<String> = self.paths.iter()
+            .map(|p| normalize_path(p).to_string_lossy().to_string())
+            .collect();
+        paths.sort();
+        paths.dedup();
+
+        let mut paths_hasher = Sha256::new();
+        for path in &paths {
+            paths_hasher.update(path.as_bytes());
+            paths_hasher.update([0u8]);
+        }
+        let paths_hash = hex::encode(paths_hasher.finalize());
+
+        let mut hasher = Sha256::new();
+        hasher.update(self.service.trim().to_lowercase().as_bytes());
+        hasher.update([0u8]);
+        hasher.update(self.operation.trim().to_lowercase().as_bytes());
+        hasher.update([0u8]);
+        hasher.update((self.arguments.len() as u64).to_le_bytes());
+        for arg in &self.arguments {
+            hasher.update((arg.len() as u64).to_le_bytes());
+            hasher.update(arg.as_bytes());
+        }
+        hasher.update(paths_hash.as_bytes());
+        if let Some(preview_hash) = &self.preview_hash {
+            hasher.update([0u8]);
+            hasher.update(preview_hash.as_bytes());
+        }
+        hex::encode(hasher.finalize())
+    }
+
+    /// Human-readable summary for audit entries
+    pub fn describe(&self) -> String {
+        let mut summary = format!("{}.{}", self.service, self.operation);
+        if !self.arguments.is_empty() {
+            summary.push_str(&format!(" [{}]", self.arguments.join(" ")));
+        }
+        if !self.paths.is_empty() {
+            let paths: Vec<String> = self.paths.iter().map(|p| p.display().to_string()).collect();
+            summary.push_str(&format!(" on {}", paths.join(", ")));
+        }
+        if let Some(preview_hash) = &self.preview_hash {
+            let short: String = preview_hash.chars().take(12).collect();
+            summary.push_str(&format!(" (preview {})", short));
+        }
+        summary
+    }
+}
+
+/// Lexically normalize a path, resolving `.` and `..` without touching the filesystem
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other.as_os_str()),
+        }
+    }
+    normalized
+}
+
+/// A freshly minted approval token
+#[derive(Debug, Clone)]
+pub struct IssuedApproval {
+    pub token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+struct PendingApproval {
+    fingerprint: String,
+    expires_at: DateTime<Utc>,
+}
+
+/// In-memory store of outstanding approval tokens
+pub struct ApprovalStore {
+    pending: Mutex<HashMap<String, PendingApproval>>,
+}
+
+impl ApprovalStore {
+    pub fn new() -> Self {
+        Self {
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Mint a single-use token for the given action
+    pub fn issue(&self, action: &ApprovalAction, ttl_secs: u64) -> IssuedApproval {
+        let token = Uuid::new_v4().to_string();
+        let expires_at = Utc::now() + Duration::seconds(ttl_secs as i64);
+
+        let mut pending = self.pending.lock().unwrap();
+        Self::purge_expired(&mut pending);
+        pending.insert(token.clone(), PendingApproval {
+            fingerprint: action.fingerprint(),
+            expires_at,
+        });
+
+        IssuedApproval { token, expires_at }
+    }
+
+    /// Consume a token for the given action.
+    ///
+    /// The token is removed on every presentation, so a mismatched or replayed
+    /// token can never be retried.
+    pub fn consume(&self, token: &str, action: &ApprovalAction) -> McpResult<()> {
+        self.redeem(token, action, true)
+    }
+
+    /// Check that a token is valid for the given action without using it up.
+    /// A token that is not valid is still removed, as by `consume`.
+    pub fn check(&self, token: &str, action: &ApprovalAction) -> McpResult<()> {
+        self.redeem(token, action, false)
+    }
+
+    fn redeem(&self, token: &str, action: &ApprovalAction, consume: bool) -> McpResult<()> {
+        if token.is_empty() {
+            return Err(McpError::ApprovalRequired(action.describe()));
+        }
+
+        let mut pending = self.pending.lock().unwrap();
+        let approval = pending.remove(token)
+            .ok_or_else(|| McpError::InvalidApproval("Unknown or already used approval token".to_string()))?;
+        Self::purge_expired(&mut pending);
+
+        if approval.expires_at <= Utc::now() {
+            return Err(McpError::InvalidApproval("Approval token has expired".to_string()));
+        }
+
+        if approval.fingerprint != action.fingerprint() {
+            return Err(McpError::InvalidApproval(format!(
+                "Approval token was not issued for {}",
+                action.describe()
+            )));
+        }
+
+        if !consume {
+            pending.insert(token.to_string(), approval);
+        }
+        Ok(())
+    }
+
+    fn purge_expired(pending: &mut HashMap<String, PendingApproval>) {
+        let now = Utc::now();
+        pending.retain(|_, approval| approval.expires_at > now);
+    }
+}
+
+impl Default for ApprovalStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,114 @@
+//! Artifacts holding the full output of commands whose output was truncated
+//!
+//! Each artifact is a directory named by its id, holding one file per
+//! stream (`stdout` and `stderr`). The directory is only created once a
+//! command's output exceeds its cap, and only the most recent artifacts are
+//! kept.
+
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use crate::error::{McpError, McpResult};
+
+/// Artifacts kept before the oldest are removed
+pub const MAX_ARTIFACTS: usize = 100;
+
+/// Most bytes returned by a single read
+pub const MAX_READ_BYTES: u64 = 1024 * 1024;
+
+/// Names of the files in an artifact
+const STREAMS: &[&str] = &["stdout", "stderr"];
+
+/// A range of bytes read from an artifact
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArtifactRead {
+    pub data: Vec<u8>,
+    /// Size of the whole stream
+    pub total_bytes: u64,
+}
+
+pub struct ArtifactStore {
+    base_dir: PathBuf,
+}
+
+impl ArtifactStore {
+    pub fn new(base_dir: &Path) -> McpResult<Self> {
+        fs::create_dir_all(base_dir)?;
+        // Output can hold anything the command printed
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(base_dir, fs::Permissions::from_mode(0o700))?;
+        }
+
+        Ok(Self { base_dir: base_dir.to_path_buf() })
+    }
+
+    /// Reserve an id and directory for a new artifact, removing the oldest
+    /// artifacts to make room
+    pub fn allocate(&self) -> (String, PathBuf) {
+        self.prune(MAX_ARTIFACTS.saturating_sub(1));
+        let id = Uuid::new_v4().to_string();
+        let dir = self.base_dir.join(&id);
+        (id, dir)
+    }
+
+    /// Whether anything was written to the artifact
+    pub fn exists(&self, id: &str) -> bool {
+        Uuid::parse_str(id).is_ok() && self.base_dir.join(id).is_dir()
+    }
+
+    /// Read up to `max_bytes` (capped at `MAX_READ_BYTES`) of a stream,
+    /// starting at `offset`
+    pub fn read(&self, id: &str, stream: &str, offset: u64, max_bytes: u64) -> McpResult<ArtifactRead> {
+        if Uuid::parse_str(id).is_err() {
+            return Err(McpError::InvalidArgument(format!("Invalid artifact id '{}'", id)));
+        }
+        if !STREAMS.contains(&stream) {
+            return Err(McpError::InvalidArgument(format!(
+                "Unknown stream '{}', expected stdout or stderr",
+                stream
+            )));
+        }
+
+        let path = self.base_dir.join(id).join(stream);
+        let mut file = fs::File::open(&path).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => McpError::NotFound(format!("Artifact '{}' not found", id)),
+            _ => McpError::IoError(e),
+        })?;
+        let total_bytes = file.metadata()?.len();
+
+        let max_bytes = if max_bytes == 0 { MAX_READ_BYTES } else { max_bytes.min(MAX_READ_BYTES) };
+        let mut data = Vec::new();
+        if offset < total_bytes {
+            file.seek(SeekFrom::Start(offset))?;
+            file.take(max_bytes).read_to_end(&mut data)?;
+        }
+
+        Ok(ArtifactRead { data, total_bytes })
+    }
+
+    /// Remove the oldest artifacts until at most `keep` are left
+    fn prune(&self, keep: usize) {
+        let Ok(entries) = fs::read_dir(&self.base_dir) else {
+            return;
+        };
+        let mut artifacts: Vec<_> = entries
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+            .filter_map(|e| Some((e.metadata().ok()?.modified().ok()?, e.path())))
+            .collect();
+        if artifacts.len() <= keep {
+            return;
+        }
+
+        artifacts.sort();
+        for (_, path) in &artifacts[..artifacts.len() - keep] {
+            if let Err(e) = fs::remove_dir_all(path) {
+                tracing::warn!("Failed to remove output artifact {}: {}", path.display(), e);
+            }
+        }
+    }
+}
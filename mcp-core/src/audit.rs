@@ -20,6 +20,12 @@ pub struct AuditEntry {
     pub approval_token: Option<String>,
     pub result: String,
     pub snapshot_id: Option<String>,
+    /// Policy rule that decided the action, if any
+    pub rule_id: Option<String>,
+    /// How long the action took, for actions that run for a while
+    pub duration_ms: Option<u64>,
+    /// Artifact holding output too large to return, if any
+    pub artifact_id: Option<String>,
 }
 
 pub struct AuditLogger {
@@ -48,11 +54,19 @@ impl AuditLogger {
                 user_approved INTEGER NOT NULL,
                 approval_token TEXT,
                 result TEXT NOT NULL,
-                snapshot_id TEXT
+                snapshot_id TEXT,
+                rule_id TEXT,
+                duration_ms INTEGER,
+                artifact_id TEXT
             )",
             [],
         ).map_err(|e| McpError::DatabaseError(e.to_string()))?;
 
+        // Databases created before rule ids were recorded lack the column
+        Self::ensure_column(&conn, "rule_id", "TEXT")?;
+        Self::ensure_column(&conn, "duration_ms", "INTEGER")?;
+        Self::ensure_column(&conn, "artifact_id", "TEXT")?;
+
         // Create index for faster queries
         conn.execute(
             "CREATE INDEX IF NOT EXISTS idx_audit_timestamp ON audit_log(timestamp)",
@@ -64,14 +78,31 @@ impl AuditLogger {
         })
     }
 
+    /// Add a column to the audit table if it does not exist yet
+    fn ensure_column(conn: &Connection, name: &str, definition: &str) -> McpResult<()> {
+        let mut stmt = conn.prepare("PRAGMA table_info(audit_log)")
+            .map_err(|e| McpError::DatabaseError(e.to_string()))?;
+        let columns = stmt.query_map([], |row| row.get::<_, String>(1))
+            .map_err(|e| McpError::DatabaseError(e.to_string()))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| McpError::DatabaseError(e.to_string()))?;
+
+        if !columns.iter().any(|c| c == name) {
+            conn.execute(&format!("ALTER TABLE audit_log ADD COLUMN {} {}", name, definition), [])
+                .map_err(|e| McpError::DatabaseError(e.to_string()))?;
+        }
+
+        Ok(())
+    }
+
     /// Log an action
     pub fn log(&self, entry: AuditEntry) -> McpResult<String> {
         let conn = self.conn.lock()
             .map_err(|e| McpError::DatabaseError(e.to_string()))?;
 
         conn.execute(
-            "INSERT INTO audit_log (id, timestamp, action, service, details, user_approved, approval_token, result, snapshot_id)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            "INSERT INTO audit_log (id, timestamp, action, service, details, user_approved, approval_token, result, snapshot_id, rule_id, duration_ms, artifact_id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
             params![
                 entry.id,
                 entry.timestamp.to_rfc3339(),
@@ -82,6 +113,9 @@ impl AuditLogger {
                 entry.approval_token,
                 entry.result,
                 entry.snapshot_id,
+                entry.rule_id,
+                entry.duration_ms.map(|d| d as i64),
+                entry.artifact_id,
             ],
         ).map_err(|e| McpError::DatabaseError(e.to_string()))?;
 
@@ -100,6 +134,9 @@ impl AuditLogger {
             approval_token: None,
             result: "pending".to_string(),
             snapshot_id: None,
+            rule_id: None,
+            duration_ms: None,
+            artifact_id: None,
         }
     }
 
@@ -115,7 +152,10 @@ impl AuditLogger {
         let conn = self.conn.lock()
             .map_err(|e| McpError::DatabaseError(e.to_string()))?;
 
-        let mut sql = String::from("SELECT * FROM audit_log WHERE 1=1");
+        let mut sql = String::from(
+            "SELECT id, timestamp, action, service, details, user_approved, approval_token, result, snapshot_id, rule_id, duration_ms, artifact_id
+             FROM audit_log WHERE 1=1",
+        );
         let mut params_vec: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
 
         if let Some(s) = service {
@@ -159,6 +199,9 @@ impl AuditLogger {
                 approval_token: row.get(6)?,
                 result: row.get(7)?,
                 snapshot_id: row.get(8)?,
+                rule_id: row.get(9)?,
+                duration_ms: row.get::<_, Option<i64>>(10)?.map(|d| d as u64),
+                artifact_id: row.get(11)?,
             })
         }).map_err(|e| McpError::DatabaseError(e.to_string()))?;
 
@@ -170,6 +213,42 @@ impl AuditLogger {
         Ok(result)
     }
 
+    /// Durations of the most recent successful actions whose details start
+    /// with `details_prefix`, newest first
+    pub fn recent_durations(
+        &self,
+        service: &str,
+        action: &str,
+        details_prefix: &str,
+        limit: usize,
+    ) -> McpResult<Vec<u64>> {
+        let conn = self.conn.lock()
+            .map_err(|e| McpError::DatabaseError(e.to_string()))?;
+
+        let escaped = details_prefix
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        let mut stmt = conn.prepare(
+            "SELECT duration_ms FROM audit_log
+             WHERE service = ?1 AND action = ?2 AND result = 'success'
+               AND duration_ms IS NOT NULL AND details LIKE ?3 ESCAPE '\\'
+             ORDER BY timestamp DESC LIMIT ?4",
+        ).map_err(|e| McpError::DatabaseError(e.to_string()))?;
+
+        let durations = stmt.query_map(
+            params![service, action, format!("{}%", escaped), limit as i64],
+            |row| row.get::<_, i64>(0),
+        ).map_err(|e| McpError::DatabaseError(e.to_string()))?;
+
+        let mut result = Vec::new();
+        for duration in durations {
+            result.push(duration.map_err(|e| McpError::DatabaseError(e.to_string()))? as u64);
+        }
+
+        Ok(result)
+    }
+
     /// Get total count of audit entries
     pub fn count(&self) -> McpResult<usize> {
         let conn = self.conn.lock()
@@ -0,0 +1,91 @@
+//! Resolution of whitelisted commands to the binaries that run
+//!
+//! Commands are looked up on the server's `PATH`, leaving out relative
+//! entries (like `node_modules/.bin`), and run by absolute path, so neither
+//! the working directory nor a request can substitute another binary. A
+//! whitelist entry can be pinned to a path and to the sha256 of its binary
+//! with `command_pins`. Pinned binaries are hashed each time they are
+//! resolved; whoever can write to the binary could still swap it between
+//! the check and the run.
+
+use sha2::{Digest, Sha256};
+use std::ffi::OsString;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use crate::config::CommandPin;
+use crate::error::{McpError, McpResult};
+
+/// The binary a command runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedBinary {
+    /// Absolute path of the binary
+    pub path: PathBuf,
+    /// Hash of the binary, when it is pinned
+    pub sha256: Option<String>,
+}
+
+/// Whether a command names a path rather than something to look up
+pub fn is_path(command: &str) -> bool {
+    command.contains('/') || command.contains(std::path::MAIN_SEPARATOR)
+}
+
+/// Resolve a whitelisted command to its binary, checking it against `pin`
+pub fn resolve(command: &str, pin: Option<&CommandPin>) -> McpResult<ResolvedBinary> {
+    let path = if is_path(command) {
+        let path = PathBuf::from(command);
+        if !path.is_absolute() {
+            return Err(McpError::PolicyViolation(format!(
+                "Command path '{}' must be absolute",
+                command
+            )));
+        }
+        path
+    } else if let Some(path) = pin.and_then(|p| p.path.clone()) {
+        path
+    } else {
+        which::which_in(command, search_path(), "/").map_err(|_| {
+            McpError::NotFound(format!("Command '{}' was not found on the server's PATH", command))
+        })?
+    };
+
+    if !path.is_file() {
+        return Err(McpError::NotFound(format!(
+            "Binary '{}' for command '{}' does not exist",
+            path.display(),
+            command
+        )));
+    }
+
+    let sha256 = match pin.and_then(|p| p.sha256.as_deref()) {
+        Some(expected) => {
+            let actual = sha256_file(&path)?;
+            if !actual.eq_ignore_ascii_case(expected) {
+                return Err(McpError::PolicyViolation(format!(
+                    "Binary '{}' for command '{}' does not match its pinned sha256 (found {})",
+                    path.display(),
+                    command,
+                    actual
+                )));
+            }
+            Some(actual)
+        }
+        None => None,
+    };
+
+    Ok(ResolvedBinary { path, sha256 })
+}
+
+/// The server's `PATH` without relative entries
+pub fn search_path() -> Option<OsString> {
+    let path = std::env::var_os("PATH")?;
+    std::env::join_paths(std::env::split_paths(&path).filter(|dir| dir.is_absolute())).ok()
+}
+
+/// Hex-encoded sha256 of a file's contents
+pub fn sha256_file(path: &Path) -> McpResult<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
@@ -0,0 +1,279 @@
+//! Argument-aware command line matching
+//!
+//! Command lines are tokenized into program, global flags, subcommand,
+//! flags and positional arguments instead of being joined into a string, so
+//! that `rm -r -f`, `rm -fr` and `rm --recursive --force` all match the
+//! pattern `rm -rf`, while `cargo fmt -- --format` does not match `format`.
+
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// Per-tool knowledge needed to tokenize a command line
+struct ToolSpec {
+    /// Whether the first positional argument is a subcommand
+    has_subcommand: bool,
+    /// Options before the subcommand that consume the next token as a value
+    global_value_flags: &'static [&'static str],
+    /// Short options that consume the rest of the token or the next token
+    value_short_flags: &'static [char],
+    /// Long options that consume the next token when not given as `--flag=value`
+    value_long_flags: &'static [&'static str],
+    /// Equivalent spellings mapped to a canonical flag
+    aliases: &'static [(&'static str, &'static str)],
+    /// Flags that make an otherwise read-only invocation unsafe to auto-approve
+    unsafe_flags: &'static [&'static str],
+}
+
+const DEFAULT_SPEC: ToolSpec = ToolSpec {
+    has_subcommand: false,
+    global_value_flags: &[],
+    value_short_flags: &[],
+    value_long_flags: &[],
+    aliases: &[],
+    unsafe_flags: &[],
+};
+
+fn tool_spec(program: &str) -> &'static ToolSpec {
+    const GIT: ToolSpec = ToolSpec {
+        has_subcommand: true,
+        global_value_flags: &["-C", "-c", "--git-dir", "--work-tree", "--namespace", "--exec-path", "--config-env"],
+        value_short_flags: &['m', 'F', 'C', 'c', 'b', 'B', 'n', 'o', 'S', 'X'],
+        value_long_flags: &["--message", "--file", "--author", "--date", "--output", "--upload-pack", "--receive-pack", "--exec"],
+        aliases: &[("--force", "-f"), ("--force-with-lease", "-f"), ("--force-if-includes", "-f"), ("--delete", "-d")],
+        unsafe_flags: &["--output", "--ext-diff", "--textconv", "--upload-pack", "--receive-pack", "--exec", "--open-files-in-pager", "-O"],
+    };
+    const NPM: ToolSpec = ToolSpec {
+        has_subcommand: true,
+        global_value_flags: &["--prefix", "--userconfig", "--registry"],
+        value_short_flags: &['w'],
+        value_long_flags: &["--workspace", "--tag", "--otp", "--registry", "--prefix"],
+        aliases: &[("--force", "-f"), ("--global", "-g")],
+        unsafe_flags: &["--prefix", "--userconfig", "--registry"],
+    };
+    const CARGO: ToolSpec = ToolSpec {
+        has_subcommand: true,
+        global_value_flags: &["--config", "-Z", "--color", "-C"],
+        value_short_flags: &['p', 'j', 'F', 'Z', 'C'],
+        value_long_flags: &["--package", "--jobs", "--features", "--target", "--manifest-path", "--bin", "--example", "--test", "--bench", "--profile", "--target-dir", "--registry", "--token"],
+        aliases: &[("--package", "-p"), ("--features", "-F"), ("--jobs", "-j")],
+        unsafe_flags: &["--config", "-Z"],
+    };
+    const DOCKER: ToolSpec = ToolSpec {
+        has_subcommand: true,
+        global_value_flags: &["-H", "--host", "--context", "-c", "--config", "-l", "--log-level"],
+        value_short_flags: &['e', 'p', 'v', 'w', 'u', 't', 'f', 'm', 'c', 'l'],
+        value_long_flags: &["--env", "--publish", "--volume", "--workdir", "--user", "--tag", "--file", "--memory", "--name", "--network", "--mount", "--entrypoint"],
+        aliases: &[("--force", "-f"), ("--volume", "-v"), ("--publish", "-p")],
+        unsafe_flags: &["-H", "--host", "--context"],
+    };
+    const DOTNET: ToolSpec = ToolSpec { has_subcommand: true, ..DEFAULT_SPEC };
+    const RM: ToolSpec = ToolSpec {
+        has_subcommand: false,
+        global_value_flags: &[],
+        value_short_flags: &[],
+        value_long_flags: &[],
+        aliases: &[("-R", "-r"), ("--recursive", "-r"), ("--force", "-f")],
+        unsafe_flags: &[],
+    };
+
+    match program {
+        "git" => &GIT,
+        "npm" | "pnpm" | "yarn" => &NPM,
+        "cargo" => &CARGO,
+        "docker" => &DOCKER,
+        "rm" => &RM,
+        "dotnet" => &DOTNET,
+        _ => &DEFAULT_SPEC,
+    }
+}
+
+/// A tokenized command line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedCommand {
+    /// Program name, without any directory
+    pub program: String,
+    /// Canonical flags given before the subcommand
+    pub global_flags: BTreeSet<String>,
+    /// Subcommand, for tools that have one
+    pub subcommand: Option<String>,
+    /// Canonical flags given after the subcommand
+    pub flags: BTreeSet<String>,
+    /// Positional arguments, including everything after `--`
+    pub positionals: Vec<String>,
+}
+
+impl ParsedCommand {
+    /// Tokenize a command and its arguments
+    pub fn parse(command: &str, args: &[String]) -> Self {
+        Self::parse_indexed(command, args).0
+    }
+
+    /// The command line as policy rules see it: the program name without any
+    /// directory, then the subcommand and everything after it, so that
+    /// `/usr/bin/npm --registry x publish` reads as `npm publish`. Tools
+    /// without a subcommand keep all their arguments.
+    pub fn normalize_argv(command: &str, args: &[String]) -> Vec<String> {
+        let (parsed, subcommand_index) = Self::parse_indexed(command, args);
+        let rest = &args[subcommand_index.unwrap_or(0)..];
+        std::iter::once(parsed.program).chain(rest.iter().cloned()).collect()
+    }
+
+    /// Tokenize a command, also returning the index of the subcommand in `args`
+    fn parse_indexed(command: &str, args: &[String]) -> (Self, Option<usize>) {
+        let program = Path::new(command)
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| command.to_string());
+        let spec = tool_spec(&program);
+
+        let mut parsed = Self {
+            program,
+            global_flags: BTreeSet::new(),
+            subcommand: None,
+            flags: BTreeSet::new(),
+            positionals: Vec::new(),
+        };
+
+        let mut tokens = args.iter().enumerate();
+        let mut options_ended = false;
+        let mut subcommand_index = None;
+
+        while let Some((index, token)) = tokens.next() {
+            let before_subcommand = spec.has_subcommand && parsed.subcommand.is_none();
+
+            if options_ended || token == "-" || !token.starts_with('-') {
+                if before_subcommand && !options_ended {
+                    parsed.subcommand = Some(token.clone());
+                    subcommand_index = Some(index);
+                } else {
+                    parsed.positionals.push(token.clone());
+                }
+                continue;
+            }
+
+            if token == "--" {
+                options_ended = true;
+                continue;
+            }
+
+            let target = if before_subcommand { &mut parsed.global_flags } else { &mut parsed.flags };
+
+            if let Some(long) = token.strip_prefix("--") {
+                let name = match long.split_once('=') {
+                    Some((name, _)) => format!("--{}", name),
+                    None => {
+                        let name = format!("--{}", long);
+                        let takes_value = if before_subcommand {
+                            spec.global_value_flags.contains(&name.as_str())
+                        } else {
+                            spec.value_long_flags.contains(&name.as_str())
+                        };
+                        if takes_value {
+                            tokens.next();
+                        }
+                        name
+                    }
+                };
+                target.insert(canonical_flag(spec, &name));
+                continue;
+            }
+
+            // Short flags: `-rf` is `-r -f`; a value flag consumes the rest of the token
+            if before_subcommand && spec.global_value_flags.contains(&token.as_str()) {
+                target.insert(canonical_flag(spec, token));
+                tokens.next();
+                continue;
+            }
+
+            let chars: Vec<char> = token[1..].chars().collect();
+            for (i, c) in chars.iter().enumerate() {
+                let flag = format!("-{}", c);
+                target.insert(canonical_flag(spec, &flag));
+                if spec.value_short_flags.contains(c) {
+                    if i + 1 == chars.len() {
+                        tokens.next();
+                    }
+                    break;
+                }
+            }
+        }
+
+        (parsed, subcommand_index)
+    }
+
+    fn has_flag(&self, flag: &str) -> bool {
+        self.flags.contains(flag) || self.global_flags.contains(flag)
+    }
+}
+
+fn canonical_flag(spec: &ToolSpec, flag: &str) -> String {
+    spec.aliases.iter()
+        .find(|(alias, _)| *alias == flag)
+        .map(|(_, canonical)| canonical.to_string())
+        .unwrap_or_else(|| flag.to_string())
+}
+
+/// A command pattern such as `git push --force` or `rm -rf`. Its positional
+/// arguments are globs, so `git push +*` matches any `+` refspec.
+#[derive(Debug, Clone)]
+pub struct CommandPattern {
+    pattern: ParsedCommand,
+    positionals: Vec<glob::Pattern>,
+}
+
+impl CommandPattern {
+    /// Parse a whitespace-separated pattern with the same rules as a command line
+    pub fn parse(pattern: &str) -> Option<Self> {
+        let mut tokens = pattern.split_whitespace();
+        let program = tokens.next()?;
+        let args: Vec<String> = tokens.map(str::to_string).collect();
+        let pattern = ParsedCommand::parse(program, &args);
+        // An argument that is not a valid glob is matched literally
+        let positionals = pattern.positionals.iter()
+            .map(|arg| glob::Pattern::new(arg).unwrap_or_else(|_| {
+                glob::Pattern::new(&glob::Pattern::escape(arg)).expect("escaped pattern is valid")
+            }))
+            .collect();
+        Some(Self { pattern, positionals })
+    }
+
+    /// Whether the command contains everything the pattern specifies.
+    ///
+    /// Extra flags and arguments are allowed, so `git push --force` matches
+    /// `git push origin main -f`.
+    pub fn matches(&self, command: &ParsedCommand) -> bool {
+        let p = &self.pattern;
+
+        if p.program != command.program {
+            return false;
+        }
+
+        if p.subcommand.is_some() && p.subcommand != command.subcommand {
+            return false;
+        }
+
+        if !p.flags.iter().chain(p.global_flags.iter()).all(|f| command.has_flag(f)) {
+            return false;
+        }
+
+        self.positionals.iter().all(|glob| command.positionals.iter().any(|arg| glob.matches(arg)))
+    }
+
+    /// Whether the pattern matches and the command adds nothing that could
+    /// change a read-only invocation into a mutating one.
+    ///
+    /// Used for auto-approval: `git status` allows `git status --short`, but
+    /// not `git -c core.pager=sh status` or `git diff --output=file`.
+    pub fn allows(&self, command: &ParsedCommand) -> bool {
+        if !self.matches(command) {
+            return false;
+        }
+
+        if !command.global_flags.is_subset(&self.pattern.global_flags) {
+            return false;
+        }
+
+        let spec = tool_spec(&command.program);
+        !spec.unsafe_flags.iter().any(|f| command.has_flag(f))
+    }
+}
@@ -1,26 +1,62 @@
 //! Configuration management for MCP Core
 
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::PathBuf;
+use crate::binaries::{self, ResolvedBinary};
+use crate::confine::Confinement;
 use crate::error::{McpError, McpResult};
+use crate::sandbox::ResourceLimits;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     /// Server listen address
     pub server_address: String,
 
-    /// Allowed root paths for file operations
+    /// Unix socket serving the policy service, which changes the config and
+    /// issues approvals; only the server's user can connect to it
+    #[serde(default = "default_policy_socket")]
+    pub policy_socket: PathBuf,
+
+    /// Allowed root paths for file operations, with full permissions
     pub allowed_paths: Vec<PathBuf>,
 
-    /// Whitelisted commands that can be executed
+    /// Allowed roots with explicit permissions; the most specific root wins
+    #[serde(default)]
+    pub path_zones: Vec<PathZone>,
+
+    /// Globs that are denied inside every allowed root
+    #[serde(default = "default_deny_globs")]
+    pub deny_globs: Vec<String>,
+
+    /// Whitelisted commands that can be executed, by name or absolute path
     pub whitelisted_commands: Vec<String>,
 
+    /// Binaries that whitelisted commands must resolve to, by command name
+    #[serde(default)]
+    pub command_pins: HashMap<String, CommandPin>,
+
     /// Path to audit database
     pub audit_db_path: PathBuf,
 
     /// Directory for snapshots
     pub snapshot_dir: PathBuf,
 
+    /// Directory for the full output of commands whose output was truncated
+    #[serde(default = "default_artifact_dir")]
+    pub artifact_dir: PathBuf,
+
+    /// Most bytes of a command's stdout, and of its stderr, returned by Run;
+    /// past it, the head and tail are returned and the full output is kept
+    /// as an artifact (0 = unlimited)
+    #[serde(default = "default_max_output_bytes")]
+    pub max_output_bytes: u64,
+
+    /// File and directory names left out of pre-command snapshots of a
+    /// working directory (globs matched against each name)
+    #[serde(default = "default_snapshot_ignore")]
+    pub snapshot_ignore: Vec<String>,
+
     /// Maximum file size for read operations (bytes)
     pub max_file_size: u64,
 
@@ -36,10 +72,143 @@ pub struct Config {
     /// Enable sandbox mode for command execution
     pub sandbox_enabled: bool,
 
+    /// Resource limits applied to sandboxed commands
+    #[serde(default = "default_sandbox_limits")]
+    pub sandbox_limits: ResourceLimits,
+
+    /// Which environment variables commands get
+    #[serde(default)]
+    pub env_policy: EnvPolicy,
+
+    /// Extra regular expressions for secrets scrubbed from command output;
+    /// a `secret` group limits the redaction to that part of the match
+    #[serde(default)]
+    pub secret_patterns: Vec<String>,
+
+    /// Path to the declarative policy rule file
+    #[serde(default = "default_policy_rules_path")]
+    pub policy_rules_path: PathBuf,
+
+    /// Path to the user-defined dry-run effect predictors
+    #[serde(default = "default_effect_rules_path")]
+    pub effect_rules_path: PathBuf,
+
+    /// How long an issued approval token stays valid (seconds)
+    #[serde(default = "default_approval_ttl_secs")]
+    pub approval_ttl_secs: u64,
+
     /// LLM provider configuration
     pub llm_config: LlmConfig,
 }
 
+/// The binary a whitelisted command is pinned to
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CommandPin {
+    /// Absolute path to run instead of looking the command up on `PATH`
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+
+    /// Expected sha256 of the binary (hex)
+    #[serde(default)]
+    pub sha256: Option<String>,
+}
+
+/// Environment variables for commands, which otherwise start from an empty
+/// environment
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnvPolicy {
+    /// Variables copied from the server's environment for every command
+    #[serde(default = "default_env_passthrough")]
+    pub passthrough: Vec<String>,
+
+    /// Additional variables copied for particular commands
+    #[serde(default)]
+    pub command_passthrough: HashMap<String, Vec<String>>,
+
+    /// Variables a request may set without approval (globs)
+    #[serde(default = "default_env_allowed")]
+    pub allowed: Vec<String>,
+
+    /// Variables a request may never set (globs); any variable that is
+    /// neither allowed nor denied requires approval
+    #[serde(default = "default_env_denied")]
+    pub denied: Vec<String>,
+}
+
+impl Default for EnvPolicy {
+    fn default() -> Self {
+        Self {
+            passthrough: default_env_passthrough(),
+            command_passthrough: HashMap::new(),
+            allowed: default_env_allowed(),
+            denied: default_env_denied(),
+        }
+    }
+}
+
+impl EnvPolicy {
+    /// Names of the server variables passed through to `command`
+    pub fn passthrough_for(&self, command: &str) -> Vec<String> {
+        let mut names = self.passthrough.clone();
+        if let Some(extra) = self.command_passthrough.get(command) {
+            names.extend(extra.iter().cloned());
+        }
+        names
+    }
+}
+
+/// An operation a path zone can grant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PathPermission {
+    Read,
+    Write,
+    Create,
+    Delete,
+    /// Use a directory as the working directory of a command
+    Execute,
+}
+
+impl PathPermission {
+    pub const ALL: [PathPermission; 5] = [
+        PathPermission::Read,
+        PathPermission::Write,
+        PathPermission::Create,
+        PathPermission::Delete,
+        PathPermission::Execute,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PathPermission::Read => "read",
+            PathPermission::Write => "write",
+            PathPermission::Create => "create",
+            PathPermission::Delete => "delete",
+            PathPermission::Execute => "execute",
+        }
+    }
+}
+
+impl std::fmt::Display for PathPermission {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// An allowed root with its own permissions and deny globs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PathZone {
+    pub path: PathBuf,
+
+    /// Operations allowed inside the zone; read-only if omitted
+    #[serde(default = "default_zone_permissions")]
+    pub permissions: Vec<PathPermission>,
+
+    /// Globs denied inside this zone, in addition to `Config::deny_globs`
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LlmConfig {
     /// Provider type: "openai", "anthropic", "local", "mock"
@@ -64,6 +233,8 @@ impl Default for Config {
                 home.join("Documents"),
                 home.join("Desktop"),
             ],
+            path_zones: Vec::new(),
+            deny_globs: default_deny_globs(),
             whitelisted_commands: vec![
                 "git".to_string(),
                 "npm".to_string(),
@@ -78,8 +249,12 @@ impl Default for Config {
                 "code".to_string(),
                 "docker".to_string(),
             ],
+            command_pins: HashMap::new(),
+            policy_socket: mcp_dir.join("policy.sock"),
             audit_db_path: mcp_dir.join("audit.db"),
             snapshot_dir: mcp_dir.join("snapshots"),
+            artifact_dir: mcp_dir.join("artifacts"),
+            max_output_bytes: default_max_output_bytes(),
             max_file_size: 10 * 1024 * 1024, // 10MB
             dry_run_default: true,
             auto_approve_patterns: vec![
@@ -97,11 +272,103 @@ impl Default for Config {
                 "git push --force".to_string(),
             ],
             sandbox_enabled: true,
+            sandbox_limits: default_sandbox_limits(),
+            env_policy: EnvPolicy::default(),
+            secret_patterns: Vec::new(),
+            snapshot_ignore: default_snapshot_ignore(),
+            policy_rules_path: default_policy_rules_path(),
+            effect_rules_path: default_effect_rules_path(),
+            approval_ttl_secs: default_approval_ttl_secs(),
             llm_config: LlmConfig::default(),
         }
     }
 }
 
+fn default_policy_socket() -> PathBuf {
+    let home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
+    home.join(".mcp").join("policy.sock")
+}
+
+fn default_policy_rules_path() -> PathBuf {
+    let home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
+    home.join(".mcp").join("policy.json")
+}
+
+fn default_effect_rules_path() -> PathBuf {
+    let home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
+    home.join(".mcp").join("effects.json")
+}
+
+fn default_artifact_dir() -> PathBuf {
+    let home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
+    home.join(".mcp").join("artifacts")
+}
+
+fn default_max_output_bytes() -> u64 {
+    256 * 1024
+}
+
+fn default_approval_ttl_secs() -> u64 {
+    300
+}
+
+fn default_sandbox_limits() -> ResourceLimits {
+    ResourceLimits {
+        // Runtimes like node reserve large address ranges up front, so the
+        // address space is left unlimited unless configured
+        max_memory: 0,
+        max_cpu_time: 600,
+        max_file_size: 1024 * 1024 * 1024, // 1GB
+    }
+}
+
+fn default_snapshot_ignore() -> Vec<String> {
+    // Version control data and build outputs that can be regenerated
+    [".git", "node_modules", "target", "dist", "build", "__pycache__", ".venv"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+}
+
+fn default_env_passthrough() -> Vec<String> {
+    ["PATH", "HOME", "USER", "LANG", "LC_ALL", "TERM", "TMPDIR"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+}
+
+fn default_env_allowed() -> Vec<String> {
+    ["CI", "DEBUG", "NODE_ENV", "RUST_BACKTRACE", "RUST_LOG", "NO_COLOR", "FORCE_COLOR"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+}
+
+fn default_env_denied() -> Vec<String> {
+    // The search path that resolves the whitelisted binary, loader and
+    // interpreter hooks that run code in every process, and anything that
+    // looks like a credential
+    [
+        "PATH", "LD_*", "DYLD_*", "BASH_ENV", "ENV", "PROMPT_COMMAND", "NODE_OPTIONS", "PYTHONSTARTUP",
+        "*TOKEN*", "*SECRET*", "*PASSWORD*", "*API_KEY*", "AWS_*",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect()
+}
+
+fn default_deny_globs() -> Vec<String> {
+    vec![
+        "**/.env".to_string(),
+        "**/.ssh/**".to_string(),
+        "**/*.pem".to_string(),
+    ]
+}
+
+fn default_zone_permissions() -> Vec<PathPermission> {
+    vec![PathPermission::Read]
+}
+
 impl Default for LlmConfig {
     fn default() -> Self {
         Self {
@@ -154,24 +421,49 @@ impl Config {
         home.join(".mcp").join("config.json")
     }
 
-    /// Check if a path is within allowed paths
+    /// Confinement to the allowed paths and zones, used to resolve and open files
+    pub fn confinement(&self) -> Confinement {
+        Confinement::new(&self.allowed_paths)
+            .with_zones(&self.path_zones)
+            .with_deny_globs(&self.deny_globs)
+    }
+
+    /// Check if a path is within allowed paths.
+    ///
+    /// The path does not need to exist; symlinks and `..` components are
+    /// resolved before the check.
     pub fn is_path_allowed(&self, path: &std::path::Path) -> bool {
-        let canonical = match path.canonicalize() {
-            Ok(p) => p,
-            Err(_) => return false,
-        };
-
-        self.allowed_paths.iter().any(|allowed| {
-            if let Ok(allowed_canonical) = allowed.canonicalize() {
-                canonical.starts_with(&allowed_canonical)
-            } else {
-                false
-            }
-        })
+        self.confinement().contains(path)
     }
 
     /// Check if a command is whitelisted
     pub fn is_command_whitelisted(&self, command: &str) -> bool {
-        self.whitelisted_commands.iter().any(|c| c == command)
+        self.whitelist_entry(command).is_some()
+    }
+
+    /// The whitelist entry a command is allowed by. A command given as a
+    /// path must be listed as that path, or be the path of a pinned entry.
+    pub fn whitelist_entry(&self, command: &str) -> Option<&str> {
+        if let Some(entry) = self.whitelisted_commands.iter().find(|c| *c == command) {
+            return Some(entry);
+        }
+        if !binaries::is_path(command) {
+            return None;
+        }
+        self.whitelisted_commands.iter()
+            .find(|c| {
+                self.command_pins.get(*c)
+                    .and_then(|pin| pin.path.as_deref())
+                    .is_some_and(|path| path == std::path::Path::new(command))
+            })
+            .map(String::as_str)
+    }
+
+    /// Resolve a whitelisted command to the binary it runs
+    pub fn resolve_command(&self, command: &str) -> McpResult<ResolvedBinary> {
+        let entry = self.whitelist_entry(command).ok_or_else(|| {
+            McpError::CommandNotWhitelisted(command.to_string())
+        })?;
+        binaries::resolve(command, self.command_pins.get(entry))
     }
 }
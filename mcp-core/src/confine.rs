@@ -0,0 +1,621 @@
+//! Path confinement for file operations
+//!
+//! Paths are resolved against the allowed directories before any access:
+//! the path is normalized lexically, the nearest existing ancestor is
+//! canonicalized (so files that do not exist yet can still be checked), and
+//! the result must lie inside one of the allowed roots. `..` components that
+//! leave a root and symlinks that point outside of it are rejected.
+//!
+//! Each root carries the permissions granted inside it, and deny globs such
+//! as `**/.env` are matched against the resolved path, so a symlink cannot
+//! be used to reach a denied file under a different name.
+//!
+//! On Linux the actual open is performed relative to a directory handle
+//! pinned at the allowed root, walking one component at a time with
+//! `O_NOFOLLOW`, so a symlink swapped in between the check and the open
+//! makes the operation fail instead of escaping the root.
+
+use std::ffi::OsString;
+use std::fs::{File, Metadata};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::config::{PathPermission, PathZone};
+use crate::error::{McpError, McpResult};
+
+/// How a confined file is opened
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpenMode {
+    /// Read an existing file
+    Read,
+    /// Create or truncate the file for writing
+    Write,
+    /// Create the file if needed and append to it
+    Append,
+    /// Create a file that must not exist yet
+    CreateNew,
+}
+
+/// An allowed root together with what may be done inside it
+#[derive(Debug, Clone)]
+struct Root {
+    path: PathBuf,
+    permissions: Vec<PathPermission>,
+    deny: Vec<glob::Pattern>,
+}
+
+/// The set of directories file operations are confined to
+#[derive(Debug, Clone, Default)]
+pub struct Confinement {
+    roots: Vec<Root>,
+    deny: Vec<glob::Pattern>,
+}
+
+impl Confinement {
+    /// Build a confinement granting every permission in the given paths.
+    ///
+    /// Allowed paths that do not exist are ignored.
+    pub fn new(allowed_paths: &[PathBuf]) -> Self {
+        let zones: Vec<PathZone> = allowed_paths.iter()
+            .map(|path| PathZone {
+                path: path.clone(),
+                permissions: PathPermission::ALL.to_vec(),
+                deny: Vec::new(),
+            })
+            .collect();
+        Self::default().with_zones(&zones)
+    }
+
+    /// Add roots with explicit permissions and deny globs
+    pub fn with_zones(mut self, zones: &[PathZone]) -> Self {
+        for zone in zones {
+            if let Ok(path) = zone.path.canonicalize() {
+                self.roots.push(Root {
+                    path,
+                    permissions: zone.permissions.clone(),
+                    deny: compile_globs(&zone.deny),
+                });
+            }
+        }
+        // The most specific root decides, so a read-only zone can sit
+        // inside a writable one
+        self.roots.sort_by_key(|root| std::cmp::Reverse(root.path.components().count()));
+        self
+    }
+
+    /// Add globs that are denied in every root
+    pub fn with_deny_globs(mut self, globs: &[String]) -> Self {
+        self.deny.extend(compile_globs(globs));
+        self
+    }
+
+    /// Resolve a path and check that it stays within an allowed root
+    pub fn resolve(&self, path: &Path) -> McpResult<ConfinedPath> {
+        Ok(self.locate(path)?.0)
+    }
+
+    /// Resolve a path and check that its zone grants `permission`
+    pub fn check(&self, path: &Path, permission: PathPermission) -> McpResult<ConfinedPath> {
+        let (confined, root) = self.locate(path)?;
+
+        if !root.permissions.contains(&permission) {
+            return Err(McpError::PathNotAllowed(format!(
+                "Path '{}' does not allow {} access",
+                path.display(),
+                permission
+            )));
+        }
+
+        let resolved = confined.resolved.to_string_lossy();
+        // Match with a trailing separator too, so `**/.ssh/**` covers the directory itself
+        let as_dir = format!("{}/", resolved.trim_end_matches('/'));
+        if let Some(glob) = self.deny.iter()
+            .chain(root.deny.iter())
+            .find(|glob| glob.matches(&resolved) || glob.matches(&as_dir))
+        {
+            return Err(McpError::PathNotAllowed(format!(
+                "Path '{}' matches denied pattern '{}'",
+                path.display(),
+                glob.as_str()
+            )));
+        }
+
+        Ok(confined)
+    }
+
+    /// Whether a path resolves to a location within an allowed root
+    pub fn contains(&self, path: &Path) -> bool {
+        self.resolve(path).is_ok()
+    }
+
+    fn locate(&self, path: &Path) -> McpResult<(ConfinedPath, &Root)> {
+        if !path.is_absolute() {
+            return Err(McpError::PathNotAllowed(format!(
+                "Path '{}' must be absolute",
+                path.display()
+            )));
+        }
+
+        let normalized = normalize(path);
+
+        // Find the nearest ancestor that exists; the rest is created later
+        let mut existing = normalized.as_path();
+        let mut missing: Vec<OsString> = Vec::new();
+        loop {
+            match std::fs::symlink_metadata(existing) {
+                Ok(_) => break,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    match (existing.file_name(), existing.parent()) {
+                        (Some(name), Some(parent)) => {
+                            missing.push(name.to_os_string());
+                            existing = parent;
+                        }
+                        _ => return Err(self.not_allowed(path)),
+                    }
+                }
+                Err(e) => return Err(McpError::IoError(e)),
+            }
+        }
+
+        // Canonicalizing resolves every symlink; a dangling one fails here
+        let mut resolved = existing.canonicalize().map_err(|_| {
+            McpError::PathNotAllowed(format!(
+                "Path '{}' contains a dangling symbolic link",
+                path.display()
+            ))
+        })?;
+        resolved.extend(missing.iter().rev());
+
+        let root = self.roots.iter()
+            .find(|root| resolved.starts_with(&root.path))
+            .ok_or_else(|| self.not_allowed(path))?;
+
+        let confined = ConfinedPath {
+            relative: resolved.strip_prefix(&root.path).unwrap_or(Path::new("")).to_path_buf(),
+            root: root.path.clone(),
+            resolved,
+        };
+        Ok((confined, root))
+    }
+
+    fn not_allowed(&self, path: &Path) -> McpError {
+        McpError::PathNotAllowed(format!(
+            "Path '{}' is not within allowed directories",
+            path.display()
+        ))
+    }
+}
+
+/// Compile deny globs, treating an invalid pattern as a literal path
+fn compile_globs(globs: &[String]) -> Vec<glob::Pattern> {
+    globs.iter()
+        .filter_map(|g| {
+            glob::Pattern::new(g)
+                .or_else(|_| glob::Pattern::new(&glob::Pattern::escape(g)))
+                .ok()
+        })
+        .collect()
+}
+
+/// Lexically normalize a path, removing `.` and resolving `..`
+pub(crate) fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// A path that has been checked against the allowed roots
+#[derive(Debug, Clone)]
+pub struct ConfinedPath {
+    root: PathBuf,
+    relative: PathBuf,
+    resolved: PathBuf,
+}
+
+impl ConfinedPath {
+    /// The fully resolved absolute path
+    pub fn path(&self) -> &Path {
+        &self.resolved
+    }
+
+    /// The allowed root the path lies in
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The path relative to its root
+    pub fn relative(&self) -> &Path {
+        &self.relative
+    }
+
+    /// Open the file without following symlinks below the root
+    pub fn open(&self, mode: OpenMode) -> io::Result<File> {
+        imp::open(self, mode)
+    }
+
+    /// Create any missing parent directories below the root
+    pub fn create_parents(&self) -> io::Result<()> {
+        imp::create_parents(self)
+    }
+
+    /// Metadata of the path, without following symlinks below the root
+    pub fn metadata(&self) -> io::Result<Metadata> {
+        imp::metadata(self)
+    }
+
+    /// The names and metadata of the entries of a directory, without
+    /// following symlinks below the root; a symlink entry is reported as
+    /// itself
+    pub fn read_dir(&self) -> io::Result<Vec<(OsString, Metadata)>> {
+        imp::read_dir(self)
+    }
+
+    fn split_leaf(&self) -> io::Result<(&Path, &std::ffi::OsStr)> {
+        match (self.relative.parent(), self.relative.file_name()) {
+            (Some(parent), Some(name)) => Ok((parent, name)),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("'{}' is an allowed root, not a file", self.resolved.display()),
+            )),
+        }
+    }
+}
+
+/// Rename a confined path to another, both relative to pinned directories
+pub fn rename(from: &ConfinedPath, to: &ConfinedPath) -> io::Result<()> {
+    imp::rename(from, to)
+}
+
+/// Replace a file atomically: `write` fills a temporary file next to it,
+/// which is synced and renamed over it, so a crash leaves either the old or
+/// the new file. See [`Replacement`].
+pub fn replace<F>(path: &ConfinedPath, mode: Option<u32>, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut File) -> io::Result<()>,
+{
+    let mut replacement = Replacement::begin(path, mode)?;
+    write(replacement.file())?;
+    replacement.commit()
+}
+
+/// A file being replaced. The new contents are written to a temporary file
+/// next to it, which `commit` syncs and renames over the file; dropping the
+/// replacement without committing removes the temporary file. An existing
+/// file keeps its permissions and ownership; a new one gets `mode` if given
+/// (on Unix), or the default permissions.
+///
+/// If the temporary file cannot be made (the directory is not writable, or
+/// the file belongs to another user), `begin` fails rather than rewriting the
+/// file in place, which a crash could leave half-written.
+pub struct Replacement {
+    file: File,
+    target: ConfinedPath,
+    /// The temporary file, until it is renamed into place
+    temp: Option<ConfinedPath>,
+}
+
+impl Replacement {
+    pub fn begin(path: &ConfinedPath, mode: Option<u32>) -> io::Result<Self> {
+        let (_, name) = path.split_leaf()?;
+        let mut temp_name = std::ffi::OsString::from(".");
+        temp_name.push(name);
+        temp_name.push(format!(".{}.tmp", uuid::Uuid::new_v4().simple()));
+        let temp = ConfinedPath {
+            root: path.root.clone(),
+            relative: path.relative.with_file_name(&temp_name),
+            resolved: path.resolved.with_file_name(&temp_name),
+        };
+
+        let original = match path.open(OpenMode::Read).and_then(|f| f.metadata()) {
+            Ok(metadata) => Some(metadata),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+
+        let prepared = temp.open(OpenMode::CreateNew).and_then(|file| {
+            match &original {
+                Some(metadata) => {
+                    keep_owner(&file, metadata)?;
+                    file.set_permissions(metadata.permissions())?;
+                }
+                None => set_mode(&file, mode)?,
+            }
+            Ok(file)
+        });
+        match prepared {
+            Ok(file) => Ok(Self { file, target: path.clone(), temp: Some(temp) }),
+            Err(e) => {
+                let _ = std::fs::remove_file(temp.path());
+                Err(io::Error::new(
+                    e.kind(),
+                    format!("cannot replace {} atomically: {}", path.path().display(), e),
+                ))
+            }
+        }
+    }
+
+    /// Where the new contents are written
+    pub fn file(&mut self) -> &mut File {
+        &mut self.file
+    }
+
+    /// Sync the new contents and put them in place
+    pub fn commit(mut self) -> io::Result<()> {
+        self.file.sync_all()?;
+        if let Some(temp) = &self.temp {
+            rename(temp, &self.target)?;
+            self.temp = None;
+            imp::sync_parent(&self.target)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Replacement {
+    fn drop(&mut self) {
+        if let Some(temp) = &self.temp {
+            let _ = std::fs::remove_file(temp.path());
+        }
+    }
+}
+
+/// Give a new file the owner of the one it replaces
+#[cfg(unix)]
+fn keep_owner(file: &File, original: &std::fs::Metadata) -> io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    let current = file.metadata()?;
+    if (current.uid(), current.gid()) == (original.uid(), original.gid()) {
+        return Ok(());
+    }
+    std::os::unix::fs::fchown(file, Some(original.uid()), Some(original.gid()))
+}
+
+#[cfg(not(unix))]
+fn keep_owner(_file: &File, _original: &std::fs::Metadata) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn set_mode(file: &File, mode: Option<u32>) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    match mode {
+        Some(mode) => file.set_permissions(std::fs::Permissions::from_mode(mode)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+fn set_mode(_file: &File, _mode: Option<u32>) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::ffi::{CStr, CString, OsStr, OsString};
+    use std::fs::{File, Metadata};
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    use super::{ConfinedPath, OpenMode};
+
+    fn c_string(s: &OsStr) -> io::Result<CString> {
+        CString::new(s.as_bytes()).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    }
+
+    fn check(ret: libc::c_int) -> io::Result<libc::c_int> {
+        if ret < 0 {
+            let err = io::Error::last_os_error();
+            return Err(match err.raw_os_error() {
+                Some(libc::ELOOP) | Some(libc::ENOTDIR) => io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "Path was replaced by a symbolic link during the operation",
+                ),
+                _ => err,
+            });
+        }
+        Ok(ret)
+    }
+
+    fn open_root(root: &Path) -> io::Result<OwnedFd> {
+        let root = c_string(root.as_os_str())?;
+        let fd = check(unsafe {
+            libc::open(root.as_ptr(), libc::O_PATH | libc::O_DIRECTORY | libc::O_CLOEXEC)
+        })?;
+        Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+
+    fn open_dir_at(dir: &OwnedFd, name: &OsStr, create: bool) -> io::Result<OwnedFd> {
+        let name = c_string(name)?;
+        let flags = libc::O_PATH | libc::O_DIRECTORY | libc::O_NOFOLLOW | libc::O_CLOEXEC;
+
+        let mut fd = unsafe { libc::openat(dir.as_raw_fd(), name.as_ptr(), flags) };
+        if fd < 0 && create && io::Error::last_os_error().raw_os_error() == Some(libc::ENOENT) {
+            let ret = unsafe { libc::mkdirat(dir.as_raw_fd(), name.as_ptr(), 0o777) };
+            if ret < 0 && io::Error::last_os_error().raw_os_error() != Some(libc::EEXIST) {
+                check(ret)?;
+            }
+            fd = unsafe { libc::openat(dir.as_raw_fd(), name.as_ptr(), flags) };
+        }
+        let fd = check(fd)?;
+        Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+
+    /// Walk from the root to `relative`, refusing to follow any symlink
+    fn open_dir(root: &Path, relative: &Path, create: bool) -> io::Result<OwnedFd> {
+        let mut dir = open_root(root)?;
+        for component in relative.iter() {
+            dir = open_dir_at(&dir, component, create)?;
+        }
+        Ok(dir)
+    }
+
+    pub fn open(path: &ConfinedPath, mode: OpenMode) -> io::Result<File> {
+        let (parent, name) = path.split_leaf()?;
+        let dir = open_dir(&path.root, parent, false)?;
+        let name = c_string(name)?;
+
+        let flags = libc::O_NOFOLLOW | libc::O_CLOEXEC | match mode {
+            OpenMode::Read => libc::O_RDONLY,
+            OpenMode::Write => libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC,
+            OpenMode::Append => libc::O_WRONLY | libc::O_CREAT | libc::O_APPEND,
+            OpenMode::CreateNew => libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL,
+        };
+        let fd = check(unsafe {
+            libc::openat(dir.as_raw_fd(), name.as_ptr(), flags, 0o666 as libc::c_uint)
+        })?;
+        Ok(unsafe { File::from_raw_fd(fd) })
+    }
+
+    pub fn create_parents(path: &ConfinedPath) -> io::Result<()> {
+        let (parent, _) = path.split_leaf()?;
+        open_dir(&path.root, parent, true).map(|_| ())
+    }
+
+    /// Open an entry of `dir` only as a handle, without following a symlink
+    fn open_entry(dir: &OwnedFd, name: &OsStr) -> io::Result<File> {
+        let name = c_string(name)?;
+        let fd = check(unsafe {
+            libc::openat(dir.as_raw_fd(), name.as_ptr(), libc::O_PATH | libc::O_NOFOLLOW | libc::O_CLOEXEC)
+        })?;
+        Ok(unsafe { File::from_raw_fd(fd) })
+    }
+
+    pub fn metadata(path: &ConfinedPath) -> io::Result<Metadata> {
+        match path.split_leaf() {
+            Ok((parent, name)) => open_entry(&open_dir(&path.root, parent, false)?, name)?.metadata(),
+            // The root itself
+            Err(_) => File::from(open_root(&path.root)?).metadata(),
+        }
+    }
+
+    pub fn read_dir(path: &ConfinedPath) -> io::Result<Vec<(OsString, Metadata)>> {
+        let dir = open_dir(&path.root, &path.relative, false)?;
+        let dot = c_string(OsStr::new("."))?;
+        let fd = check(unsafe {
+            libc::openat(dir.as_raw_fd(), dot.as_ptr(), libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC)
+        })?;
+        let listing = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let stream = unsafe { libc::fdopendir(listing.as_raw_fd()) };
+        if stream.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        // The stream owns the descriptor from here on
+        let _ = listing.into_raw_fd();
+
+        let mut names = Vec::new();
+        loop {
+            let entry = unsafe { libc::readdir(stream) };
+            if entry.is_null() {
+                break;
+            }
+            let name = unsafe { CStr::from_ptr((*entry).d_name.as_ptr()) };
+            let name = OsStr::from_bytes(name.to_bytes());
+            if name != "." && name != ".." {
+                names.push(name.to_os_string());
+            }
+        }
+        unsafe { libc::closedir(stream) };
+
+        let mut entries = Vec::with_capacity(names.len());
+        for name in names {
+            match open_entry(&dir, &name).and_then(|entry| entry.metadata()) {
+                Ok(metadata) => entries.push((name, metadata)),
+                // Removed since it was listed
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Flush the directory entry of a file to disk
+    pub fn sync_parent(path: &ConfinedPath) -> io::Result<()> {
+        let (parent, _) = path.split_leaf()?;
+        let dir = open_dir(&path.root, parent, false)?;
+        let dot = c_string(OsStr::new("."))?;
+        let fd = check(unsafe {
+            libc::openat(dir.as_raw_fd(), dot.as_ptr(), libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC)
+        })?;
+        unsafe { File::from_raw_fd(fd) }.sync_all()
+    }
+
+    pub fn rename(from: &ConfinedPath, to: &ConfinedPath) -> io::Result<()> {
+        let (from_parent, from_name) = from.split_leaf()?;
+        let (to_parent, to_name) = to.split_leaf()?;
+        let from_dir = open_dir(&from.root, from_parent, false)?;
+        let to_dir = open_dir(&to.root, to_parent, false)?;
+        let from_name = c_string(from_name)?;
+        let to_name = c_string(to_name)?;
+
+        check(unsafe {
+            libc::renameat(from_dir.as_raw_fd(), from_name.as_ptr(), to_dir.as_raw_fd(), to_name.as_ptr())
+        })?;
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use std::ffi::OsString;
+    use std::fs::{File, Metadata, OpenOptions};
+    use std::io;
+
+    use super::{ConfinedPath, OpenMode};
+
+    pub fn open(path: &ConfinedPath, mode: OpenMode) -> io::Result<File> {
+        path.split_leaf()?;
+        let mut options = OpenOptions::new();
+        match mode {
+            OpenMode::Read => options.read(true),
+            OpenMode::Write => options.write(true).create(true).truncate(true),
+            OpenMode::Append => options.append(true).create(true),
+            OpenMode::CreateNew => options.write(true).create_new(true),
+        };
+        options.open(&path.resolved)
+    }
+
+    pub fn create_parents(path: &ConfinedPath) -> io::Result<()> {
+        match path.resolved.parent() {
+            Some(parent) => std::fs::create_dir_all(parent),
+            None => Ok(()),
+        }
+    }
+
+    pub fn rename(from: &ConfinedPath, to: &ConfinedPath) -> io::Result<()> {
+        std::fs::rename(&from.resolved, &to.resolved)
+    }
+
+    pub fn metadata(path: &ConfinedPath) -> io::Result<Metadata> {
+        std::fs::symlink_metadata(&path.resolved)
+    }
+
+    pub fn read_dir(path: &ConfinedPath) -> io::Result<Vec<(OsString, Metadata)>> {
+        std::fs::read_dir(&path.resolved)?
+            .map(|entry| {
+                let entry = entry?;
+                Ok((entry.file_name(), entry.metadata()?))
+            })
+            .collect()
+    }
+
+    /// Flush the directory entry of a file to disk, where directories can be opened
+    pub fn sync_parent(path: &ConfinedPath) -> io::Result<()> {
+        match path.resolved.parent() {
+            Some(parent) if cfg!(unix) => File::open(parent)?.sync_all(),
+            _ => Ok(()),
+        }
+    }
+}
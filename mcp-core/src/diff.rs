@@ -0,0 +1,58 @@
+//! Unified diffs of file contents for previews and change reports
+
+use similar::TextDiff;
+
+/// Lines of unchanged context around each hunk
+const CONTEXT_LINES: usize = 3;
+
+/// Files larger than this are reported as changed without a line diff
+pub const MAX_DIFF_BYTES: usize = 1024 * 1024;
+
+/// Unified diff between two versions of `path`, in the format `git diff`
+/// uses. `None` stands for a file that does not exist on that side.
+pub fn unified(path: &str, old: Option<&[u8]>, new: Option<&[u8]>) -> String {
+    let (old_exists, new_exists) = (old.is_some(), new.is_some());
+    let old = old.unwrap_or_default();
+    let new = new.unwrap_or_default();
+
+    if old == new {
+        return String::new();
+    }
+    let (old_text, new_text) = match (text(old), text(new)) {
+        (Some(old), Some(new)) => (old, new),
+        _ => return binary(path, old_exists, new_exists),
+    };
+
+    let (old_name, new_name) = names(path, old_exists, new_exists);
+    TextDiff::from_lines(old_text, new_text)
+        .unified_diff()
+        .context_radius(CONTEXT_LINES)
+        .header(&old_name, &new_name)
+        .to_string()
+}
+
+/// What `git diff` prints for a file it does not diff line by line
+pub fn binary(path: &str, old_exists: bool, new_exists: bool) -> String {
+    let (old_name, new_name) = names(path, old_exists, new_exists);
+    format!("Binary files {} and {} differ\n", old_name, new_name)
+}
+
+/// Whether contents can be diffed line by line
+pub fn is_text(content: &[u8]) -> bool {
+    text(content).is_some()
+}
+
+fn names(path: &str, old_exists: bool, new_exists: bool) -> (String, String) {
+    let name = |prefix: &str, exists: bool| {
+        if exists { format!("{}/{}", prefix, path) } else { "/dev/null".to_string() }
+    };
+    (name("a", old_exists), name("b", new_exists))
+}
+
+/// Contents as text, if they are small enough to diff and not binary
+fn text(content: &[u8]) -> Option<&str> {
+    if content.len() > MAX_DIFF_BYTES || content.contains(&0) {
+        return None;
+    }
+    std::str::from_utf8(content).ok()
+}
@@ -0,0 +1,250 @@
+//! Applying edits to text files: unified diffs and search/replace edits
+//!
+//! Edits are applied to the whole text in memory, so either all of them
+//! apply or nothing changes. A diff hunk must match the text exactly. It is
+//! tried first at the line its header names, then anywhere after the
+//! previous hunk, so hunks whose line numbers drifted still apply; a hunk
+//! that matches in more than one of those places is a conflict.
+
+use regex::Regex;
+
+use crate::error::{McpError, McpResult};
+
+/// Replace `search` with `replace`, which must occur exactly once unless
+/// `replace_all` is set
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Replacement {
+    pub search: String,
+    pub replace: String,
+    pub replace_all: bool,
+}
+
+/// Apply search/replace edits in order, each to the result of the last
+pub fn apply_replacements(text: &str, edits: &[Replacement]) -> McpResult<String> {
+    let mut text = text.to_string();
+    for (i, edit) in edits.iter().enumerate() {
+        if edit.search.is_empty() {
+            return Err(McpError::InvalidArgument(format!("Edit {}: search text is empty", i + 1)));
+        }
+        match text.matches(edit.search.as_str()).count() {
+            0 => return Err(McpError::Conflict(format!("Edit {}: search text not found", i + 1))),
+            1 => {}
+            _ if edit.replace_all => {}
+            count => {
+                return Err(McpError::Conflict(format!(
+                    "Edit {}: search text occurs {} times; include surrounding lines to make it unique, or set replace_all",
+                    i + 1,
+                    count
+                )))
+            }
+        }
+        text = if edit.replace_all {
+            text.replace(&edit.search, &edit.replace)
+        } else {
+            text.replacen(&edit.search, &edit.replace, 1)
+        };
+    }
+    Ok(text)
+}
+
+/// Apply a unified diff of a single file
+pub fn apply_unified(text: &str, patch: &str) -> McpResult<String> {
+    let hunks = parse(patch)?;
+    let lines: Vec<&str> = text.split_inclusive('\n').collect();
+
+    let mut result = String::with_capacity(text.len());
+    let mut next = 0;
+    for (i, hunk) in hunks.iter().enumerate() {
+        let old = hunk.old_lines();
+        let at = locate(&lines, &old, next, hunk.expected_index()).map_err(|reason| {
+            McpError::Conflict(format!("Hunk {} ({}) does not apply: {}", i + 1, hunk.header, reason))
+        })?;
+
+        result.extend(lines[next..at].iter().copied());
+        result.extend(hunk.new_lines());
+        next = at + old.len();
+    }
+    result.extend(lines[next..].iter().copied());
+    Ok(result)
+}
+
+#[derive(Debug)]
+enum HunkLine {
+    Context(String),
+    Removed(String),
+    Added(String),
+}
+
+#[derive(Debug)]
+struct Hunk {
+    header: String,
+    old_start: usize,
+    old_count: usize,
+    new_count: usize,
+    lines: Vec<HunkLine>,
+}
+
+impl Hunk {
+    /// Index of the first line the hunk replaces, according to its header.
+    /// A hunk that removes nothing names the line it inserts after.
+    fn expected_index(&self) -> usize {
+        if self.old_count == 0 { self.old_start } else { self.old_start.saturating_sub(1) }
+    }
+
+    fn old_lines(&self) -> Vec<&str> {
+        self.lines.iter()
+            .filter_map(|l| match l {
+                HunkLine::Context(s) | HunkLine::Removed(s) => Some(s.as_str()),
+                HunkLine::Added(_) => None,
+            })
+            .collect()
+    }
+
+    fn new_lines(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().filter_map(|l| match l {
+            HunkLine::Context(s) | HunkLine::Added(s) => Some(s.as_str()),
+            HunkLine::Removed(_) => None,
+        })
+    }
+
+    fn counts(&self) -> (usize, usize) {
+        self.lines.iter().fold((0, 0), |(old, new), l| match l {
+            HunkLine::Context(_) => (old + 1, new + 1),
+            HunkLine::Removed(_) => (old + 1, new),
+            HunkLine::Added(_) => (old, new + 1),
+        })
+    }
+
+    fn complete(&self) -> bool {
+        self.counts() == (self.old_count, self.new_count)
+    }
+}
+
+fn parse(patch: &str) -> McpResult<Vec<Hunk>> {
+    let header = Regex::new(r"^@@ -(\d+)(?:,(\d+))? \+(\d+)(?:,(\d+))? @@").expect("hunk header pattern is valid");
+    let invalid = |number: usize, reason: String| {
+        McpError::InvalidArgument(format!("Invalid patch at line {}: {}", number, reason))
+    };
+
+    let mut hunks: Vec<Hunk> = Vec::new();
+    let mut files = 0;
+    for (i, raw) in patch.split_inclusive('\n').enumerate() {
+        let number = i + 1;
+        let line = raw.strip_suffix('\n').unwrap_or(raw);
+
+        if let Some(caps) = header.captures(line) {
+            if let Some(last) = hunks.last().filter(|h| !h.complete()) {
+                return Err(invalid(number, format!("hunk '{}' is shorter than its header says", last.header)));
+            }
+            let number_at = |i: usize| caps.get(i).map_or(Ok(1), |m| m.as_str().parse::<usize>());
+            let (Ok(old_start), Ok(old_count), Ok(new_count)) = (number_at(1), number_at(2), number_at(4)) else {
+                return Err(invalid(number, "line numbers out of range".to_string()));
+            };
+            hunks.push(Hunk {
+                header: caps[0].to_string(),
+                old_start,
+                old_count,
+                new_count,
+                lines: Vec::new(),
+            });
+            continue;
+        }
+
+        // Marks the line before as having no newline
+        if line.starts_with('\\') {
+            match hunks.last_mut().and_then(|h| h.lines.last_mut()) {
+                Some(HunkLine::Context(s) | HunkLine::Removed(s) | HunkLine::Added(s)) => {
+                    s.pop();
+                }
+                None => return Err(invalid(number, "'\\' marker outside a hunk".to_string())),
+            }
+            continue;
+        }
+
+        match hunks.last_mut().filter(|h| !h.complete()) {
+            Some(hunk) => {
+                let content = |rest: &str| format!("{}\n", rest);
+                let parsed = match line.chars().next() {
+                    Some(' ') => HunkLine::Context(content(&line[1..])),
+                    // Some tools strip the space from empty context lines
+                    None => HunkLine::Context(content("")),
+                    Some('-') => HunkLine::Removed(content(&line[1..])),
+                    Some('+') => HunkLine::Added(content(&line[1..])),
+                    Some(_) => return Err(invalid(number, format!("unexpected line {:?} inside hunk '{}'", line, hunk.header))),
+                };
+                hunk.lines.push(parsed);
+            }
+            None => {
+                // File headers and anything else between hunks
+                let header_line = line.starts_with("--- ") || line.starts_with("+++ ");
+                if let (Some(hunk), false, Some(' ' | '+' | '-')) = (hunks.last(), header_line, line.chars().next()) {
+                    return Err(invalid(number, format!("hunk '{}' is longer than its header says", hunk.header)));
+                }
+                if line.starts_with("--- ") {
+                    files += 1;
+                    if files > 1 {
+                        return Err(invalid(number, "the patch changes more than one file".to_string()));
+                    }
+                }
+            }
+        }
+    }
+
+    match hunks.last() {
+        None => Err(McpError::InvalidArgument("Patch has no hunks".to_string())),
+        Some(last) if !last.complete() => Err(McpError::InvalidArgument(format!(
+            "Invalid patch: hunk '{}' is shorter than its header says",
+            last.header
+        ))),
+        Some(_) => Ok(hunks),
+    }
+}
+
+/// Where the hunk's old lines are in `lines`, looking from `from` on
+fn locate(lines: &[&str], old: &[&str], from: usize, expected: usize) -> Result<usize, String> {
+    let matches_at = |at: usize| at + old.len() <= lines.len() && lines[at..at + old.len()] == *old;
+
+    if expected >= from && matches_at(expected) {
+        return Ok(expected);
+    }
+    // Pure insertions have nothing to search for
+    if old.is_empty() {
+        return Err(format!("the file has {} lines, fewer than the hunk inserts after", lines.len()));
+    }
+
+    let found: Vec<usize> = (from..=lines.len().saturating_sub(old.len())).filter(|&at| matches_at(at)).collect();
+    match found[..] {
+        [at] => Ok(at),
+        [] => Err(mismatch(lines, old, expected)),
+        _ => Err(format!(
+            "its lines match at lines {}; add context to pick one",
+            found.iter().map(|at| (at + 1).to_string()).collect::<Vec<_>>().join(", ")
+        )),
+    }
+}
+
+/// Describe how the text differs from the hunk at the line it names
+fn mismatch(lines: &[&str], old: &[&str], at: usize) -> String {
+    for (offset, wanted) in old.iter().enumerate() {
+        match lines.get(at + offset) {
+            None => {
+                return format!(
+                    "expected {:?} at line {}, but the file has only {} lines",
+                    wanted.trim_end_matches('\n'),
+                    at + offset + 1,
+                    lines.len()
+                )
+            }
+            Some(found) if found != wanted => {
+                return format!(
+                    "line {} is {:?}, expected {:?}",
+                    at + offset + 1,
+                    found.trim_end_matches('\n'),
+                    wanted.trim_end_matches('\n')
+                )
+            }
+            Some(_) => {}
+        }
+    }
+    "its lines are not found after the previous hunk".to_string()
+}
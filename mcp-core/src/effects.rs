@@ -0,0 +1,367 @@
+//! Dry-run effect prediction
+//!
+//! Predictors map command patterns to the effects a command is expected to
+//! have. They are loaded from a JSON file (by default `~/.mcp/effects.json`)
+//! and evaluated before the built-in ones; the first matching predictor
+//! decides:
+//!
+//! ```json
+//! {
+//!   "predictors": [
+//!     { "id": "make-clean", "command": ["make clean", "make distclean"], "risk": "medium",
+//!       "effects": [{ "kind": "delete", "path": "build", "description": "Will remove {path}" }] },
+//!     { "id": "prettier-write", "command": "prettier --write", "estimate": "seconds",
+//!       "effects": [{ "kind": "modify", "path": "{args}", "description": "Will reformat {path}" }] }
+//!   ]
+//! }
+//! ```
+//!
+//! `command` uses the same patterns as `auto_approve_patterns`, so extra
+//! flags and arguments still match. In effect paths and descriptions,
+//! `{args}` repeats the effect for every positional argument and `{arg1}`
+//! is the first one; relative paths are resolved against the working
+//! directory, and `{path}` in a description is the resolved path.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::audit::AuditLogger;
+use crate::command_match::{CommandPattern, ParsedCommand};
+use crate::error::{McpError, McpResult};
+use crate::rules::OneOrMany;
+
+/// Previous runs considered when estimating how long a command takes
+const HISTORY_LIMIT: usize = 20;
+
+/// What a command is expected to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EffectKind {
+    Create,
+    Modify,
+    Delete,
+    Network,
+    Other,
+}
+
+impl EffectKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EffectKind::Create => "create",
+            EffectKind::Modify => "modify",
+            EffectKind::Delete => "delete",
+            EffectKind::Network => "network",
+            EffectKind::Other => "other",
+        }
+    }
+}
+
+/// How much damage a command could do if it were a mistake
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RiskLevel {
+    Low,
+    Medium,
+    High,
+}
+
+impl RiskLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RiskLevel::Low => "low",
+            RiskLevel::Medium => "medium",
+            RiskLevel::High => "high",
+        }
+    }
+}
+
+/// An effect as written in the predictor file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EffectTemplate {
+    pub kind: EffectKind,
+    #[serde(default)]
+    pub path: Option<String>,
+    pub description: String,
+}
+
+/// A predictor as written in the predictor file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Predictor {
+    pub id: String,
+    pub command: OneOrMany,
+    #[serde(default)]
+    pub effects: Vec<EffectTemplate>,
+    #[serde(default = "default_risk")]
+    pub risk: RiskLevel,
+    /// Shown as the estimated time when there is no history for the command
+    #[serde(default)]
+    pub estimate: Option<String>,
+}
+
+fn default_risk() -> RiskLevel {
+    RiskLevel::Medium
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct PredictorFile {
+    predictors: Vec<Predictor>,
+}
+
+/// A predicted effect of a command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Effect {
+    pub kind: EffectKind,
+    pub path: Option<String>,
+    pub description: String,
+}
+
+impl Effect {
+    /// Whether the effect creates, modifies or deletes files
+    pub fn changes_files(&self) -> bool {
+        matches!(self.kind, EffectKind::Create | EffectKind::Modify | EffectKind::Delete)
+    }
+}
+
+/// What a command is expected to do
+#[derive(Debug, Clone)]
+pub struct Prediction {
+    /// The predictor that matched, if any
+    pub predictor_id: Option<String>,
+    pub effects: Vec<Effect>,
+    pub risk: RiskLevel,
+    pub estimate: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct CompiledPredictor {
+    patterns: Vec<CommandPattern>,
+    predictor: Predictor,
+}
+
+impl CompiledPredictor {
+    fn compile(predictor: Predictor) -> McpResult<Self> {
+        let patterns = predictor.command.clone().into_vec().iter()
+            .map(|p| CommandPattern::parse(p).ok_or_else(|| {
+                McpError::ConfigError(format!("Predictor '{}': empty command pattern", predictor.id))
+            }))
+            .collect::<McpResult<Vec<_>>>()?;
+        Ok(Self { patterns, predictor })
+    }
+
+    fn effects(&self, command: &ParsedCommand, cwd: Option<&Path>) -> Vec<Effect> {
+        let mut effects = Vec::new();
+        for template in &self.predictor.effects {
+            let uses_args = template.path.as_deref().unwrap_or_default().contains("{args}")
+                || template.description.contains("{args}");
+            let args: Vec<&str> = if uses_args {
+                command.positionals.iter().map(String::as_str).collect()
+            } else {
+                vec![""]
+            };
+
+            for arg in args {
+                let fill = |text: &str| -> Option<String> {
+                    let first = command.positionals.first();
+                    if text.contains("{arg1}") && first.is_none() {
+                        return None;
+                    }
+                    Some(text
+                        .replace("{args}", arg)
+                        .replace("{arg1}", first.map(String::as_str).unwrap_or_default()))
+                };
+
+                let path = match template.path.as_deref().map(fill) {
+                    Some(Some(path)) => Some(resolve(&path, cwd)),
+                    Some(None) => continue,
+                    None => None,
+                };
+                let Some(description) = fill(&template.description) else { continue };
+                let description = description.replace("{path}", path.as_deref().unwrap_or_default());
+
+                effects.push(Effect { kind: template.kind, path, description });
+            }
+        }
+        effects
+    }
+}
+
+fn resolve(path: &str, cwd: Option<&Path>) -> String {
+    match cwd {
+        Some(dir) if Path::new(path).is_relative() => dir.join(path).to_string_lossy().to_string(),
+        _ => path.to_string(),
+    }
+}
+
+/// Default predictors applied after any user-defined ones
+const BUILTIN_PREDICTORS: &str = r#"{
+  "predictors": [
+    { "id": "builtin:node-install", "risk": "medium", "estimate": "30s-5m",
+      "command": ["npm install", "npm i", "npm ci", "npm add", "pnpm install", "pnpm i", "pnpm add",
+                  "yarn install", "yarn add"],
+      "effects": [
+        { "kind": "modify", "path": "node_modules", "description": "Will create/update node_modules folder" },
+        { "kind": "modify", "description": "May update package-lock.json or yarn.lock" },
+        { "kind": "network", "description": "Will download packages from the registry" } ] },
+    { "id": "builtin:node-run", "risk": "medium",
+      "command": ["npm run", "pnpm run", "yarn run"],
+      "effects": [{ "kind": "other", "description": "Will run npm script: {arg1}" }] },
+    { "id": "builtin:node-publish", "risk": "high",
+      "command": ["npm publish", "pnpm publish", "yarn publish"],
+      "effects": [{ "kind": "network", "description": "Will publish the package to the registry" }] },
+    { "id": "builtin:git-read", "risk": "low", "estimate": "seconds",
+      "command": ["git status", "git log", "git diff", "git show", "git branch"] },
+    { "id": "builtin:git-commit", "risk": "low", "estimate": "seconds",
+      "command": "git commit",
+      "effects": [{ "kind": "modify", "path": ".git", "description": "Will create a new git commit" }] },
+    { "id": "builtin:git-force-push", "risk": "high",
+      "command": "git push --force",
+      "effects": [{ "kind": "network", "description": "Will overwrite the history of the remote branch" }] },
+    { "id": "builtin:git-push", "risk": "medium",
+      "command": "git push",
+      "effects": [{ "kind": "network", "description": "Will push commits to remote repository" }] },
+    { "id": "builtin:git-pull", "risk": "medium",
+      "command": "git pull",
+      "effects": [
+        { "kind": "network", "description": "Will fetch and merge changes from remote" },
+        { "kind": "modify", "description": "May change files in the working tree" } ] },
+    { "id": "builtin:git-checkout", "risk": "medium",
+      "command": ["git checkout", "git switch", "git restore"],
+      "effects": [{ "kind": "modify", "description": "Will switch branches or restore files" }] },
+    { "id": "builtin:git-reset-hard", "risk": "high",
+      "command": "git reset --hard",
+      "effects": [{ "kind": "delete", "description": "Will discard uncommitted changes" }] },
+    { "id": "builtin:cargo-fix", "risk": "medium", "estimate": "10s-10m",
+      "command": ["cargo fix", "cargo fmt", "cargo clippy --fix"],
+      "effects": [{ "kind": "modify", "description": "Will rewrite Rust source files" }] },
+    { "id": "builtin:cargo-build", "risk": "low", "estimate": "10s-10m",
+      "command": ["cargo build", "cargo test", "cargo check", "cargo run", "cargo clippy"],
+      "effects": [
+        { "kind": "other", "description": "Will compile Rust project" },
+        { "kind": "modify", "path": "target", "description": "Will create/update target directory" } ] },
+    { "id": "builtin:cargo-publish", "risk": "high",
+      "command": "cargo publish",
+      "effects": [{ "kind": "network", "description": "Will publish the crate to the registry" }] },
+    { "id": "builtin:docker-build", "risk": "medium",
+      "command": "docker build",
+      "effects": [{ "kind": "other", "description": "Will build a Docker image" }] },
+    { "id": "builtin:docker-run", "risk": "medium",
+      "command": "docker run",
+      "effects": [{ "kind": "other", "description": "Will start a Docker container" }] },
+    { "id": "builtin:docker-stop", "risk": "medium",
+      "command": "docker stop",
+      "effects": [{ "kind": "other", "description": "Will stop running container(s)" }] },
+    { "id": "builtin:rm-recursive", "risk": "high",
+      "command": "rm -r",
+      "effects": [{ "kind": "delete", "path": "{args}", "description": "Will recursively delete {path}" }] },
+    { "id": "builtin:rm", "risk": "high",
+      "command": "rm",
+      "effects": [{ "kind": "delete", "path": "{args}", "description": "Will delete {path}" }] },
+    { "id": "builtin:mkdir", "risk": "low",
+      "command": "mkdir",
+      "effects": [{ "kind": "create", "path": "{args}", "description": "Will create directory {path}" }] },
+    { "id": "builtin:touch", "risk": "low",
+      "command": "touch",
+      "effects": [{ "kind": "modify", "path": "{args}", "description": "Will create or touch {path}" }] }
+  ]
+}"#;
+
+/// An ordered list of predictors
+#[derive(Debug, Clone, Default)]
+pub struct EffectRegistry {
+    predictors: Vec<CompiledPredictor>,
+}
+
+impl EffectRegistry {
+    /// Parse predictors from JSON
+    pub fn from_json(content: &str) -> McpResult<Self> {
+        let file: PredictorFile = serde_json::from_str(content)
+            .map_err(|e| McpError::ConfigError(format!("Invalid effect predictors: {}", e)))?;
+
+        let predictors = file.predictors.into_iter()
+            .map(CompiledPredictor::compile)
+            .collect::<McpResult<Vec<_>>>()?;
+
+        Ok(Self { predictors })
+    }
+
+    /// The predictors shipped with the server
+    pub fn builtin() -> Self {
+        Self::from_json(BUILTIN_PREDICTORS).expect("built-in effect predictors are valid")
+    }
+
+    /// Load user predictors from a file, followed by the built-in ones
+    pub fn load(path: &Path) -> McpResult<Self> {
+        let mut registry = if path.exists() {
+            let content = std::fs::read_to_string(path)
+                .map_err(|e| McpError::ConfigError(e.to_string()))?;
+            Self::from_json(&content)?
+        } else {
+            Self::default()
+        };
+        registry.predictors.extend(Self::builtin().predictors);
+        Ok(registry)
+    }
+
+    /// Predict the effects of a command with the first matching predictor
+    pub fn predict(&self, command: &str, args: &[String], cwd: Option<&Path>) -> Prediction {
+        let parsed = ParsedCommand::parse(command, args);
+
+        match self.predictors.iter().find(|p| p.patterns.iter().any(|pattern| pattern.matches(&parsed))) {
+            Some(matched) => Prediction {
+                predictor_id: Some(matched.predictor.id.clone()),
+                effects: matched.effects(&parsed, cwd),
+                risk: matched.predictor.risk,
+                estimate: matched.predictor.estimate.clone(),
+            },
+            None => Prediction {
+                predictor_id: None,
+                effects: vec![Effect {
+                    kind: EffectKind::Other,
+                    path: None,
+                    description: format!("Will execute: {} {}", command, args.join(" ")),
+                }],
+                risk: RiskLevel::Medium,
+                estimate: None,
+            },
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.predictors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.predictors.is_empty()
+    }
+}
+
+/// Median duration of recent successful runs of the same command and
+/// subcommand, with the number of runs it is based on
+pub fn historical_duration(audit: &AuditLogger, command: &str, args: &[String]) -> McpResult<Option<(Duration, usize)>> {
+    let parsed = ParsedCommand::parse(command, args);
+    // Matches the details written by `CommandService` for finished commands
+    let prefix = match &parsed.subcommand {
+        Some(subcommand) => format!("Executed: {} {} ", command, subcommand),
+        None => format!("Executed: {} ", command),
+    };
+
+    let mut durations = audit.recent_durations("command", "execute", &prefix, HISTORY_LIMIT)?;
+    if durations.is_empty() {
+        return Ok(None);
+    }
+    durations.sort_unstable();
+    Ok(Some((Duration::from_millis(durations[durations.len() / 2]), durations.len())))
+}
+
+/// Format a duration estimate for display, e.g. `~1m 30s`
+pub fn format_estimate(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs == 0 {
+        format!("~{}ms", duration.as_millis())
+    } else if secs < 60 {
+        format!("~{}s", secs)
+    } else {
+        format!("~{}m {}s", secs / 60, secs % 60)
+    }
+}
@@ -16,6 +16,9 @@ pub enum McpError {
     #[error("Approval required for action: {0}")]
     ApprovalRequired(String),
 
+    #[error("Invalid approval: {0}")]
+    InvalidApproval(String),
+
     #[error("File operation failed: {0}")]
     FileError(String),
 
@@ -43,6 +46,12 @@ pub enum McpError {
     #[error("Resource not found: {0}")]
     NotFound(String),
 
+    #[error("Not supported: {0}")]
+    Unsupported(String),
+
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
     #[error("Internal error: {0}")]
     Internal(String),
 }
@@ -54,8 +63,11 @@ impl From<McpError> for tonic::Status {
             McpError::PathNotAllowed(msg) => tonic::Status::permission_denied(msg),
             McpError::CommandNotWhitelisted(msg) => tonic::Status::permission_denied(msg),
             McpError::ApprovalRequired(msg) => tonic::Status::failed_precondition(msg),
+            McpError::InvalidApproval(msg) => tonic::Status::permission_denied(msg),
             McpError::InvalidArgument(msg) => tonic::Status::invalid_argument(msg),
             McpError::NotFound(msg) => tonic::Status::not_found(msg),
+            McpError::Unsupported(msg) => tonic::Status::unimplemented(msg),
+            McpError::Conflict(msg) => tonic::Status::failed_precondition(msg),
             _ => tonic::Status::internal(err.to_string()),
         }
     }
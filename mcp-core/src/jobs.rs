@@ -0,0 +1,502 @@
+//! Background jobs: commands that keep running after the request returns
+//!
+//! Each job runs in the sandbox like a regular command, but its output goes
+//! to a bounded buffer that clients can tail while it runs. Starting,
+//! cancelling and finishing a job are all recorded in the audit log.
+
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, watch, Notify};
+use uuid::Uuid;
+
+use crate::audit::AuditLogger;
+use crate::error::{McpError, McpResult};
+use crate::policy::Authorization;
+use crate::sandbox::{OutputChunk, SandboxConfig, SandboxExecutor, SandboxOutput};
+
+/// Output kept per job before the oldest output is dropped
+pub const DEFAULT_OUTPUT_CAPACITY: usize = 1024 * 1024;
+
+/// Jobs without an explicit timeout are killed after a day
+pub const DEFAULT_JOB_TIMEOUT_SECS: u64 = 24 * 60 * 60;
+
+/// Finished jobs kept for listing and tailing before the oldest are forgotten
+const MAX_FINISHED_JOBS: usize = 50;
+
+/// How long shutdown waits for cancelled jobs to be reaped
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+
+/// Lifecycle state of a job
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobState {
+    Running,
+    Exited,
+    TimedOut,
+    Cancelled,
+    /// The command could not be run at all
+    Failed,
+}
+
+impl JobState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobState::Running => "running",
+            JobState::Exited => "exited",
+            JobState::TimedOut => "timed_out",
+            JobState::Cancelled => "cancelled",
+            JobState::Failed => "failed",
+        }
+    }
+}
+
+/// A piece of job output and its position in the job's combined output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JobOutput {
+    pub offset: u64,
+    pub chunk: OutputChunk,
+}
+
+impl JobOutput {
+    fn data(&self) -> &[u8] {
+        match &self.chunk {
+            OutputChunk::Stdout(data) | OutputChunk::Stderr(data) => data,
+        }
+    }
+
+    fn end(&self) -> u64 {
+        self.offset + self.data().len() as u64
+    }
+
+    /// Drop the first `n` bytes of the chunk
+    fn skip(&mut self, n: usize) {
+        match &mut self.chunk {
+            OutputChunk::Stdout(data) | OutputChunk::Stderr(data) => {
+                data.drain(..n);
+            }
+        }
+        self.offset += n as u64;
+    }
+}
+
+/// Ring buffer holding the most recent output of a job
+struct OutputRing {
+    capacity: usize,
+    chunks: VecDeque<JobOutput>,
+    size: usize,
+    end: u64,
+}
+
+impl OutputRing {
+    fn new(capacity: usize) -> Self {
+        Self { capacity, chunks: VecDeque::new(), size: 0, end: 0 }
+    }
+
+    fn push(&mut self, chunk: OutputChunk) {
+        let output = JobOutput { offset: self.end, chunk };
+        let len = output.data().len();
+        if len == 0 {
+            return;
+        }
+        self.end += len as u64;
+        self.size += len;
+        self.chunks.push_back(output);
+
+        while self.size > self.capacity {
+            let excess = self.size - self.capacity;
+            let front = self.chunks.front_mut().expect("buffer holds the excess");
+            if front.data().len() <= excess {
+                self.size -= front.data().len();
+                self.chunks.pop_front();
+            } else {
+                front.skip(excess);
+                self.size -= excess;
+            }
+        }
+    }
+
+    /// Offset of the oldest byte still held
+    fn start(&self) -> u64 {
+        self.end - self.size as u64
+    }
+
+    /// Output from `offset` on, or from the oldest held byte if it was dropped
+    fn since(&self, offset: u64) -> Vec<JobOutput> {
+        self.chunks.iter()
+            .filter(|c| c.end() > offset)
+            .map(|c| {
+                let mut c = c.clone();
+                if c.offset < offset {
+                    c.skip((offset - c.offset) as usize);
+                }
+                c
+            })
+            .collect()
+    }
+}
+
+/// A snapshot of a job's status
+#[derive(Debug, Clone)]
+pub struct JobInfo {
+    pub id: String,
+    pub command_line: String,
+    pub cwd: Option<String>,
+    pub state: JobState,
+    pub exit_code: Option<i32>,
+    pub signal: Option<i32>,
+    pub started_at: DateTime<Utc>,
+    /// How long the job has been running, or ran for
+    pub duration: Duration,
+    /// Range of output offsets held in the buffer
+    pub output_start: u64,
+    pub output_end: u64,
+    pub error: Option<String>,
+    /// Snapshot of the working directory taken before the job started
+    pub snapshot_id: Option<String>,
+}
+
+struct Job {
+    id: String,
+    command_line: String,
+    cwd: Option<String>,
+    snapshot_id: Option<String>,
+    started_at: DateTime<Utc>,
+    started: Instant,
+    status: Mutex<JobStatus>,
+    output: Mutex<OutputRing>,
+    cancel: Notify,
+    /// Bumped on every new output and state change
+    changed: watch::Sender<u64>,
+}
+
+#[derive(Default)]
+struct JobStatus {
+    finished: Option<SandboxOutput>,
+    cancelled: Option<String>,
+    error: Option<String>,
+}
+
+impl Job {
+    fn state(&self) -> JobState {
+        let status = self.status.lock().unwrap();
+        match (&status.finished, &status.error) {
+            (_, Some(_)) => JobState::Failed,
+            (None, None) => JobState::Running,
+            (Some(_), None) if status.cancelled.is_some() => JobState::Cancelled,
+            (Some(output), None) if output.timed_out => JobState::TimedOut,
+            (Some(_), None) => JobState::Exited,
+        }
+    }
+
+    fn info(&self) -> JobInfo {
+        let state = self.state();
+        let status = self.status.lock().unwrap();
+        let output = self.output.lock().unwrap();
+        let finished = status.finished.as_ref();
+        JobInfo {
+            id: self.id.clone(),
+            command_line: self.command_line.clone(),
+            cwd: self.cwd.clone(),
+            state,
+            exit_code: finished.map(|o| o.exit_code),
+            signal: finished.and_then(|o| o.signal),
+            started_at: self.started_at,
+            duration: finished.map(|o| o.duration).unwrap_or_else(|| self.started.elapsed()),
+            output_start: output.start(),
+            output_end: output.end,
+            error: status.error.clone(),
+            snapshot_id: self.snapshot_id.clone(),
+        }
+    }
+
+    fn push_output(&self, chunk: OutputChunk) {
+        self.output.lock().unwrap().push(chunk);
+        self.changed.send_modify(|v| *v += 1);
+    }
+
+    /// Request cancellation; returns false if the job already finished
+    fn request_cancel(&self, reason: &str) -> bool {
+        {
+            let mut status = self.status.lock().unwrap();
+            if status.finished.is_some() || status.error.is_some() {
+                return false;
+            }
+            status.cancelled.get_or_insert_with(|| reason.to_string());
+        }
+        self.cancel.notify_one();
+        true
+    }
+
+    async fn wait(&self) {
+        let mut changed = self.changed.subscribe();
+        while self.state() == JobState::Running {
+            if changed.changed().await.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+/// Handle for following a job's output
+pub struct JobTail {
+    job: Arc<Job>,
+    changed: watch::Receiver<u64>,
+    offset: u64,
+}
+
+impl JobTail {
+    /// Output produced since the last call, starting at the requested offset
+    pub fn read(&mut self) -> Vec<JobOutput> {
+        self.changed.borrow_and_update();
+        let output = self.job.output.lock().unwrap().since(self.offset);
+        if let Some(last) = output.last() {
+            self.offset = last.end();
+        }
+        output
+    }
+
+    /// Whether the job is still running
+    pub fn running(&self) -> bool {
+        self.job.state() == JobState::Running
+    }
+
+    /// Wait until there is new output or the job changes state
+    pub async fn changed(&mut self) {
+        let _ = self.changed.changed().await;
+    }
+
+    pub fn info(&self) -> JobInfo {
+        self.job.info()
+    }
+}
+
+/// Tracks background jobs for the lifetime of the server
+pub struct JobManager {
+    audit: Arc<AuditLogger>,
+    jobs: Mutex<HashMap<String, Arc<Job>>>,
+    output_capacity: usize,
+    shutting_down: AtomicBool,
+}
+
+impl JobManager {
+    pub fn new(audit: Arc<AuditLogger>) -> Self {
+        Self {
+            audit,
+            jobs: Mutex::new(HashMap::new()),
+            output_capacity: DEFAULT_OUTPUT_CAPACITY,
+            shutting_down: AtomicBool::new(false),
+        }
+    }
+
+    /// Keep at most `bytes` of output per job
+    pub fn with_output_capacity(mut self, bytes: usize) -> Self {
+        self.output_capacity = bytes;
+        self
+    }
+
+    /// Start a command as a background job
+    pub fn start(
+        &self,
+        command: &str,
+        args: &[String],
+        config: SandboxConfig,
+        authorization: &Authorization,
+    ) -> McpResult<JobInfo> {
+        if self.shutting_down.load(Ordering::SeqCst) {
+            return Err(McpError::CommandError("Server is shutting down".to_string()));
+        }
+
+        let job = Arc::new(Job {
+            id: Uuid::new_v4().to_string(),
+            command_line: format!("{} {}", command, args.join(" ")),
+            cwd: config.cwd.clone(),
+            snapshot_id: authorization.snapshot_id.clone(),
+            started_at: Utc::now(),
+            started: Instant::now(),
+            status: Mutex::new(JobStatus::default()),
+            output: Mutex::new(OutputRing::new(self.output_capacity)),
+            cancel: Notify::new(),
+            changed: watch::channel(0).0,
+        });
+
+        {
+            let mut jobs = self.jobs.lock().unwrap();
+            Self::prune(&mut jobs);
+            jobs.insert(job.id.clone(), job.clone());
+        }
+
+        let mut entry = AuditLogger::create_entry("job", "start");
+        entry.details = format!("Started job {}: {}", job.id, job.command_line);
+        authorization.apply_to(&mut entry);
+        entry.snapshot_id = job.snapshot_id.clone();
+        entry.result = "running".to_string();
+        let _ = self.audit.log(entry);
+
+        let info = job.info();
+        tokio::spawn(Self::run(
+            job,
+            command.to_string(),
+            args.to_vec(),
+            config,
+            self.audit.clone(),
+        ));
+        Ok(info)
+    }
+
+    async fn run(
+        job: Arc<Job>,
+        command: String,
+        args: Vec<String>,
+        config: SandboxConfig,
+        audit: Arc<AuditLogger>,
+    ) {
+        let (chunk_tx, mut chunk_rx) = mpsc::channel(64);
+        let execution = SandboxExecutor::execute_streaming(&command, &args, &config, Some(chunk_tx));
+        tokio::pin!(execution);
+
+        let mut cancelling = false;
+        let result = loop {
+            tokio::select! {
+                result = &mut execution => break result,
+                Some(chunk) = chunk_rx.recv() => job.push_output(chunk),
+                // Closing the channel makes the executor kill the process group
+                _ = job.cancel.notified(), if !cancelling => {
+                    cancelling = true;
+                    chunk_rx.close();
+                }
+            }
+        };
+        while let Ok(chunk) = chunk_rx.try_recv() {
+            job.push_output(chunk);
+        }
+
+        {
+            let mut status = job.status.lock().unwrap();
+            match result {
+                Ok(output) => status.finished = Some(output),
+                Err(e) => status.error = Some(e.to_string()),
+            }
+        }
+
+        let info = job.info();
+        let cancelled = job.status.lock().unwrap().cancelled.clone();
+        let mut entry = AuditLogger::create_entry("job", "finish");
+        entry.duration_ms = Some(info.duration.as_millis() as u64);
+        entry.details = match info.state {
+            JobState::Failed => format!(
+                "Job {} failed: {} ({})",
+                job.id,
+                job.command_line,
+                info.error.as_deref().unwrap_or_default()
+            ),
+            JobState::Cancelled => format!(
+                "Job {} cancelled: {} ({})",
+                job.id,
+                job.command_line,
+                cancelled.as_deref().unwrap_or_default()
+            ),
+            JobState::TimedOut => format!(
+                "Job {} timed out after {}s: {} (process group killed)",
+                job.id, config.timeout_secs, job.command_line
+            ),
+            _ => match info.signal {
+                Some(signal) => format!("Job {} exited: {} (killed by signal {})", job.id, job.command_line, signal),
+                None => format!("Job {} exited: {} (exit: {})", job.id, job.command_line, info.exit_code.unwrap_or(-1)),
+            },
+        };
+        entry.result = match info.state {
+            JobState::Exited if info.exit_code == Some(0) => "success",
+            JobState::Exited => "failed",
+            state => state.as_str(),
+        }.to_string();
+        let _ = audit.log(entry);
+
+        job.changed.send_modify(|v| *v += 1);
+    }
+
+    /// Forget the oldest finished jobs beyond the retention limit
+    fn prune(jobs: &mut HashMap<String, Arc<Job>>) {
+        let mut finished: Vec<(DateTime<Utc>, String)> = jobs.values()
+            .filter(|job| job.state() != JobState::Running)
+            .map(|job| (job.started_at, job.id.clone()))
+            .collect();
+        if finished.len() < MAX_FINISHED_JOBS {
+            return;
+        }
+        finished.sort();
+        for (_, id) in &finished[..=finished.len() - MAX_FINISHED_JOBS] {
+            jobs.remove(id);
+        }
+    }
+
+    fn job(&self, id: &str) -> McpResult<Arc<Job>> {
+        self.jobs.lock().unwrap()
+            .get(id)
+            .cloned()
+            .ok_or_else(|| McpError::NotFound(format!("Job '{}'", id)))
+    }
+
+    /// List jobs, oldest first
+    pub fn list(&self, include_finished: bool) -> Vec<JobInfo> {
+        let mut jobs: Vec<JobInfo> = self.jobs.lock().unwrap()
+            .values()
+            .map(|job| job.info())
+            .filter(|info| include_finished || info.state == JobState::Running)
+            .collect();
+        jobs.sort_by_key(|info| info.started_at);
+        jobs
+    }
+
+    pub fn get(&self, id: &str) -> McpResult<JobInfo> {
+        Ok(self.job(id)?.info())
+    }
+
+    /// Follow a job's output from `offset`
+    pub fn tail(&self, id: &str, offset: u64) -> McpResult<JobTail> {
+        let job = self.job(id)?;
+        let changed = job.changed.subscribe();
+        Ok(JobTail { job, changed, offset })
+    }
+
+    /// Cancel a running job, killing its process group, and wait for it to end
+    pub async fn cancel(&self, id: &str) -> McpResult<JobInfo> {
+        let job = self.job(id)?;
+
+        let mut entry = AuditLogger::create_entry("job", "cancel");
+        entry.details = format!("Cancel requested for job {}: {}", job.id, job.command_line);
+        entry.result = if job.request_cancel("cancelled by client") {
+            "success"
+        } else {
+            "not_running"
+        }.to_string();
+        let _ = self.audit.log(entry);
+
+        job.wait().await;
+        Ok(job.info())
+    }
+
+    /// Cancel all running jobs and stop accepting new ones
+    pub async fn shutdown(&self) {
+        self.shutting_down.store(true, Ordering::SeqCst);
+
+        let running: Vec<Arc<Job>> = self.jobs.lock().unwrap()
+            .values()
+            .filter(|job| job.request_cancel("server shutdown"))
+            .cloned()
+            .collect();
+
+        let mut entry = AuditLogger::create_entry("job", "shutdown");
+        entry.details = format!("Cancelling {} running job(s) at shutdown", running.len());
+        entry.result = "success".to_string();
+        let _ = self.audit.log(entry);
+
+        let reaped = async {
+            for job in &running {
+                job.wait().await;
+            }
+        };
+        let _ = tokio::time::timeout(SHUTDOWN_GRACE, reaped).await;
+    }
+}
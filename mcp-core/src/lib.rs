@@ -2,16 +2,67 @@
 //! 
 //! This module exports the core functionality for use in tests and as a library.
 
+mod proto;
+
+pub mod approval;
+pub mod artifacts;
 pub mod audit;
+pub mod binaries;
+pub mod command_match;
 pub mod config;
+pub mod confine;
+pub mod diff;
+pub mod edits;
+pub mod effects;
 pub mod error;
+pub mod jobs;
 pub mod policy;
+pub mod preview;
+pub mod rules;
+pub mod secrets;
 pub mod sandbox;
+pub mod services;
+pub mod simulate;
 pub mod snapshot;
 
+pub mod file_proto {
+    include!("proto/mcp.file.rs");
+}
+
+pub mod command_proto {
+    include!("proto/mcp.command.rs");
+}
+
+pub mod git_proto {
+    include!("proto/mcp.git.rs");
+}
+
+pub mod snapshot_proto {
+    include!("proto/mcp.snapshot.rs");
+}
+
+pub mod system_proto {
+    include!("proto/mcp.system.rs");
+}
+
+pub mod policy_proto {
+    include!("proto/mcp.policy.rs");
+}
+
+pub use approval::{ApprovalAction, ApprovalStore, IssuedApproval};
+pub use artifacts::{ArtifactRead, ArtifactStore};
 pub use audit::{AuditLogger, AuditEntry};
-pub use config::Config;
+pub use binaries::ResolvedBinary;
+pub use command_match::{CommandPattern, ParsedCommand};
+pub use config::{CommandPin, Config, EnvPolicy, PathPermission, PathZone};
+pub use confine::{Confinement, ConfinedPath, OpenMode};
+pub use effects::{Effect, EffectKind, EffectRegistry, Prediction, RiskLevel};
 pub use error::{McpError, McpResult};
-pub use policy::{PolicyEngine, PolicyDecision};
-pub use sandbox::{SandboxExecutor, SandboxConfig, SandboxOutput};
+pub use jobs::{JobInfo, JobManager, JobOutput, JobState};
+pub use policy::{PolicyEngine, PolicyDecision, PolicyVerdict, Authorization};
+pub use preview::{FileState, Preview, PreviewChange};
+pub use rules::{RuleSet, RuleInput, RuleMatch};
+pub use sandbox::{SandboxExecutor, SandboxConfig, SandboxOutput, ResourceLimits, OutputChunk, CommandInput, TerminalSize};
+pub use secrets::SecretScrubber;
+pub use simulate::{ChangeKind, FileChange, Workspace};
 pub use snapshot::{SnapshotManager, Snapshot};
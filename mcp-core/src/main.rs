@@ -3,57 +3,40 @@
 //! A secure local Model Context Protocol server that exposes capability-limited
 //! tools to LLMs with audit logging and policy enforcement.
 
-mod proto;
-mod services;
-mod policy;
-mod audit;
-mod sandbox;
-mod snapshot;
-mod error;
-mod config;
-
+use std::future::Future;
 use std::net::SocketAddr;
+use std::path::Path;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use tonic::transport::Server;
+#[cfg(unix)]
+use tokio_stream::wrappers::UnixListenerStream;
 use tracing::{info, Level};
 use tracing_subscriber::FmtSubscriber;
 
-use crate::audit::AuditLogger;
-use crate::config::Config;
-use crate::policy::PolicyEngine;
-use crate::services::{
+use mcp_core::artifacts::ArtifactStore;
+use mcp_core::audit::AuditLogger;
+use mcp_core::config::Config;
+use mcp_core::effects::EffectRegistry;
+use mcp_core::jobs::JobManager;
+use mcp_core::policy::PolicyEngine;
+use mcp_core::rules::RuleSet;
+use mcp_core::secrets::SecretScrubber;
+use mcp_core::snapshot;
+use mcp_core::services::{
     file_service::FileServiceImpl,
     command_service::CommandServiceImpl,
     git_service::GitServiceImpl,
     snapshot_service::SnapshotServiceImpl,
     system_service::SystemServiceImpl,
+    policy_service::PolicyServiceImpl,
 };
-
-pub mod file_proto {
-    include!("proto/file_service.rs");
-}
-
-pub mod command_proto {
-    include!("proto/command_service.rs");
-}
-
-pub mod git_proto {
-    include!("proto/git_service.rs");
-}
-
-pub mod snapshot_proto {
-    include!("proto/snapshot_service.rs");
-}
-
-pub mod system_proto {
-    include!("proto/system_service.rs");
-}
+use mcp_core::{file_proto, command_proto, git_proto, snapshot_proto, system_proto, policy_proto};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Initialize tracing
-    let subscriber = FmtSubscriber::builder()
+    FmtSubscriber::builder()
         .with_max_level(Level::INFO)
         .pretty()
         .init();
@@ -68,7 +51,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let audit_logger = Arc::new(AuditLogger::new(&config.read().await.audit_db_path)?);
 
     // Initialize policy engine
-    let policy_engine = Arc::new(PolicyEngine::new(config.clone()));
+    let rules = RuleSet::load(&config.read().await.policy_rules_path)?;
+    info!("Loaded {} policy rules", rules.len());
+    let policy_engine = Arc::new(PolicyEngine::new(config.clone(), audit_logger.clone()).with_rules(rules));
 
     // Initialize snapshot service
     let snapshot_service = Arc::new(snapshot::SnapshotManager::new(
@@ -83,20 +68,35 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         snapshot_service.clone(),
     );
 
+    // Background jobs are shared so they can be stopped at shutdown
+    let job_manager = Arc::new(JobManager::new(audit_logger.clone()));
+
+    let predictors = EffectRegistry::load(&config.read().await.effect_rules_path)?;
+    info!("Loaded {} effect predictors", predictors.len());
+
+    let artifacts = Arc::new(ArtifactStore::new(&config.read().await.artifact_dir)?);
+
+    let scrubber = SecretScrubber::builtin().with_patterns(&config.read().await.secret_patterns)?;
+
     let command_service = CommandServiceImpl::new(
         config.clone(),
         audit_logger.clone(),
         policy_engine.clone(),
-    );
+    )
+    .with_jobs(job_manager.clone())
+    .with_predictors(predictors)
+    .with_snapshots(snapshot_service.clone())
+    .with_scrubber(scrubber)
+    .with_artifacts(artifacts);
 
     let git_service = GitServiceImpl::new(
-        config.clone(),
         audit_logger.clone(),
         policy_engine.clone(),
     );
 
     let snapshot_svc = SnapshotServiceImpl::new(
         audit_logger.clone(),
+        policy_engine.clone(),
         snapshot_service.clone(),
     );
 
@@ -104,19 +104,103 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         audit_logger.clone(),
     );
 
+    let policy_service = PolicyServiceImpl::new(
+        config.clone(),
+        audit_logger.clone(),
+        policy_engine.clone(),
+    );
+
     // Configure server address
     let addr: SocketAddr = config.read().await.server_address.parse()?;
     info!("MCP Server listening on {}", addr);
 
+    // Jobs are cancelled as soon as the signal arrives: tonic waits for open
+    // requests to finish before shutting down, and job streams would keep
+    // it waiting
+    let (stopped, mut stop) = tokio::sync::watch::channel(());
+    let shutdown = async move {
+        shutdown_signal().await;
+        info!("Shutting down, stopping background jobs...");
+        job_manager.shutdown().await;
+        let _ = stopped.send(());
+    };
+
+    // The policy service can widen what the other services allow and issue
+    // approvals, so it is not served to the clients using them
+    let policy_socket = config.read().await.policy_socket.clone();
+    let policy_server = serve_policy(policy_service, &policy_socket, async move {
+        let _ = stop.changed().await;
+    });
+
     // Start gRPC server
-    Server::builder()
+    let server = Server::builder()
         .add_service(file_proto::file_service_server::FileServiceServer::new(file_service))
         .add_service(command_proto::command_service_server::CommandServiceServer::new(command_service))
         .add_service(git_proto::git_service_server::GitServiceServer::new(git_service))
         .add_service(snapshot_proto::snapshot_service_server::SnapshotServiceServer::new(snapshot_svc))
         .add_service(system_proto::system_service_server::SystemServiceServer::new(system_service))
-        .serve(addr)
+        .serve_with_shutdown(addr, shutdown);
+
+    tokio::try_join!(async { server.await.map_err(Into::into) }, policy_server)?;
+
+    Ok(())
+}
+
+/// Serve the policy service on a Unix socket only the server's user can
+/// connect to, until `shutdown` resolves
+#[cfg(unix)]
+async fn serve_policy(
+    service: PolicyServiceImpl,
+    socket: &Path,
+    shutdown: impl Future<Output = ()>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    if let Some(parent) = socket.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    // A socket left by a previous run would make the bind fail
+    if std::fs::symlink_metadata(socket).is_ok_and(|m| m.file_type().is_socket()) {
+        std::fs::remove_file(socket)?;
+    }
+    let listener = tokio::net::UnixListener::bind(socket)?;
+    std::fs::set_permissions(socket, std::fs::Permissions::from_mode(0o600))?;
+    info!("Policy service listening on {}", socket.display());
+
+    Server::builder()
+        .add_service(policy_proto::policy_service_server::PolicyServiceServer::new(service))
+        .serve_with_incoming_shutdown(UnixListenerStream::new(listener), shutdown)
         .await?;
+    Ok(())
+}
 
+#[cfg(not(unix))]
+async fn serve_policy(
+    _service: PolicyServiceImpl,
+    _socket: &Path,
+    _shutdown: impl Future<Output = ()>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    tracing::warn!("The policy service needs a Unix socket and is not served on this platform");
     Ok(())
 }
+
+/// Wait for Ctrl-C, or for SIGTERM as sent by service managers and containers
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+                return;
+            }
+            Err(e) => tracing::warn!("Failed to listen for SIGTERM: {}", e),
+        }
+    }
+
+    let _ = tokio::signal::ctrl_c().await;
+}
@@ -1,9 +1,15 @@
 //! Policy engine for MCP operations
 
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use crate::config::Config;
+use crate::approval::{ApprovalAction, ApprovalStore, IssuedApproval};
+use crate::audit::{AuditEntry, AuditLogger};
+use crate::command_match::{CommandPattern, ParsedCommand};
+use crate::config::{Config, PathPermission};
 use crate::error::{McpError, McpResult};
+use crate::rules::{RuleInput, RuleMatch, RuleSet};
 
 /// Policy decision result
 #[derive(Debug, Clone)]
@@ -16,125 +22,438 @@ pub enum PolicyDecision {
     Deny(String),
 }
 
+/// A policy decision together with the rule that produced it
+#[derive(Debug, Clone)]
+pub struct PolicyVerdict {
+    pub decision: PolicyDecision,
+    /// Identifier of the matched rule, if the decision came from a rule
+    pub rule_id: Option<String>,
+    /// Whether the matched rule asks for a snapshot before the action
+    pub snapshot: Option<bool>,
+    /// Whether the matched rule only allows the action without network access
+    pub offline: bool,
+    /// Binary a command resolved to
+    pub binary: Option<PathBuf>,
+}
+
+impl PolicyVerdict {
+    /// A decision that did not come from any rule
+    pub fn new(decision: PolicyDecision) -> Self {
+        Self { decision, rule_id: None, snapshot: None, offline: false, binary: None }
+    }
+
+    /// Combine two verdicts, keeping the most restrictive one
+    pub fn and(self, other: PolicyVerdict) -> PolicyVerdict {
+        let rank = |v: &PolicyVerdict| match v.decision {
+            PolicyDecision::Allow => 0,
+            PolicyDecision::RequireApproval(_) => 1,
+            PolicyDecision::Deny(_) => 2,
+        };
+        if rank(&other) > rank(&self) { other } else { self }
+    }
+}
+
+impl From<RuleMatch> for PolicyVerdict {
+    fn from(m: RuleMatch) -> Self {
+        Self {
+            decision: m.decision,
+            rule_id: Some(m.rule_id),
+            snapshot: m.snapshot,
+            offline: m.offline,
+            binary: None,
+        }
+    }
+}
+
+/// Result of enforcing a policy decision for an action
+#[derive(Debug, Clone, Default)]
+pub struct Authorization {
+    /// Whether the action was explicitly approved by the user
+    pub user_approved: bool,
+    /// The approval token consumed for this action, if any
+    pub approval_token: Option<String>,
+    /// The policy rule that decided the action, if any
+    pub rule_id: Option<String>,
+    /// The action must run without network access
+    pub offline: bool,
+    /// Binary a command must run as
+    pub binary: Option<PathBuf>,
+    /// Snapshot of the working directory taken before a command ran
+    pub snapshot_id: Option<String>,
+}
+
+impl Authorization {
+    /// Record the authorization on an audit entry
+    pub fn apply_to(&self, entry: &mut AuditEntry) {
+        entry.user_approved = self.user_approved;
+        entry.approval_token = self.approval_token.clone();
+        entry.rule_id = self.rule_id.clone();
+    }
+}
+
 /// Policy engine for checking and enforcing rules
 pub struct PolicyEngine {
     config: Arc<RwLock<Config>>,
+    audit: Arc<AuditLogger>,
+    approvals: ApprovalStore,
+    /// User-defined rules, evaluated before the built-in ones
+    rules: RuleSet,
+    builtin_rules: RuleSet,
 }
 
 impl PolicyEngine {
-    pub fn new(config: Arc<RwLock<Config>>) -> Self {
-        Self { config }
+    pub fn new(config: Arc<RwLock<Config>>, audit: Arc<AuditLogger>) -> Self {
+        Self {
+            config,
+            audit,
+            approvals: ApprovalStore::new(),
+            rules: RuleSet::default(),
+            builtin_rules: RuleSet::builtin(),
+        }
     }
 
-    /// Check if a file operation is allowed
-    pub async fn check_file_access(&self, path: &std::path::Path, write: bool) -> McpResult<PolicyDecision> {
-        let config = self.config.read().await;
+    /// Use the given user-defined rules
+    pub fn with_rules(mut self, rules: RuleSet) -> Self {
+        self.rules = rules;
+        self
+    }
 
-        // Check if path is within allowed paths
-        if !config.is_path_allowed(path) {
-            return Ok(PolicyDecision::Deny(format!(
-                "Path '{}' is not within allowed directories",
-                path.display()
-            )));
-        }
+    /// Evaluate user rules, then built-in rules, falling back to `default`
+    fn evaluate(&self, input: &RuleInput, default: PolicyDecision) -> PolicyVerdict {
+        self.rules.evaluate(input)
+            .or_else(|| self.builtin_rules.evaluate(input))
+            .map(PolicyVerdict::from)
+            .unwrap_or_else(|| PolicyVerdict::new(default))
+    }
 
-        // Write operations may require approval
-        if write {
-            // Check for sensitive paths
-            let path_str = path.to_string_lossy().to_lowercase();
-            if path_str.contains("system32") || path_str.contains("windows") || path_str.contains("/etc") {
-                return Ok(PolicyDecision::Deny("Cannot write to system directories".to_string()));
+    /// Resolve a path, with `..` and symlinks followed, for the rules to
+    /// match. A path that is outside the allowed roots, lacks `permission` in
+    /// its zone, or matches a deny glob is denied instead.
+    fn check_path(config: &Config, path: &Path, permission: PathPermission) -> McpResult<Result<PathBuf, PolicyVerdict>> {
+        match config.confinement().check(path, permission) {
+            Ok(confined) => Ok(Ok(confined.path().to_path_buf())),
+            Err(McpError::PathNotAllowed(reason)) => {
+                Ok(Err(PolicyVerdict::new(PolicyDecision::Deny(reason))))
             }
-
-            // By default, file writes require approval unless auto-approved
-            return Ok(PolicyDecision::RequireApproval(format!(
-                "Write to '{}'",
-                path.display()
-            )));
+            Err(e) => Err(e),
         }
+    }
+
+    /// Check if a file operation is allowed
+    pub async fn check_file_access(&self, path: &Path, permission: PathPermission) -> McpResult<PolicyVerdict> {
+        let config = self.config.read().await;
+
+        let paths = match Self::check_path(&config, path, permission)? {
+            Ok(resolved) => [resolved],
+            Err(denied) => return Ok(denied),
+        };
+        let input = RuleInput {
+            service: "file",
+            operation: permission.as_str(),
+            argv: &[],
+            paths: &paths,
+            cwd: None,
+        };
+
+        // By default, anything but reads requires approval unless a rule allows it
+        let default = match permission {
+            PathPermission::Read => PolicyDecision::Allow,
+            PathPermission::Write => PolicyDecision::RequireApproval(format!("Write to '{}'", path.display())),
+            PathPermission::Create => PolicyDecision::RequireApproval(format!("Create '{}'", path.display())),
+            PathPermission::Delete => PolicyDecision::RequireApproval(format!("Delete '{}'", path.display())),
+            PathPermission::Execute => PolicyDecision::RequireApproval(format!("Execute in '{}'", path.display())),
+        };
 
-        Ok(PolicyDecision::Allow)
+        Ok(self.evaluate(&input, default))
     }
 
     /// Check if a command execution is allowed
-    pub async fn check_command(&self, command: &str, args: &[String]) -> McpResult<PolicyDecision> {
+    pub async fn check_command(&self, command: &str, args: &[String], cwd: Option<&Path>) -> McpResult<PolicyVerdict> {
         let config = self.config.read().await;
 
         // Check if command is whitelisted
         if !config.is_command_whitelisted(command) {
-            return Ok(PolicyDecision::Deny(format!(
+            return Ok(PolicyVerdict::new(PolicyDecision::Deny(format!(
                 "Command '{}' is not whitelisted",
                 command
-            )));
+            ))));
         }
 
-        // Build full command string for pattern matching
-        let full_command = format!("{} {}", command, args.join(" "));
+        // The working directory must be a zone that allows running commands
+        let cwd = match cwd {
+            Some(dir) => match Self::check_path(&config, dir, PathPermission::Execute)? {
+                Ok(resolved) => Some(resolved),
+                Err(denied) => return Ok(denied),
+            },
+            None => None,
+        };
 
-        // Check for auto-approve patterns
-        for pattern in &config.auto_approve_patterns {
-            if full_command.starts_with(pattern) {
-                return Ok(PolicyDecision::Allow);
-            }
+        let verdict = self.evaluate_command(&config, command, args, cwd.as_deref());
+        if matches!(verdict.decision, PolicyDecision::Deny(_)) {
+            return Ok(verdict);
+        }
+
+        // Whatever decided, the command only runs as the binary it resolves to
+        match config.resolve_command(command) {
+            Ok(binary) => Ok(PolicyVerdict { binary: Some(binary.path), ..verdict }),
+            Err(e) => Ok(PolicyVerdict::new(PolicyDecision::Deny(e.to_string()))),
         }
+    }
+
+    /// Decide a whitelisted command from the rules and command patterns
+    fn evaluate_command(&self, config: &Config, command: &str, args: &[String], cwd: Option<&Path>) -> PolicyVerdict {
+        let argv: Vec<String> = std::iter::once(command.to_string())
+            .chain(args.iter().cloned())
+            .collect();
+        let paths: Vec<PathBuf> = cwd.map(Path::to_path_buf).into_iter().collect();
+        let input = RuleInput {
+            service: "command",
+            operation: "execute",
+            argv: &argv,
+            paths: &paths,
+            cwd,
+        };
 
-        // Check for sensitive patterns (always require approval)
-        for pattern in &config.sensitive_patterns {
-            if full_command.contains(pattern) {
-                return Ok(PolicyDecision::RequireApproval(format!(
+        if let Some(matched) = self.rules.evaluate(&input).or_else(|| self.builtin_rules.evaluate(&input)) {
+            return matched.into();
+        }
+
+        let parsed = ParsedCommand::parse(command, args);
+        let full_command = argv.join(" ");
+
+        // Sensitive patterns always require approval, even if auto-approved
+        for pattern in config.sensitive_patterns.iter().filter_map(|p| CommandPattern::parse(p)) {
+            if pattern.matches(&parsed) {
+                return PolicyVerdict::new(PolicyDecision::RequireApproval(format!(
                     "Sensitive command detected: {}",
                     full_command
                 )));
             }
         }
 
+        // Auto-approve patterns only allow invocations that add nothing unsafe
+        for pattern in config.auto_approve_patterns.iter().filter_map(|p| CommandPattern::parse(p)) {
+            if pattern.allows(&parsed) {
+                return PolicyVerdict::new(PolicyDecision::Allow);
+            }
+        }
+
         // Default: require approval for commands
-        Ok(PolicyDecision::RequireApproval(format!(
+        PolicyVerdict::new(PolicyDecision::RequireApproval(format!(
             "Execute command: {}",
             full_command
         )))
     }
 
+    /// Check the environment variables a request sets for a command: denied
+    /// names are refused, and names that are not allowed need approval
+    pub async fn check_env(&self, env: &HashMap<String, String>) -> McpResult<PolicyVerdict> {
+        let config = self.config.read().await;
+        let policy = &config.env_policy;
+        let matches = |globs: &[String], name: &str| {
+            globs.iter().any(|g| glob::Pattern::new(g).is_ok_and(|p| p.matches(name)))
+        };
+
+        let mut names: Vec<&String> = env.keys().collect();
+        names.sort();
+
+        let mut unlisted = Vec::new();
+        for name in names {
+            if name.is_empty() || name.contains(['=', '\0']) {
+                return Ok(PolicyVerdict::new(PolicyDecision::Deny(format!(
+                    "Invalid environment variable name '{}'",
+                    name
+                ))));
+            }
+            if matches(&policy.denied, name) {
+                return Ok(PolicyVerdict::new(PolicyDecision::Deny(format!(
+                    "Setting environment variable '{}' is not allowed",
+                    name
+                ))));
+            }
+            if !matches(&policy.allowed, name) {
+                unlisted.push(name.as_str());
+            }
+        }
+
+        if unlisted.is_empty() {
+            Ok(PolicyVerdict::new(PolicyDecision::Allow))
+        } else {
+            Ok(PolicyVerdict::new(PolicyDecision::RequireApproval(format!(
+                "Set environment variables: {}",
+                unlisted.join(", ")
+            ))))
+        }
+    }
+
     /// Check if a git operation is allowed
-    pub async fn check_git_operation(&self, repo_path: &std::path::Path, operation: &str) -> McpResult<PolicyDecision> {
+    pub async fn check_git_operation(&self, repo_path: &Path, operation: &str) -> McpResult<PolicyVerdict> {
         let config = self.config.read().await;
 
-        // Check if repo path is within allowed paths
-        if !config.is_path_allowed(repo_path) {
-            return Ok(PolicyDecision::Deny(format!(
-                "Repository path '{}' is not within allowed directories",
-                repo_path.display()
-            )));
+        // Read-only operations only need read access to the repository
+        let permission = match operation {
+            "status" | "log" | "diff" | "branch" => PathPermission::Read,
+            _ => PathPermission::Write,
+        };
+        let paths = match Self::check_path(&config, repo_path, permission)? {
+            Ok(resolved) => [resolved],
+            Err(denied) => return Ok(denied),
+        };
+        let input = RuleInput {
+            service: "git",
+            operation,
+            argv: &[],
+            paths: &paths,
+            cwd: Some(&paths[0]),
+        };
+
+        Ok(self.evaluate(&input, PolicyDecision::RequireApproval(format!(
+            "Git {}: {}",
+            operation,
+            repo_path.display()
+        ))))
+    }
+
+    /// Check a snapshot operation against the affected paths
+    pub async fn check_snapshot_operation(&self, operation: &str, paths: &[PathBuf]) -> McpResult<PolicyVerdict> {
+        let config = self.config.read().await;
+
+        // Restoring writes the paths back; everything else only reads them
+        let permission = if operation == "restore" { PathPermission::Write } else { PathPermission::Read };
+        let mut resolved = Vec::with_capacity(paths.len());
+        for path in paths {
+            match Self::check_path(&config, path, permission)? {
+                Ok(path) => resolved.push(path),
+                Err(denied) => return Ok(denied),
+            }
+        }
+
+        let input = RuleInput {
+            service: "snapshot",
+            operation,
+            argv: &[],
+            paths: &resolved,
+            cwd: None,
+        };
+
+        Ok(self.evaluate(&input, PolicyDecision::RequireApproval(format!("Snapshot {}", operation))))
+    }
+
+    /// Enforce a policy verdict for an action.
+    ///
+    /// Every mutating RPC goes through this: denied actions fail, actions that
+    /// require approval must present a token issued for exactly this action,
+    /// and the token is consumed so it cannot be replayed.
+    pub async fn enforce(
+        &self,
+        verdict: PolicyVerdict,
+        action: &ApprovalAction,
+        approval_token: &str,
+    ) -> McpResult<Authorization> {
+        match verdict.decision {
+            PolicyDecision::Allow => Ok(Authorization {
+                rule_id: verdict.rule_id,
+                offline: verdict.offline,
+                binary: verdict.binary,
+                ..Default::default()
+            }),
+            PolicyDecision::Deny(reason) => {
+                self.audit_denial(verdict.rule_id, action, &reason);
+                Err(McpError::PolicyViolation(reason))
+            }
+            PolicyDecision::RequireApproval(reason) => {
+                if approval_token.is_empty() {
+                    return Err(McpError::ApprovalRequired(reason));
+                }
+                self.validate_approval(approval_token, action).await?;
+                Ok(Authorization {
+                    user_approved: true,
+                    approval_token: Some(approval_token.to_string()),
+                    rule_id: verdict.rule_id,
+                    offline: verdict.offline,
+                    binary: verdict.binary,
+                    ..Default::default()
+                })
+            }
         }
+    }
 
-        // Read operations are generally allowed
-        match operation {
-            "status" | "log" | "diff" | "branch" => Ok(PolicyDecision::Allow),
-            "commit" | "push" | "pull" | "checkout" | "merge" => {
-                Ok(PolicyDecision::RequireApproval(format!(
-                    "Git {}: {}",
-                    operation,
-                    repo_path.display()
-                )))
+    /// Check a verdict the way `enforce` does, without consuming the
+    /// approval token. A handler with checks of its own runs them between
+    /// this and `enforce`, so a request failing them keeps its approval.
+    pub async fn precheck(
+        &self,
+        verdict: &PolicyVerdict,
+        action: &ApprovalAction,
+        approval_token: &str,
+    ) -> McpResult<()> {
+        match &verdict.decision {
+            PolicyDecision::Allow => Ok(()),
+            PolicyDecision::Deny(reason) => {
+                self.audit_denial(verdict.rule_id.clone(), action, reason);
+                Err(McpError::PolicyViolation(reason.clone()))
             }
-            "push --force" | "reset --hard" => {
-                Ok(PolicyDecision::Deny(format!(
-                    "Dangerous git operation '{}' is blocked by default",
-                    operation
-                )))
+            PolicyDecision::RequireApproval(reason) => {
+                if approval_token.is_empty() {
+                    return Err(McpError::ApprovalRequired(reason.clone()));
+                }
+                let result = self.approvals.check(approval_token, action);
+                if result.is_err() {
+                    self.audit_approval(approval_token, action, &result);
+                }
+                result
             }
-            _ => Ok(PolicyDecision::RequireApproval(format!(
-                "Git {}: {}",
-                operation,
-                repo_path.display()
-            ))),
         }
     }
 
-    /// Validate an approval token
-    pub async fn validate_approval(&self, token: &str) -> bool {
-        // In a real implementation, this would check against stored approval tokens
-        // For now, we accept any non-empty token
-        !token.is_empty()
+    fn audit_denial(&self, rule_id: Option<String>, action: &ApprovalAction, reason: &str) {
+        let mut entry = AuditLogger::create_entry(&action.service, &action.operation);
+        entry.details = format!("Denied {}: {}", action.describe(), reason);
+        entry.rule_id = rule_id;
+        entry.result = "denied".to_string();
+        let _ = self.audit.log(entry);
+    }
+
+    /// Issue a single-use approval token bound to the given action
+    pub async fn issue_approval(&self, action: &ApprovalAction) -> IssuedApproval {
+        let ttl_secs = self.config.read().await.approval_ttl_secs;
+        let issued = self.approvals.issue(action, ttl_secs);
+
+        let mut entry = AuditLogger::create_entry("policy", "approval_issued");
+        entry.details = format!(
+            "Issued approval for {} (expires {})",
+            action.describe(),
+            issued.expires_at.to_rfc3339()
+        );
+        entry.user_approved = true;
+        entry.approval_token = Some(issued.token.clone());
+        entry.result = "issued".to_string();
+        let _ = self.audit.log(entry);
+
+        issued
+    }
+
+    /// Validate and consume an approval token for the given action
+    pub async fn validate_approval(&self, token: &str, action: &ApprovalAction) -> McpResult<()> {
+        let result = self.approvals.consume(token, action);
+        self.audit_approval(token, action, &result);
+        result
+    }
+
+    fn audit_approval(&self, token: &str, action: &ApprovalAction, result: &McpResult<()>) {
+        let mut entry = AuditLogger::create_entry("policy", "approval_consumed");
+        entry.approval_token = if token.is_empty() { None } else { Some(token.to_string()) };
+        match result {
+            Ok(()) => {
+                entry.details = format!("Consumed approval for {}", action.describe());
+                entry.user_approved = true;
+                entry.result = "success".to_string();
+            }
+            Err(e) => {
+                entry.details = format!("Rejected approval for {}: {}", action.describe(), e);
+                entry.result = "rejected".to_string();
+            }
+        }
+        let _ = self.audit.log(entry);
     }
 }
@@ -0,0 +1,182 @@
+//! Previews of what a file mutation would change
+//!
+//! A dry run reports every file the request would create, modify or delete,
+//! with sizes and sha256 hashes before and after, and a unified diff when
+//! both sides are text. The preview hash covers the paths and the hashes,
+//! so an approval bound to it only approves that exact change: if a file
+//! changes between the preview and the write, the write is refused.
+
+use sha2::{Digest, Sha256};
+use std::io::{self, Read};
+
+use crate::confine::{ConfinedPath, OpenMode};
+use crate::diff;
+use crate::error::{McpError, McpResult};
+use crate::simulate::ChangeKind;
+
+/// The contents of a file, hashed as they are read
+#[derive(Clone)]
+pub struct FileState {
+    size: u64,
+    hasher: Sha256,
+    /// The contents, while they are small enough to diff
+    content: Option<Vec<u8>>,
+}
+
+impl FileState {
+    pub fn from_bytes(content: &[u8]) -> Self {
+        Self::empty().appended(content)
+    }
+
+    /// The state of a file, or `None` if it does not exist
+    pub fn read(target: &ConfinedPath) -> McpResult<Option<Self>> {
+        let mut file = match target.open(OpenMode::Read) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut state = Self::empty();
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            match file.read(&mut buf)? {
+                0 => return Ok(Some(state)),
+                n => state.append(&buf[..n]),
+            }
+        }
+    }
+
+    /// The state after `data` is appended
+    pub fn appended(&self, data: &[u8]) -> Self {
+        let mut state = self.clone();
+        state.append(data);
+        state
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn sha256(&self) -> String {
+        hex::encode(self.hasher.clone().finalize())
+    }
+
+    /// The state of an empty file, to `append` to
+    pub fn empty() -> Self {
+        Self { size: 0, hasher: Sha256::new(), content: Some(Vec::new()) }
+    }
+
+    pub fn append(&mut self, data: &[u8]) {
+        self.size += data.len() as u64;
+        self.hasher.update(data);
+        if self.size > diff::MAX_DIFF_BYTES as u64 {
+            self.content = None;
+        } else if let Some(content) = &mut self.content {
+            content.extend_from_slice(data);
+        }
+    }
+}
+
+/// A file a mutation would change; `None` stands for no file on that side
+pub struct PreviewChange {
+    pub path: String,
+    pub before: Option<FileState>,
+    pub after: Option<FileState>,
+}
+
+impl PreviewChange {
+    pub fn kind(&self) -> ChangeKind {
+        match (&self.before, &self.after) {
+            (None, _) => ChangeKind::Created,
+            (_, None) => ChangeKind::Deleted,
+            _ => ChangeKind::Modified,
+        }
+    }
+
+    /// Whether a side is not diffed line by line, for being binary or too large
+    pub fn binary(&self) -> bool {
+        [&self.before, &self.after].into_iter().flatten().any(|state| {
+            !state.content.as_deref().is_some_and(diff::is_text)
+        })
+    }
+
+    /// Unified diff of the change
+    pub fn diff(&self) -> String {
+        let old = self.before.as_ref().map(|s| s.content.as_deref());
+        let new = self.after.as_ref().map(|s| s.content.as_deref());
+        match (old, new) {
+            (Some(None), _) | (_, Some(None)) => {
+                let sha256 = |state: &Option<FileState>| state.as_ref().map(FileState::sha256);
+                if sha256(&self.before) == sha256(&self.after) {
+                    String::new()
+                } else {
+                    diff::binary(&self.path, self.before.is_some(), self.after.is_some())
+                }
+            }
+            (old, new) => diff::unified(&self.path, old.flatten(), new.flatten()),
+        }
+    }
+}
+
+/// Everything a file mutation would change
+pub struct Preview {
+    pub changes: Vec<PreviewChange>,
+}
+
+impl Preview {
+    /// `target` is overwritten with `content`
+    pub fn write(path: &str, target: &ConfinedPath, content: &[u8]) -> McpResult<Self> {
+        Self::replace(path, target, FileState::from_bytes(content))
+    }
+
+    /// `target` is overwritten with contents that were hashed as they arrived
+    pub fn replace(path: &str, target: &ConfinedPath, after: FileState) -> McpResult<Self> {
+        Ok(Self::single(path, FileState::read(target)?, Some(after)))
+    }
+
+    /// `data` is appended to `target`, which is created if needed
+    pub fn append(path: &str, target: &ConfinedPath, data: &[u8]) -> McpResult<Self> {
+        let before = FileState::read(target)?;
+        let after = before.as_ref().map_or_else(|| FileState::from_bytes(data), |s| s.appended(data));
+        Ok(Self::single(path, before, Some(after)))
+    }
+
+    /// `from` is copied over `to`
+    pub fn copy(from: (&str, &ConfinedPath), to: (&str, &ConfinedPath)) -> McpResult<Self> {
+        let source = Self::source(from)?;
+        Ok(Self::single(to.0, FileState::read(to.1)?, Some(source)))
+    }
+
+    /// `from` is moved over `to`
+    pub fn rename(from: (&str, &ConfinedPath), to: (&str, &ConfinedPath)) -> McpResult<Self> {
+        let source = Self::source(from)?;
+        Ok(Self {
+            changes: vec![
+                PreviewChange { path: from.0.to_string(), before: Some(source.clone()), after: None },
+                PreviewChange { path: to.0.to_string(), before: FileState::read(to.1)?, after: Some(source) },
+            ],
+        })
+    }
+
+    /// Hash of the paths and of their contents before and after
+    pub fn hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        for change in &self.changes {
+            hasher.update(change.path.as_bytes());
+            hasher.update([0u8]);
+            for state in [&change.before, &change.after] {
+                hasher.update(state.as_ref().map(FileState::sha256).unwrap_or_default().as_bytes());
+                hasher.update([0u8]);
+            }
+        }
+        hex::encode(hasher.finalize())
+    }
+
+    fn single(path: &str, before: Option<FileState>, after: Option<FileState>) -> Self {
+        Self { changes: vec![PreviewChange { path: path.to_string(), before, after }] }
+    }
+
+    fn source((path, target): (&str, &ConfinedPath)) -> McpResult<FileState> {
+        FileState::read(target)?.ok_or_else(|| McpError::NotFound(format!("File not found: {}", path)))
+    }
+}
@@ -0,0 +1,385 @@
+//! Declarative rule-based policy language
+//!
+//! Rules are loaded from a JSON file (by default `~/.mcp/policy.json`) and
+//! evaluated in order; the first matching rule decides. A rule matches when
+//! every condition it specifies matches the action:
+//!
+//! ```json
+//! {
+//!   "rules": [
+//!     { "id": "cargo-test", "effect": "allow", "service": "command",
+//!       "argv": ["cargo", "test"], "cwd": "~/projects/*" },
+//!     { "id": "cargo-publish", "effect": "ask", "argv": ["cargo", "publish"] },
+//!     { "id": "npm-publish", "effect": "deny", "argv": ["npm", "publish"],
+//!       "reason": "Publishing packages is not allowed" }
+//!   ]
+//! }
+//! ```
+//!
+//! `service` and `operation` accept a pattern or a list of patterns. `argv`
+//! patterns match the leading tokens of the command line, one pattern per
+//! token; `**` matches all remaining tokens. The program is matched by its
+//! name without any directory, and for tools with subcommands (`git`, `npm`,
+//! `cargo`, ...) global flags before the subcommand are skipped, so
+//! `["npm", "publish"]` also matches `/usr/bin/npm --registry x publish`.
+//! `command` takes command patterns like `git push --force` instead, which
+//! match wherever the flags and arguments appear on the command line.
+//!
+//! `paths` requires every affected path to match one of the globs, and `cwd`
+//! matches the working directory. Both are matched against the resolved path,
+//! with `..` and symlinks followed, and `*` in them does not match `/`.
+//! Patterns are globs unless prefixed with `re:`, in which case they are
+//! regular expressions. A leading `~` expands to the home directory.
+//!
+//! Command rules may also set `"snapshot": true` or `false` to decide whether
+//! the working directory is snapshotted before a matching command runs;
+//! otherwise the command's predicted effects decide. `"offline": true` runs
+//! matching commands without network access (Linux only).
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::command_match::{CommandPattern, ParsedCommand};
+use crate::error::{McpError, McpResult};
+use crate::policy::PolicyDecision;
+
+/// What a rule does when it matches
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleEffect {
+    Allow,
+    Ask,
+    Deny,
+}
+
+/// A single or repeated pattern in a rule file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl OneOrMany {
+    pub(crate) fn into_vec(self) -> Vec<String> {
+        match self {
+            OneOrMany::One(s) => vec![s],
+            OneOrMany::Many(v) => v,
+        }
+    }
+}
+
+/// A rule as written in the rule file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyRule {
+    /// Identifier recorded in the audit log when this rule matches
+    pub id: String,
+    pub effect: RuleEffect,
+    /// Message shown to the user when the rule asks or denies
+    #[serde(default)]
+    pub reason: Option<String>,
+    #[serde(default)]
+    pub service: Option<OneOrMany>,
+    #[serde(default)]
+    pub operation: Option<OneOrMany>,
+    #[serde(default)]
+    pub argv: Option<Vec<String>>,
+    #[serde(default)]
+    pub command: Option<OneOrMany>,
+    #[serde(default)]
+    pub paths: Option<Vec<String>>,
+    #[serde(default)]
+    pub cwd: Option<String>,
+    /// Snapshot the working directory before a matching command runs
+    #[serde(default)]
+    pub snapshot: Option<bool>,
+    /// Run a matching command without network access
+    #[serde(default)]
+    pub offline: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct RuleFile {
+    rules: Vec<PolicyRule>,
+}
+
+/// A compiled glob or regular expression
+#[derive(Debug, Clone)]
+enum Pattern {
+    Any,
+    Glob(glob::Pattern),
+    Regex(Regex),
+}
+
+impl Pattern {
+    fn compile(source: &str, rule_id: &str) -> McpResult<Self> {
+        if source == "**" {
+            return Ok(Pattern::Any);
+        }
+
+        if let Some(re) = source.strip_prefix("re:") {
+            return Regex::new(re).map(Pattern::Regex).map_err(|e| {
+                McpError::ConfigError(format!("Rule '{}': invalid regex '{}': {}", rule_id, re, e))
+            });
+        }
+
+        glob::Pattern::new(&expand_home(source)).map(Pattern::Glob).map_err(|e| {
+            McpError::ConfigError(format!("Rule '{}': invalid glob '{}': {}", rule_id, source, e))
+        })
+    }
+
+    fn matches(&self, value: &str) -> bool {
+        match self {
+            Pattern::Any => true,
+            Pattern::Glob(glob) => glob.matches(value),
+            Pattern::Regex(re) => re.is_match(value),
+        }
+    }
+
+    /// Match a path; unlike in command arguments, `*` does not match `/`
+    fn matches_path(&self, path: &Path) -> bool {
+        let options = glob::MatchOptions { require_literal_separator: true, ..Default::default() };
+        match self {
+            Pattern::Glob(glob) => glob.matches_path_with(path, options),
+            _ => self.matches(&path.to_string_lossy()),
+        }
+    }
+}
+
+fn expand_home(pattern: &str) -> String {
+    if pattern == "~" || pattern.starts_with("~/") {
+        if let Some(home) = dirs::home_dir() {
+            return format!("{}{}", home.display(), &pattern[1..]);
+        }
+    }
+    pattern.to_string()
+}
+
+#[derive(Debug, Clone)]
+struct CompiledRule {
+    id: String,
+    effect: RuleEffect,
+    reason: Option<String>,
+    service: Vec<Pattern>,
+    operation: Vec<Pattern>,
+    argv: Option<Vec<Pattern>>,
+    command: Vec<CommandPattern>,
+    paths: Option<Vec<Pattern>>,
+    cwd: Option<Pattern>,
+    snapshot: Option<bool>,
+    offline: bool,
+}
+
+impl CompiledRule {
+    fn compile(rule: PolicyRule) -> McpResult<Self> {
+        let id = rule.id;
+        let compile_all = |patterns: Vec<String>| -> McpResult<Vec<Pattern>> {
+            patterns.iter().map(|p| Pattern::compile(p, &id)).collect()
+        };
+
+        Ok(Self {
+            service: compile_all(rule.service.map(OneOrMany::into_vec).unwrap_or_default())?,
+            operation: compile_all(rule.operation.map(OneOrMany::into_vec).unwrap_or_default())?,
+            argv: rule.argv.map(compile_all).transpose()?,
+            command: rule.command.map(OneOrMany::into_vec).unwrap_or_default().iter()
+                .map(|p| CommandPattern::parse(p).ok_or_else(|| {
+                    McpError::ConfigError(format!("Rule '{}': empty command pattern", id))
+                }))
+                .collect::<McpResult<_>>()?,
+            paths: rule.paths.map(compile_all).transpose()?,
+            cwd: rule.cwd.map(|c| Pattern::compile(&c, &id)).transpose()?,
+            effect: rule.effect,
+            reason: rule.reason,
+            snapshot: rule.snapshot,
+            offline: rule.offline,
+            id,
+        })
+    }
+
+    fn matches(&self, input: &RuleInput) -> bool {
+        if !self.service.is_empty() && !self.service.iter().any(|p| p.matches(input.service)) {
+            return false;
+        }
+
+        if !self.operation.is_empty() && !self.operation.iter().any(|p| p.matches(input.operation)) {
+            return false;
+        }
+
+        if let Some(argv) = &self.argv {
+            if !Self::argv_matches(argv, input.argv) {
+                return false;
+            }
+        }
+
+        if !self.command.is_empty() {
+            let Some((program, args)) = input.argv.split_first() else {
+                return false;
+            };
+            let parsed = ParsedCommand::parse(program, args);
+            if !self.command.iter().any(|p| p.matches(&parsed)) {
+                return false;
+            }
+        }
+
+        if let Some(globs) = &self.paths {
+            if input.paths.is_empty()
+                || !input.paths.iter().all(|path| globs.iter().any(|g| g.matches_path(path)))
+            {
+                return false;
+            }
+        }
+
+        if let Some(cwd) = &self.cwd {
+            match input.cwd {
+                Some(dir) if cwd.matches_path(dir) => {}
+                _ => return false,
+            }
+        }
+
+        true
+    }
+
+    fn argv_matches(patterns: &[Pattern], argv: &[String]) -> bool {
+        let argv = match argv.split_first() {
+            Some((command, args)) => ParsedCommand::normalize_argv(command, args),
+            None => Vec::new(),
+        };
+        for (i, pattern) in patterns.iter().enumerate() {
+            if let Pattern::Any = pattern {
+                return true;
+            }
+            match argv.get(i) {
+                Some(token) if pattern.matches(token) => {}
+                _ => return false,
+            }
+        }
+        true
+    }
+
+    fn decision(&self, input: &RuleInput) -> PolicyDecision {
+        let reason = || match &self.reason {
+            Some(reason) => reason.clone(),
+            None => format!("{} (rule '{}')", input.describe(), self.id),
+        };
+
+        match self.effect {
+            RuleEffect::Allow => PolicyDecision::Allow,
+            RuleEffect::Ask => PolicyDecision::RequireApproval(reason()),
+            RuleEffect::Deny => PolicyDecision::Deny(reason()),
+        }
+    }
+}
+
+/// The action a rule set is evaluated against
+#[derive(Debug, Clone, Copy)]
+pub struct RuleInput<'a> {
+    pub service: &'a str,
+    pub operation: &'a str,
+    pub argv: &'a [String],
+    pub paths: &'a [PathBuf],
+    pub cwd: Option<&'a Path>,
+}
+
+impl RuleInput<'_> {
+    /// Human-readable summary used when a rule has no explicit reason
+    pub fn describe(&self) -> String {
+        if !self.argv.is_empty() {
+            return format!("Execute command: {}", self.argv.join(" "));
+        }
+
+        let paths: Vec<String> = self.paths.iter().map(|p| p.display().to_string()).collect();
+        if paths.is_empty() {
+            format!("{} {}", self.service, self.operation)
+        } else {
+            format!("{} {}: {}", self.service, self.operation, paths.join(", "))
+        }
+    }
+}
+
+/// The first rule that matched an action
+#[derive(Debug, Clone)]
+pub struct RuleMatch {
+    pub rule_id: String,
+    pub decision: PolicyDecision,
+    /// Whether the rule asks for a snapshot before the action, if it says
+    pub snapshot: Option<bool>,
+    /// Whether the rule only allows the action without network access
+    pub offline: bool,
+}
+
+/// An ordered list of compiled rules
+#[derive(Debug, Clone, Default)]
+pub struct RuleSet {
+    rules: Vec<CompiledRule>,
+}
+
+/// Default rules applied after any user-defined rules
+const BUILTIN_RULES: &str = r#"{
+  "rules": [
+    { "id": "builtin:git-read", "effect": "allow", "service": "git",
+      "operation": ["status", "log", "diff", "branch"] },
+    { "id": "builtin:git-destructive", "effect": "deny", "service": "command",
+      "command": ["git push --force", "git push --mirror", "git push +*", "git reset --hard"],
+      "reason": "Dangerous git operation is blocked by default" },
+    { "id": "builtin:git-write", "effect": "ask", "service": "git",
+      "operation": ["commit", "create_branch", "push", "pull", "checkout", "merge"] },
+    { "id": "builtin:file-read", "effect": "allow", "service": "file", "operation": "read" },
+    { "id": "builtin:file-write", "effect": "ask", "service": "file",
+      "operation": ["write", "create", "delete"] },
+    { "id": "builtin:snapshot-create", "effect": "allow", "service": "snapshot",
+      "operation": ["create", "list"] },
+    { "id": "builtin:snapshot-modify", "effect": "ask", "service": "snapshot",
+      "operation": ["restore", "delete"] }
+  ]
+}"#;
+
+impl RuleSet {
+    /// Parse a rule set from JSON
+    pub fn from_json(content: &str) -> McpResult<Self> {
+        let file: RuleFile = serde_json::from_str(content)
+            .map_err(|e| McpError::ConfigError(format!("Invalid policy rules: {}", e)))?;
+
+        let rules = file.rules.into_iter()
+            .map(CompiledRule::compile)
+            .collect::<McpResult<Vec<_>>>()?;
+
+        Ok(Self { rules })
+    }
+
+    /// Load a rule set from a file, returning an empty set if it does not exist
+    pub fn load(path: &Path) -> McpResult<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| McpError::ConfigError(e.to_string()))?;
+        Self::from_json(&content)
+    }
+
+    /// The default rules shipped with the server
+    pub fn builtin() -> Self {
+        Self::from_json(BUILTIN_RULES).expect("built-in policy rules are valid")
+    }
+
+    /// Find the first rule that matches the input
+    pub fn evaluate(&self, input: &RuleInput) -> Option<RuleMatch> {
+        self.rules.iter()
+            .find(|rule| rule.matches(input))
+            .map(|rule| RuleMatch {
+                rule_id: rule.id.clone(),
+                decision: rule.decision(input),
+                snapshot: rule.snapshot,
+                offline: rule.offline,
+            })
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+}
@@ -1,9 +1,20 @@
 //! Sandbox execution environment for safe command execution
 
-use std::collections::HashMap;
-use std::path::Path;
-use std::process::{Command, Output, Stdio};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc;
+use serde::{Deserialize, Serialize};
 use crate::error::{McpError, McpResult};
+use crate::secrets::SecretScrubber;
+
+/// Variables kept by default when the environment is cleared
+const INHERITED_ENV: &[&str] = &["PATH", "HOME", "USER", "LANG", "LC_ALL", "TERM", "TMPDIR"];
 
 /// Sandbox configuration for command execution
 #[derive(Debug, Clone)]
@@ -20,11 +31,44 @@ pub struct SandboxConfig {
     pub capture_stderr: bool,
     /// Resource limits
     pub limits: ResourceLimits,
+    /// Run the command in its own process group
+    pub process_group: bool,
+    /// Prevent the command from gaining privileges through setuid binaries (Linux)
+    pub no_new_privs: bool,
+    /// Start from a minimal environment instead of inheriting the server's
+    pub clear_env: bool,
+    /// Server variables kept when the environment is cleared
+    pub inherit_env: Vec<String>,
+    /// Run the command without network access, in a new network namespace
+    /// with only loopback (Linux)
+    pub isolate_network: bool,
+    /// Attach the command to a pseudo-terminal of this size (Linux); its
+    /// stdout and stderr are then both read from the terminal
+    pub pty: Option<TerminalSize>,
+    /// Most bytes of each stream kept in the returned output (0 = unlimited);
+    /// past it, only the first and last halves are kept
+    pub max_output_bytes: usize,
+    /// Directory the full output is written to, as `stdout` and `stderr`
+    /// files, once a stream exceeds `max_output_bytes`
+    pub spill_dir: Option<PathBuf>,
+    /// Scrubs secrets from spilled output as a whole, before the returned
+    /// output is capped from it
+    pub scrubber: Option<Arc<SecretScrubber>>,
+    /// Binary to run instead of looking the command up on `PATH`
+    pub program: Option<PathBuf>,
+}
+
+/// Size of a pseudo-terminal, in character cells
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TerminalSize {
+    pub rows: u16,
+    pub cols: u16,
 }
 
-#[derive(Debug, Clone, Default)]
+/// Limits applied with setrlimit before the command starts (Linux)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ResourceLimits {
-    /// Max memory in bytes (0 = unlimited)
+    /// Max address space in bytes (0 = unlimited)
     pub max_memory: u64,
     /// Max CPU time in seconds (0 = unlimited)
     pub max_cpu_time: u64,
@@ -41,39 +85,337 @@ impl Default for SandboxConfig {
             capture_stdout: true,
             capture_stderr: true,
             limits: ResourceLimits::default(),
+            process_group: false,
+            no_new_privs: false,
+            clear_env: false,
+            inherit_env: INHERITED_ENV.iter().map(|s| s.to_string()).collect(),
+            isolate_network: false,
+            pty: None,
+            max_output_bytes: 0,
+            spill_dir: None,
+            scrubber: None,
+            program: None,
+        }
+    }
+}
+
+impl SandboxConfig {
+    /// Isolate the command: own process group, no new privileges, a minimal
+    /// environment, and the given resource limits
+    pub fn isolated(limits: ResourceLimits) -> Self {
+        Self {
+            limits,
+            process_group: true,
+            no_new_privs: true,
+            clear_env: true,
+            ..Default::default()
         }
     }
 }
 
+/// A piece of output read from a running command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputChunk {
+    Stdout(Vec<u8>),
+    Stderr(Vec<u8>),
+}
+
+/// Input for an interactive command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandInput {
+    /// Bytes to write to the command's stdin
+    Data(Vec<u8>),
+    /// New terminal size; ignored unless the command runs in a PTY
+    Resize(TerminalSize),
+}
+
 /// Execute a command in a sandboxed environment
 pub struct SandboxExecutor;
 
 impl SandboxExecutor {
-    /// Execute a command with the given configuration
-    pub fn execute(
+    /// Execute a command with the given configuration.
+    ///
+    /// The command is killed when `timeout_secs` elapses; if it runs in its
+    /// own process group, the whole group is killed so no descendants linger.
+    pub async fn execute(
+        command: &str,
+        args: &[String],
+        config: &SandboxConfig,
+    ) -> McpResult<SandboxOutput> {
+        Self::execute_streaming(command, args, config, None).await
+    }
+
+    /// Execute a command, sending output chunks to `output` as they are read.
+    ///
+    /// Chunks are sent in the order they were read from the two pipes and
+    /// are not kept, so the returned `stdout` and `stderr` are empty. If
+    /// the receiver is dropped, the command is killed as if it had timed out,
+    /// but `timed_out` is not set.
+    pub async fn execute_streaming(
+        command: &str,
+        args: &[String],
+        config: &SandboxConfig,
+        output: Option<mpsc::Sender<OutputChunk>>,
+    ) -> McpResult<SandboxOutput> {
+        Self::run_child(command, args, config, None, output).await
+    }
+
+    /// Execute a command like `execute_streaming`, writing whatever arrives
+    /// on `input` to its stdin. Stdin is closed once `input` is closed; in a
+    /// PTY, end-of-file (Ctrl-D) is sent instead.
+    pub async fn execute_interactive(
+        command: &str,
+        args: &[String],
+        config: &SandboxConfig,
+        input: mpsc::Receiver<CommandInput>,
+        output: mpsc::Sender<OutputChunk>,
+    ) -> McpResult<SandboxOutput> {
+        Self::run_child(command, args, config, Some(input), Some(output)).await
+    }
+
+    async fn run_child(
         command: &str,
         args: &[String],
         config: &SandboxConfig,
+        input: Option<mpsc::Receiver<CommandInput>>,
+        output: Option<mpsc::Sender<OutputChunk>>,
     ) -> McpResult<SandboxOutput> {
-        let mut cmd = Command::new(command);
+        let mut cmd = tokio::process::Command::from(Self::build_command(command, args, config));
+        cmd.kill_on_drop(true);
+        if input.is_some() {
+            cmd.stdin(Stdio::piped());
+        }
+
+        #[cfg(target_os = "linux")]
+        let terminal = match config.pty {
+            Some(size) => Some(pty::attach(&mut cmd, size)?),
+            None => None,
+        };
+        #[cfg(not(target_os = "linux"))]
+        if config.pty.is_some() {
+            return Err(McpError::CommandError("PTY mode is only supported on Linux".to_string()));
+        }
+        #[cfg(not(target_os = "linux"))]
+        if config.isolate_network {
+            return Err(McpError::Unsupported("Network isolation is only supported on Linux".to_string()));
+        }
+
+        let started = Instant::now();
+        let mut child = cmd.spawn().map_err(|e| {
+            #[cfg(target_os = "linux")]
+            if config.isolate_network && netns::unsupported(&e) {
+                return McpError::Unsupported(format!(
+                    "Network isolation is not available on this system: {} (are unprivileged user namespaces disabled?)",
+                    e
+                ));
+            }
+            McpError::CommandError(format!("Failed to execute command: {}", e))
+        })?;
+        // Drop our copies of the terminal's slave side, so reads from the
+        // master end once the command and its descendants are gone
+        drop(cmd);
+
+        let mut stdin: Option<Box<dyn AsyncWrite + Send + Unpin>> =
+            child.stdin.take().map(|p| Box::new(p) as _);
+        let mut stdout_pipe: Option<Box<dyn AsyncRead + Send + Unpin>> =
+            child.stdout.take().map(|p| Box::new(p) as _);
+        let mut stderr_pipe = child.stderr.take();
+
+        #[cfg(target_os = "linux")]
+        let terminal = match terminal {
+            Some(master) => {
+                stdin = Some(Box::new(master.clone()));
+                stdout_pipe = Some(Box::new(master.clone()));
+                Some(master)
+            }
+            None => None,
+        };
+
+        // Stdin is fed from its own task so a child that stops reading
+        // cannot stall the output loop below
+        let stdin_writer = match (input, stdin) {
+            (Some(mut input), Some(mut stdin)) => {
+                #[cfg(target_os = "linux")]
+                let terminal = terminal.clone();
+                Some(tokio::spawn(async move {
+                    while let Some(message) = input.recv().await {
+                        match message {
+                            CommandInput::Data(data) => {
+                                if stdin.write_all(&data).await.is_err() || stdin.flush().await.is_err() {
+                                    return;
+                                }
+                            }
+                            #[cfg(target_os = "linux")]
+                            CommandInput::Resize(size) => {
+                                if let Some(terminal) = &terminal {
+                                    let _ = terminal.resize(size);
+                                }
+                            }
+                            #[cfg(not(target_os = "linux"))]
+                            CommandInput::Resize(_) => {}
+                        }
+                    }
+                    // A terminal stays open for output, so signal end of input instead
+                    #[cfg(target_os = "linux")]
+                    if terminal.is_some() {
+                        let _ = stdin.write_all(&[pty::EOF]).await;
+                    }
+                }))
+            }
+            _ => None,
+        };
+        let mut stdout = CapturedStream::new("stdout", config.max_output_bytes, output.is_none());
+        let mut stderr = CapturedStream::new("stderr", config.max_output_bytes, output.is_none());
+        let mut stdout_buf = [0u8; 8192];
+        let mut stderr_buf = [0u8; 8192];
+
+        let deadline = tokio::time::sleep(Duration::from_secs(config.timeout_secs.max(1)));
+        // Descendants that outlive the command keep the pipes open; once it
+        // exits, they get a moment to finish before reading stops
+        let drain = tokio::time::sleep(Duration::MAX);
+        tokio::pin!(deadline, drain);
+
+        let mut status = None;
+        let mut timed_out = false;
+        let mut receiver_gone = false;
+
+        loop {
+            if status.is_some() && stdout_pipe.is_none() && stderr_pipe.is_none() {
+                break;
+            }
+
+            let chunk = tokio::select! {
+                read = read_chunk(&mut stdout_pipe, &mut stdout_buf), if stdout_pipe.is_some() => {
+                    match read {
+                        Some(n) => {
+                            stdout.push(&stdout_buf[..n], config.spill_dir.as_deref());
+                            Some(OutputChunk::Stdout(stdout_buf[..n].to_vec()))
+                        }
+                        None => {
+                            stdout_pipe = None;
+                            None
+                        }
+                    }
+                }
+                read = read_chunk(&mut stderr_pipe, &mut stderr_buf), if stderr_pipe.is_some() => {
+                    match read {
+                        Some(n) => {
+                            stderr.push(&stderr_buf[..n], config.spill_dir.as_deref());
+                            Some(OutputChunk::Stderr(stderr_buf[..n].to_vec()))
+                        }
+                        None => {
+                            stderr_pipe = None;
+                            None
+                        }
+                    }
+                }
+                exited = child.wait(), if status.is_none() => {
+                    status = Some(exited?);
+                    drain.as_mut().reset(tokio::time::Instant::now() + Duration::from_secs(2));
+                    None
+                }
+                _ = &mut deadline, if status.is_none() && !timed_out => {
+                    timed_out = true;
+                    Self::kill(&mut child, config.process_group);
+                    None
+                }
+                _ = receiver_closed(&output), if status.is_none() && !receiver_gone => {
+                    receiver_gone = true;
+                    Self::kill(&mut child, config.process_group);
+                    None
+                }
+                _ = &mut drain, if status.is_some() => break,
+            };
+
+            if let (Some(chunk), Some(sender)) = (chunk, &output) {
+                if !receiver_gone && sender.send(chunk).await.is_err() {
+                    receiver_gone = true;
+                    Self::kill(&mut child, config.process_group);
+                }
+            }
+        }
+
+        if let Some(writer) = stdin_writer {
+            writer.abort();
+        }
+
+        let status = match status {
+            Some(status) => status,
+            None => child.wait().await?,
+        };
+
+        // Once either stream is spilled, the other goes alongside it so the
+        // artifact holds the whole output
+        let spilled = match &config.spill_dir {
+            Some(dir) if stdout.capped || stderr.capped => {
+                let scrubber = config.scrubber.as_deref();
+                stdout.spill_to(dir, scrubber) & stderr.spill_to(dir, scrubber)
+            }
+            _ => false,
+        };
+
+        Ok(SandboxOutput::new(status, stdout, stderr, spilled, timed_out, started.elapsed()))
+    }
+
+    /// Kill the command, and its whole process group if it has one
+    fn kill(child: &mut tokio::process::Child, process_group: bool) {
+        #[cfg(unix)]
+        if let (true, Some(pid)) = (process_group, child.id()) {
+            // The child leads its own group, so its pid is the group id
+            unsafe {
+                libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+            }
+            return;
+        }
+
+        #[cfg(not(unix))]
+        let _ = process_group;
+        let _ = child.start_kill();
+    }
+
+    fn build_command(command: &str, args: &[String], config: &SandboxConfig) -> Command {
+        let mut cmd = match &config.program {
+            Some(program) => Command::new(program),
+            None => Command::new(command),
+        };
         cmd.args(args);
+        cmd.stdin(Stdio::null());
 
         if let Some(cwd) = &config.cwd {
             cmd.current_dir(cwd);
         }
 
+        if config.clear_env {
+            cmd.env_clear();
+            for key in &config.inherit_env {
+                if let Ok(value) = std::env::var(key) {
+                    cmd.env(key, value);
+                }
+            }
+        }
+
         for (key, value) in &config.env {
             cmd.env(key, value);
         }
 
-        if config.capture_stdout {
-            cmd.stdout(Stdio::piped());
+        // A PTY session leader gets its own process group from setsid instead
+        #[cfg(unix)]
+        if config.process_group && config.pty.is_none() {
+            use std::os::unix::process::CommandExt;
+            cmd.process_group(0);
         }
 
-        if config.capture_stderr {
-            cmd.stderr(Stdio::piped());
+        #[cfg(target_os = "linux")]
+        Self::apply_limits(&mut cmd, config);
+
+        #[cfg(target_os = "linux")]
+        if config.isolate_network {
+            netns::isolate(&mut cmd);
         }
 
+        cmd.stdout(if config.capture_stdout { Stdio::piped() } else { Stdio::null() });
+        cmd.stderr(if config.capture_stderr { Stdio::piped() } else { Stdio::null() });
+
         // On Windows, we can use Job Objects for resource limiting
         // For now, we'll implement basic execution
         #[cfg(target_os = "windows")]
@@ -81,59 +423,57 @@ impl SandboxExecutor {
             // TODO: Implement Windows Job Object sandboxing
         }
 
-        let output = cmd.output()
-            .map_err(|e| McpError::CommandError(format!("Failed to execute command: {}", e)))?;
-
-        Ok(SandboxOutput::from_output(output))
+        cmd
     }
 
-    /// Predict the effects of a command without executing it (dry-run)
-    pub fn predict_effects(command: &str, args: &[String], cwd: Option<&Path>) -> Vec<String> {
-        let mut effects = Vec::new();
-        let full_cmd = format!("{} {}", command, args.join(" "));
+    /// Apply resource limits and no-new-privs in the child before exec
+    #[cfg(target_os = "linux")]
+    fn apply_limits(cmd: &mut Command, config: &SandboxConfig) {
+        use std::os::unix::process::CommandExt;
 
-        match command {
-            "npm" | "pnpm" | "yarn" => {
-                if args.first().map(|s| s.as_str()) == Some("install") {
-                    effects.push("Will create/update node_modules folder".to_string());
-                    effects.push("May update package-lock.json or yarn.lock".to_string());
-                } else if args.first().map(|s| s.as_str()) == Some("run") {
-                    effects.push(format!("Will run npm script: {}", args.get(1).unwrap_or(&"".to_string())));
-                }
-            }
-            "git" => {
-                match args.first().map(|s| s.as_str()) {
-                    Some("commit") => effects.push("Will create a new git commit".to_string()),
-                    Some("push") => effects.push("Will push commits to remote repository".to_string()),
-                    Some("pull") => effects.push("Will fetch and merge changes from remote".to_string()),
-                    Some("checkout") => effects.push("Will switch branches or restore files".to_string()),
-                    _ => {}
-                }
-            }
-            "cargo" => {
-                if args.first().map(|s| s.as_str()) == Some("build") {
-                    effects.push("Will compile Rust project".to_string());
-                    effects.push("Will create/update target directory".to_string());
+        let ResourceLimits { max_memory, max_cpu_time, max_file_size } = config.limits;
+        let no_new_privs = config.no_new_privs;
+        if max_memory == 0 && max_cpu_time == 0 && max_file_size == 0 && !no_new_privs {
+            return;
+        }
+
+        // (resource, soft, hard); exceeding the soft CPU limit sends SIGXCPU,
+        // the hard limit one second later sends SIGKILL
+        let limits = [
+            (libc::RLIMIT_AS, max_memory, max_memory),
+            (libc::RLIMIT_CPU, max_cpu_time, max_cpu_time.saturating_add(1)),
+            (libc::RLIMIT_FSIZE, max_file_size, max_file_size),
+        ];
+
+        // Only async-signal-safe calls are allowed between fork and exec
+        unsafe {
+            cmd.pre_exec(move || {
+                for (resource, soft, hard) in limits {
+                    if soft == 0 {
+                        continue;
+                    }
+                    let mut current = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+                    if libc::getrlimit(resource, &mut current) != 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                    // An unprivileged process can only lower its hard limit
+                    let hard = (hard as libc::rlim_t).min(current.rlim_max);
+                    let limit = libc::rlimit {
+                        rlim_cur: (soft as libc::rlim_t).min(hard),
+                        rlim_max: hard,
+                    };
+                    if libc::setrlimit(resource, &limit) != 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
                 }
-            }
-            "docker" => {
-                match args.first().map(|s| s.as_str()) {
-                    Some("build") => effects.push("Will build a Docker image".to_string()),
-                    Some("run") => effects.push("Will start a Docker container".to_string()),
-                    Some("stop") => effects.push("Will stop running container(s)".to_string()),
-                    _ => {}
+
+                if no_new_privs && libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                    return Err(std::io::Error::last_os_error());
                 }
-            }
-            _ => {
-                effects.push(format!("Will execute: {}", full_cmd));
-            }
-        }
 
-        if let Some(dir) = cwd {
-            effects.push(format!("Working directory: {}", dir.display()));
+                Ok(())
+            });
         }
-
-        effects
     }
 }
 
@@ -143,15 +483,416 @@ pub struct SandboxOutput {
     pub stdout: String,
     pub stderr: String,
     pub success: bool,
+    /// Signal that terminated the process, if any (e.g. SIGXCPU, SIGKILL)
+    pub signal: Option<i32>,
+    /// Whether the command was killed because it exceeded its timeout
+    pub timed_out: bool,
+    /// Wall-clock time the command ran for
+    pub duration: Duration,
+    /// Size of the command's whole stdout, after scrubbing if it was spilled
+    pub stdout_bytes: u64,
+    /// Size of the command's whole stderr, after scrubbing if it was spilled
+    pub stderr_bytes: u64,
+    /// Whether `stdout` or `stderr` lost bytes to `max_output_bytes`
+    pub truncated: bool,
+    /// Whether the full output was written to `spill_dir`
+    pub spilled: bool,
 }
 
 impl SandboxOutput {
-    fn from_output(output: Output) -> Self {
+    fn new(
+        status: ExitStatus,
+        stdout: CapturedStream,
+        stderr: CapturedStream,
+        spilled: bool,
+        timed_out: bool,
+        duration: Duration,
+    ) -> Self {
+        #[cfg(unix)]
+        let signal = std::os::unix::process::ExitStatusExt::signal(&status);
+        #[cfg(not(unix))]
+        let signal = None;
+
         Self {
-            exit_code: output.status.code().unwrap_or(-1),
-            stdout: String::from_utf8_lossy(&output.stdout).to_string(),
-            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
-            success: output.status.success(),
+            exit_code: status.code().unwrap_or(-1),
+            success: status.success() && !timed_out,
+            signal,
+            timed_out,
+            duration,
+            stdout_bytes: stdout.total,
+            stderr_bytes: stderr.total,
+            truncated: stdout.capped || stderr.capped,
+            spilled,
+            stdout: stdout.into_text(),
+            stderr: stderr.into_text(),
         }
     }
 }
+
+/// One stream of a command's output. Past the cap, only its head and tail
+/// are kept, and the rest goes to a spill file if there is one.
+struct CapturedStream {
+    name: &'static str,
+    limit: usize,
+    /// Whether the output is kept at all, rather than only counted
+    collect: bool,
+    head: Vec<u8>,
+    tail: VecDeque<u8>,
+    total: u64,
+    capped: bool,
+    spill: Option<BufWriter<fs::File>>,
+}
+
+impl CapturedStream {
+    fn new(name: &'static str, limit: usize, collect: bool) -> Self {
+        Self {
+            name,
+            limit,
+            collect,
+            head: Vec::new(),
+            tail: VecDeque::new(),
+            total: 0,
+            capped: false,
+            spill: None,
+        }
+    }
+
+    fn push(&mut self, data: &[u8], spill_dir: Option<&Path>) {
+        self.total += data.len() as u64;
+        if !self.collect {
+            return;
+        }
+
+        if !self.capped {
+            if self.limit == 0 || self.head.len() + data.len() <= self.limit {
+                self.head.extend_from_slice(data);
+                return;
+            }
+            // Nothing has been dropped yet, so the spill file can start
+            // from the beginning
+            self.capped = true;
+            self.spill = spill_dir.and_then(|dir| self.open_spill(dir));
+            if self.head.len() > self.limit / 2 {
+                let rest = self.head.split_off(self.limit / 2);
+                self.tail.extend(rest);
+            }
+        }
+
+        if let Some(file) = &mut self.spill {
+            if let Err(e) = file.write_all(data) {
+                tracing::warn!("Failed to spill command {}: {}", self.name, e);
+                self.spill = None;
+            }
+        }
+        // The head is only short of its half on the read that hit the cap
+        let room = (self.limit / 2).saturating_sub(self.head.len()).min(data.len());
+        self.head.extend_from_slice(&data[..room]);
+        self.tail.extend(&data[room..]);
+        let tail_limit = self.limit - self.limit / 2;
+        if self.tail.len() > tail_limit {
+            self.tail.drain(..self.tail.len() - tail_limit);
+        }
+    }
+
+    /// Start the spill file with what has been kept so far
+    fn open_spill(&self, dir: &Path) -> Option<BufWriter<fs::File>> {
+        match self.create_spill(dir) {
+            Ok(file) => Some(file),
+            Err(e) => {
+                tracing::warn!("Failed to spill command {} to {}: {}", self.name, dir.display(), e);
+                None
+            }
+        }
+    }
+
+    /// Create the spill file, readable only by the server's user since it
+    /// holds the output before secrets are scrubbed. It is renamed to the
+    /// stream's name once finished, so a partial spill is never read back.
+    fn create_spill(&self, dir: &Path) -> std::io::Result<BufWriter<fs::File>> {
+        fs::create_dir_all(dir)?;
+        let mut file = BufWriter::new(create_private(&self.raw_spill_path(dir))?);
+        file.write_all(&self.head)?;
+        Ok(file)
+    }
+
+    fn raw_spill_path(&self, dir: &Path) -> PathBuf {
+        dir.join(format!("{}.raw", self.name))
+    }
+
+    /// Finish the stream's spill file, writing it now if the stream was
+    /// never capped. Returns whether the file holds the whole stream.
+    fn spill_to(&mut self, dir: &Path, scrubber: Option<&SecretScrubber>) -> bool {
+        let result = match self.spill.take() {
+            Some(mut file) => file.flush(),
+            None if self.capped => return false,
+            None => self.create_spill(dir).and_then(|mut file| file.flush()),
+        };
+        let result = result.and_then(|()| match scrubber {
+            Some(scrubber) => self.scrub_spill(dir, scrubber),
+            None => fs::rename(self.raw_spill_path(dir), dir.join(self.name)),
+        });
+        if let Err(e) = &result {
+            tracing::warn!("Failed to spill command {} to {}: {}", self.name, dir.display(), e);
+        }
+        result.is_ok()
+    }
+
+    /// Scrub the whole spilled stream at once, so secrets split across reads
+    /// or slices are caught, and keep the head and tail of the scrubbed
+    /// stream so the returned output matches the spill file
+    fn scrub_spill(&mut self, dir: &Path, scrubber: &SecretScrubber) -> std::io::Result<()> {
+        let raw_path = self.raw_spill_path(dir);
+        let raw = fs::read(&raw_path)?;
+        let scrubbed = scrubber.scrub(&raw);
+        let mut file = create_private(&dir.join(self.name))?;
+        file.write_all(&scrubbed)?;
+        fs::remove_file(&raw_path)?;
+
+        self.total = scrubbed.len() as u64;
+        self.capped = self.limit != 0 && scrubbed.len() > self.limit;
+        if self.capped {
+            let tail_start = scrubbed.len() - (self.limit - self.limit / 2);
+            self.head = scrubbed[..self.limit / 2].to_vec();
+            self.tail = scrubbed[tail_start..].iter().copied().collect();
+        } else {
+            self.head = scrubbed.into_owned();
+            self.tail.clear();
+        }
+        Ok(())
+    }
+
+    /// The kept output, with a marker where bytes were left out
+    fn into_text(self) -> String {
+        let mut bytes = self.head;
+        if self.capped {
+            let omitted = self.total - bytes.len() as u64 - self.tail.len() as u64;
+            bytes.extend_from_slice(format!("\n[... {} bytes omitted ...]\n", omitted).as_bytes());
+            bytes.extend(self.tail);
+        }
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+}
+
+/// Create a new file readable only by the server's user
+fn create_private(path: &Path) -> std::io::Result<fs::File> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)
+}
+
+/// Resolve once the receiver of streamed output has gone away
+async fn receiver_closed(output: &Option<mpsc::Sender<OutputChunk>>) {
+    match output {
+        Some(sender) => sender.closed().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Read the next chunk from a pipe, returning `None` at end of output
+async fn read_chunk<R: AsyncRead + Unpin>(pipe: &mut Option<R>, buf: &mut [u8]) -> Option<usize> {
+    match pipe.as_mut()?.read(buf).await {
+        Ok(0) | Err(_) => None,
+        Ok(n) => Some(n),
+    }
+}
+
+/// Pseudo-terminal support: the command runs as the leader of a new session
+/// whose controlling terminal is the slave side, and the server reads and
+/// writes the master side
+#[cfg(target_os = "linux")]
+mod pty {
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::pin::Pin;
+    use std::process::Stdio;
+    use std::sync::Arc;
+    use std::task::{ready, Context, Poll};
+    use tokio::io::unix::AsyncFd;
+    use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+    use super::TerminalSize;
+    use crate::error::{McpError, McpResult};
+
+    /// End-of-file character (Ctrl-D) in the terminal's default settings
+    pub const EOF: u8 = 0x04;
+
+    /// The master side of a pseudo-terminal
+    #[derive(Clone)]
+    pub struct Master(Arc<AsyncFd<OwnedFd>>);
+
+    fn winsize(size: TerminalSize) -> libc::winsize {
+        libc::winsize { ws_row: size.rows, ws_col: size.cols, ws_xpixel: 0, ws_ypixel: 0 }
+    }
+
+    fn check(result: libc::c_int) -> io::Result<libc::c_int> {
+        if result < 0 { Err(io::Error::last_os_error()) } else { Ok(result) }
+    }
+
+    /// Open a terminal of the given size and make it the command's stdio and
+    /// controlling terminal
+    pub fn attach(cmd: &mut tokio::process::Command, size: TerminalSize) -> McpResult<Master> {
+        let pty_error = |e: io::Error| McpError::CommandError(format!("Failed to open a PTY: {}", e));
+
+        let (master, slave) = unsafe {
+            let mut master = -1;
+            let mut slave = -1;
+            let ws = winsize(size);
+            check(libc::openpty(&mut master, &mut slave, std::ptr::null_mut(), std::ptr::null(), &ws))
+                .map_err(pty_error)?;
+            (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave))
+        };
+
+        // Neither side may leak into the command beyond its stdio, and the
+        // master is polled without blocking
+        unsafe {
+            check(libc::fcntl(master.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC)).map_err(pty_error)?;
+            check(libc::fcntl(slave.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC)).map_err(pty_error)?;
+            let flags = check(libc::fcntl(master.as_raw_fd(), libc::F_GETFL)).map_err(pty_error)?;
+            check(libc::fcntl(master.as_raw_fd(), libc::F_SETFL, flags | libc::O_NONBLOCK)).map_err(pty_error)?;
+        }
+
+        cmd.stdin(Stdio::from(slave.try_clone().map_err(pty_error)?));
+        cmd.stdout(Stdio::from(slave.try_clone().map_err(pty_error)?));
+        cmd.stderr(Stdio::from(slave));
+
+        // Stdio is already in place when this runs, so fd 0 is the slave
+        unsafe {
+            cmd.pre_exec(|| {
+                check(libc::setsid())?;
+                check(libc::ioctl(0, libc::TIOCSCTTY, 0))?;
+                Ok(())
+            });
+        }
+
+        Ok(Master(Arc::new(AsyncFd::new(master).map_err(pty_error)?)))
+    }
+
+    impl Master {
+        /// Resize the terminal; the kernel notifies the command with SIGWINCH
+        pub fn resize(&self, size: TerminalSize) -> io::Result<()> {
+            let ws = winsize(size);
+            check(unsafe { libc::ioctl(self.0.as_raw_fd(), libc::TIOCSWINSZ, &ws) })?;
+            Ok(())
+        }
+    }
+
+    impl AsyncRead for Master {
+        fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+            loop {
+                let mut guard = ready!(self.0.poll_read_ready(cx))?;
+                let unfilled = buf.initialize_unfilled();
+                let read = guard.try_io(|fd| {
+                    let n = unsafe {
+                        libc::read(fd.as_raw_fd(), unfilled.as_mut_ptr().cast(), unfilled.len())
+                    };
+                    if n < 0 { Err(io::Error::last_os_error()) } else { Ok(n as usize) }
+                });
+                match read {
+                    Ok(Ok(n)) => {
+                        buf.advance(n);
+                        return Poll::Ready(Ok(()));
+                    }
+                    // EIO once the last process holding the slave side exits
+                    Ok(Err(e)) => return Poll::Ready(Err(e)),
+                    Err(_would_block) => continue,
+                }
+            }
+        }
+    }
+
+    impl AsyncWrite for Master {
+        fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, data: &[u8]) -> Poll<io::Result<usize>> {
+            loop {
+                let mut guard = ready!(self.0.poll_write_ready(cx))?;
+                let written = guard.try_io(|fd| {
+                    let n = unsafe { libc::write(fd.as_raw_fd(), data.as_ptr().cast(), data.len()) };
+                    if n < 0 { Err(io::Error::last_os_error()) } else { Ok(n as usize) }
+                });
+                match written {
+                    Ok(result) => return Poll::Ready(result),
+                    Err(_would_block) => continue,
+                }
+            }
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+}
+
+/// Network isolation: the command runs in a new network namespace that only
+/// has a loopback interface. Unless the server runs as root, a new user
+/// namespace mapping the server's own user is created first, which needs
+/// unprivileged user namespaces to be enabled.
+#[cfg(target_os = "linux")]
+mod netns {
+    use std::ffi::CStr;
+    use std::io;
+    use std::os::unix::process::CommandExt;
+    use std::process::Command;
+
+    fn check(result: libc::c_int) -> io::Result<libc::c_int> {
+        if result < 0 { Err(io::Error::last_os_error()) } else { Ok(result) }
+    }
+
+    /// Move the command into new namespaces before it starts
+    pub fn isolate(cmd: &mut Command) {
+        let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+        // Formatted here, since the child may not allocate between fork and exec
+        let uid_map = format!("{} {} 1", uid, uid);
+        let gid_map = format!("{} {} 1", gid, gid);
+
+        unsafe {
+            cmd.pre_exec(move || {
+                if uid == 0 {
+                    check(libc::unshare(libc::CLONE_NEWNET))?;
+                } else {
+                    check(libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNET))?;
+                    write_file(c"/proc/self/setgroups", b"deny")?;
+                    write_file(c"/proc/self/uid_map", uid_map.as_bytes())?;
+                    write_file(c"/proc/self/gid_map", gid_map.as_bytes())?;
+                }
+                loopback_up()
+            });
+        }
+    }
+
+    /// Whether a failure to start the command means the namespaces could not
+    /// be created on this system
+    pub fn unsupported(error: &io::Error) -> bool {
+        matches!(
+            error.raw_os_error(),
+            Some(libc::EPERM | libc::EINVAL | libc::ENOSPC | libc::EUSERS | libc::ENOSYS)
+        )
+    }
+
+    fn write_file(path: &CStr, data: &[u8]) -> io::Result<()> {
+        let fd = check(unsafe { libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC) })?;
+        let written = unsafe { libc::write(fd, data.as_ptr().cast(), data.len()) };
+        unsafe { libc::close(fd) };
+        if written < 0 { Err(io::Error::last_os_error()) } else { Ok(()) }
+    }
+
+    /// Bring up the new namespace's loopback interface, which starts out down
+    fn loopback_up() -> io::Result<()> {
+        let socket = check(unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) })?;
+        let result = unsafe {
+            let mut request: libc::ifreq = std::mem::zeroed();
+            request.ifr_name[0] = b'l' as libc::c_char;
+            request.ifr_name[1] = b'o' as libc::c_char;
+            check(libc::ioctl(socket, libc::SIOCGIFFLAGS as _, &mut request)).and_then(|_| {
+                request.ifr_ifru.ifru_flags |= libc::IFF_UP as libc::c_short;
+                check(libc::ioctl(socket, libc::SIOCSIFFLAGS as _, &request))
+            })
+        };
+        unsafe { libc::close(socket) };
+        result.map(|_| ())
+    }
+}
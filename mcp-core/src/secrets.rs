@@ -0,0 +1,123 @@
+//! Scrubbing of secrets from command output before it is returned or audited
+//!
+//! Output is matched against patterns for well-known credential formats,
+//! configured patterns, and the values of secret-looking variables in the
+//! server's own environment. Streamed output is scrubbed chunk by chunk, so
+//! a secret split across two reads can get through; output kept as an
+//! artifact is scrubbed as a whole before it is stored.
+
+use regex::bytes::Regex;
+use std::borrow::Cow;
+
+use crate::error::{McpError, McpResult};
+use crate::sandbox::OutputChunk;
+
+/// What a secret is replaced with
+const REDACTED: &[u8] = b"[REDACTED]";
+
+/// Shortest environment value treated as a secret, so short values like
+/// `1` or `true` are not scrubbed everywhere
+const MIN_SECRET_LEN: usize = 8;
+
+/// Credential formats scrubbed by default. Where a pattern has a `secret`
+/// group, only that part of the match is redacted.
+const BUILTIN_PATTERNS: &[&str] = &[
+    // AWS access key ids
+    r"\b(?:AKIA|ASIA)[0-9A-Z]{16}\b",
+    // GitHub tokens
+    r"\bgh[pousr]_[A-Za-z0-9]{36,}\b",
+    r"\bgithub_pat_[A-Za-z0-9_]{22,}\b",
+    // OpenAI and Anthropic style API keys
+    r"\bsk-[A-Za-z0-9_-]{20,}",
+    // Slack tokens
+    r"\bxox[abposr]-[A-Za-z0-9-]{10,}",
+    // Google API keys
+    r"\bAIza[0-9A-Za-z_-]{35}\b",
+    // JSON web tokens
+    r"\beyJ[A-Za-z0-9_-]{10,}\.[A-Za-z0-9_-]{10,}\.[A-Za-z0-9_-]{10,}",
+    // Private key blocks
+    r"(?s)-----BEGIN [A-Z ]*PRIVATE KEY-----.*?-----END [A-Z ]*PRIVATE KEY-----",
+    // Authorization headers
+    r"(?i)\bbearer\s+(?P<secret>[A-Za-z0-9._~+/-]{16,}=*)",
+    // Assignments like `password=...` or `"api_key": "..."`
+    r#"(?i)\b(?:password|passwd|secret|api[_-]?key|access[_-]?token|auth[_-]?token)["']?\s*[:=]\s*["']?(?P<secret>[^\s"']{6,})"#,
+];
+
+/// Names of server environment variables whose values are scrubbed
+const SECRET_ENV_NAMES: &str = r"(?i)token|secret|password|passwd|api_?key|access_?key|private_?key|credential";
+
+/// Replaces secrets in command output with `[REDACTED]`
+#[derive(Debug, Clone)]
+pub struct SecretScrubber {
+    patterns: Vec<Regex>,
+}
+
+impl SecretScrubber {
+    /// The built-in patterns, plus the values of secret-looking variables in
+    /// the server's environment
+    pub fn builtin() -> Self {
+        let mut patterns: Vec<Regex> = BUILTIN_PATTERNS.iter()
+            .map(|p| Regex::new(p).expect("built-in secret patterns are valid"))
+            .collect();
+
+        let names = regex::Regex::new(SECRET_ENV_NAMES).expect("secret variable pattern is valid");
+        for (name, value) in std::env::vars() {
+            if names.is_match(&name) && value.len() >= MIN_SECRET_LEN {
+                patterns.push(Regex::new(&regex::escape(&value)).expect("escaped value is a valid pattern"));
+            }
+        }
+
+        Self { patterns }
+    }
+
+    /// Also scrub matches of the given regular expressions
+    pub fn with_patterns(mut self, patterns: &[String]) -> McpResult<Self> {
+        for pattern in patterns {
+            let regex = Regex::new(pattern).map_err(|e| {
+                McpError::ConfigError(format!("Invalid secret pattern '{}': {}", pattern, e))
+            })?;
+            self.patterns.push(regex);
+        }
+        Ok(self)
+    }
+
+    /// Replace every secret in `data`
+    pub fn scrub<'a>(&self, data: &'a [u8]) -> Cow<'a, [u8]> {
+        let mut data = Cow::Borrowed(data);
+        for pattern in &self.patterns {
+            if !pattern.is_match(&data) {
+                continue;
+            }
+            let mut scrubbed = Vec::with_capacity(data.len());
+            let mut last = 0;
+            for captures in pattern.captures_iter(&data) {
+                let secret = captures.name("secret").or_else(|| captures.get(0)).unwrap();
+                scrubbed.extend_from_slice(&data[last..secret.start()]);
+                scrubbed.extend_from_slice(REDACTED);
+                last = secret.end();
+            }
+            scrubbed.extend_from_slice(&data[last..]);
+            data = Cow::Owned(scrubbed);
+        }
+        data
+    }
+
+    /// Replace every secret in `text`
+    pub fn scrub_str(&self, text: &str) -> String {
+        String::from_utf8_lossy(&self.scrub(text.as_bytes())).into_owned()
+    }
+
+    /// Replace every secret in a chunk of streamed output
+    pub fn scrub_chunk(&self, chunk: OutputChunk) -> OutputChunk {
+        match chunk {
+            OutputChunk::Stdout(data) => OutputChunk::Stdout(self.scrub(&data).into_owned()),
+            OutputChunk::Stderr(data) => OutputChunk::Stderr(self.scrub(&data).into_owned()),
+        }
+    }
+}
+
+impl Default for SecretScrubber {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
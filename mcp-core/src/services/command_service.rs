@@ -1,21 +1,51 @@
 //! Command service implementation with dry-run and sandbox support
 
 use std::sync::Arc;
-use std::path::PathBuf;
-use tokio::sync::RwLock;
-use tonic::{Request, Response, Status};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, RwLock};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status, Streaming};
 
+use crate::approval::ApprovalAction;
+use crate::artifacts::ArtifactStore;
 use crate::audit::{AuditLogger, AuditEntry};
 use crate::config::Config;
-use crate::policy::{PolicyEngine, PolicyDecision};
-use crate::sandbox::{SandboxExecutor, SandboxConfig};
+use crate::effects::{self, Effect, EffectRegistry};
+use crate::error::{McpError, McpResult};
+use crate::jobs::{self, JobInfo as Job, JobManager, JobOutput as JobChunk};
+use crate::policy::{Authorization, PolicyDecision, PolicyEngine, PolicyVerdict};
+use crate::sandbox::{self, CommandInput, OutputChunk, SandboxConfig, SandboxExecutor, SandboxOutput};
+use crate::secrets::SecretScrubber;
+use crate::simulate::{self, Workspace};
+use crate::snapshot::SnapshotManager;
 
 pub use crate::command_proto::*;
 
+/// How often `RunStream` reports progress while a command is running
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Timeout for commands that do not set one
+const DEFAULT_TIMEOUT_SECS: u64 = 300;
+
+/// Bytes of an interactive session kept in its audit entry
+const TRANSCRIPT_LIMIT: usize = 64 * 1024;
+
+/// Events buffered per stream before the command is slowed down
+const STREAM_BUFFER: usize = 64;
+
+/// Largest working directory `simulate` will copy
+const SIMULATE_COPY_LIMIT: u64 = 256 * 1024 * 1024;
+
 pub struct CommandServiceImpl {
     config: Arc<RwLock<Config>>,
     audit: Arc<AuditLogger>,
     policy: Arc<PolicyEngine>,
+    jobs: Arc<JobManager>,
+    predictors: Arc<EffectRegistry>,
+    snapshots: Option<Arc<SnapshotManager>>,
+    scrubber: Arc<SecretScrubber>,
+    artifacts: Option<Arc<ArtifactStore>>,
 }
 
 impl CommandServiceImpl {
@@ -24,7 +54,581 @@ impl CommandServiceImpl {
         audit: Arc<AuditLogger>,
         policy: Arc<PolicyEngine>,
     ) -> Self {
-        Self { config, audit, policy }
+        let jobs = Arc::new(JobManager::new(audit.clone()));
+        let predictors = Arc::new(EffectRegistry::builtin());
+        let scrubber = Arc::new(SecretScrubber::builtin());
+        Self { config, audit, policy, jobs, predictors, snapshots: None, scrubber, artifacts: None }
+    }
+
+    /// Keep the full output of commands whose output is truncated
+    pub fn with_artifacts(mut self, artifacts: Arc<ArtifactStore>) -> Self {
+        self.artifacts = Some(artifacts);
+        self
+    }
+
+    /// Scrub command output with the given scrubber
+    pub fn with_scrubber(mut self, scrubber: SecretScrubber) -> Self {
+        self.scrubber = Arc::new(scrubber);
+        self
+    }
+
+    /// Snapshot the working directory before running commands that change files
+    pub fn with_snapshots(mut self, snapshots: Arc<SnapshotManager>) -> Self {
+        self.snapshots = Some(snapshots);
+        self
+    }
+
+    /// Use the given dry-run effect predictors
+    pub fn with_predictors(mut self, predictors: EffectRegistry) -> Self {
+        self.predictors = Arc::new(predictors);
+        self
+    }
+
+    /// Use a shared job manager, so the server can stop its jobs at shutdown
+    pub fn with_jobs(mut self, jobs: Arc<JobManager>) -> Self {
+        self.jobs = jobs;
+        self
+    }
+
+    /// Check and enforce the policy for running a command right away
+    async fn check_and_authorize(&self, req: &RunCommandRequest, cwd: Option<&Path>) -> Result<Authorization, Status> {
+        let verdict = self.check(req, cwd).await?;
+        if let PolicyDecision::Deny(reason) = &verdict.decision {
+            return Err(Status::permission_denied(reason.clone()));
+        }
+        let snapshot_rule = verdict.snapshot;
+        let mut authorization = self.authorize(req, verdict).await?;
+        authorization.snapshot_id = self.pre_command_snapshot(req, snapshot_rule, cwd).await?;
+        Ok(authorization)
+    }
+
+    /// Policy verdict for the command together with the environment it sets
+    async fn check(&self, req: &RunCommandRequest, cwd: Option<&Path>) -> McpResult<PolicyVerdict> {
+        let verdict = self.policy.check_command(&req.command, &req.args, cwd).await?;
+        let env = self.policy.check_env(&req.env).await?;
+        // Only the command's verdict says whether to snapshot or go offline,
+        // and which binary runs
+        Ok(PolicyVerdict {
+            snapshot: verdict.snapshot,
+            offline: verdict.offline,
+            binary: verdict.binary.clone(),
+            ..verdict.and(env)
+        })
+    }
+
+    /// Enforce the policy verdict for actually running the command
+    async fn authorize(&self, req: &RunCommandRequest, verdict: PolicyVerdict) -> Result<Authorization, Status> {
+        match self.policy
+            .enforce(verdict, &req.approval_action(), &req.approval_token)
+            .await
+        {
+            Err(McpError::ApprovalRequired(reason)) => Err(Status::failed_precondition(format!(
+                "Approval required: {}. Use dry_run=true to preview, or provide approval_token.",
+                reason
+            ))),
+            result => Ok(result?),
+        }
+    }
+
+    async fn sandbox_config(
+        &self,
+        req: &RunCommandRequest,
+        cwd: Option<PathBuf>,
+        authorization: &Authorization,
+        default_timeout: u64,
+    ) -> SandboxConfig {
+        // Commands always get their own process group so a timeout can kill the whole tree
+        let (base, inherit_env, max_output_bytes) = {
+            let config = self.config.read().await;
+            let base = if config.sandbox_enabled {
+                SandboxConfig::isolated(config.sandbox_limits.clone())
+            } else {
+                SandboxConfig { process_group: true, ..Default::default() }
+            };
+            (base, config.env_policy.passthrough_for(&req.command), config.max_output_bytes as usize)
+        };
+        // Commands never inherit the server's environment beyond the
+        // passthrough variables; the request's variables were checked by policy
+        SandboxConfig {
+            cwd: cwd.map(|p| p.to_string_lossy().to_string()),
+            env: req.env.clone(),
+            clear_env: true,
+            inherit_env,
+            isolate_network: authorization.offline,
+            program: authorization.binary.clone(),
+            max_output_bytes,
+            timeout_secs: if req.timeout_secs > 0 { req.timeout_secs as u64 } else { default_timeout },
+            ..base
+        }
+    }
+
+    /// Snapshot `cwd` if the command is expected to change files in it. A
+    /// matching policy rule decides; otherwise the predicted effects do,
+    /// ignoring effects on paths excluded from snapshots (like `target`).
+    async fn pre_command_snapshot(
+        &self,
+        req: &RunCommandRequest,
+        rule: Option<bool>,
+        cwd: Option<&Path>,
+    ) -> McpResult<Option<String>> {
+        let (Some(snapshots), Some(cwd)) = (&self.snapshots, cwd) else {
+            return Ok(None);
+        };
+        let ignore = self.config.read().await.snapshot_ignore.clone();
+
+        let mutating = rule.unwrap_or_else(|| {
+            let patterns: Vec<glob::Pattern> = ignore.iter()
+                .filter_map(|p| glob::Pattern::new(p).ok())
+                .collect();
+            let ignored = |path: &str| {
+                Path::new(path).strip_prefix(cwd).is_ok_and(|relative| {
+                    relative.components().any(|c| {
+                        patterns.iter().any(|p| p.matches(&c.as_os_str().to_string_lossy()))
+                    })
+                })
+            };
+            self.predictors.predict(&req.command, &req.args, Some(cwd)).effects.iter()
+                .filter(|e| e.changes_files())
+                .any(|e| e.path.as_deref().is_none_or(|path| !ignored(path)))
+        });
+        if !mutating {
+            return Ok(None);
+        }
+
+        let snapshot = snapshots.create_excluding(&[cwd.to_path_buf()], "pre-command", &ignore)?;
+        Ok(Some(snapshot.id))
+    }
+
+    /// Run the command in a throwaway copy of `cwd` and report what it
+    /// changed there
+    async fn simulate(
+        &self,
+        req: RunCommandRequest,
+        verdict: PolicyVerdict,
+        cwd: Option<PathBuf>,
+    ) -> Result<Response<RunCommandResponse>, Status> {
+        let cwd = cwd.ok_or_else(|| Status::invalid_argument("simulate requires a working directory"))?;
+
+        // The command really runs, only somewhere else, so it needs the same
+        // authorization as a normal execution
+        let authorization = self.authorize(&req, verdict).await?;
+
+        let workspace = Workspace::copy_of(&cwd, SIMULATE_COPY_LIMIT)?;
+        let args: Vec<String> = req.args.iter().map(|a| workspace.rewrite_arg(a)).collect();
+        let mut sandbox_config = self.sandbox_config(&req, Some(workspace.root().to_path_buf()), &authorization, DEFAULT_TIMEOUT_SECS).await;
+        let artifact_id = self.allocate_artifact(&mut sandbox_config);
+
+        let output = SandboxExecutor::execute(&req.command, &args, &sandbox_config).await?;
+        let artifact_id = artifact_id.filter(|_| output.spilled);
+        let changes = workspace.changes()?;
+
+        let command_line = format!("{} {}", req.command, req.args.join(" "));
+        let mut entry = Self::execution_entry(&command_line, &output, &sandbox_config, &authorization, false);
+        entry.action = "simulate".to_string();
+        entry.artifact_id = artifact_id.clone();
+        entry.details = format!(
+            "{}; {} file{} changed in a copy of {}",
+            entry.details,
+            changes.len(),
+            if changes.len() == 1 { "" } else { "s" },
+            cwd.display()
+        );
+        let _ = self.audit.log(entry);
+
+        Ok(Response::new(RunCommandResponse {
+            simulated: true,
+            changes: changes.into_iter().map(FileChange::from).collect(),
+            ..self.executed_response(command_line, &output, artifact_id)
+        }))
+    }
+
+    /// Reserve an artifact for the command's full output, in case it is
+    /// truncated
+    fn allocate_artifact(&self, sandbox_config: &mut SandboxConfig) -> Option<String> {
+        let artifacts = self.artifacts.as_ref()?;
+        if sandbox_config.max_output_bytes == 0 {
+            return None;
+        }
+        let (id, dir) = artifacts.allocate();
+        sandbox_config.spill_dir = Some(dir);
+        sandbox_config.scrubber = Some(self.scrubber.clone());
+        Some(id)
+    }
+
+    /// Response for a command that ran to completion, with its output scrubbed
+    fn executed_response(
+        &self,
+        command_line: String,
+        output: &SandboxOutput,
+        artifact_id: Option<String>,
+    ) -> RunCommandResponse {
+        RunCommandResponse {
+            command_line,
+            exit_code: output.exit_code,
+            stdout: self.scrubber.scrub_str(&output.stdout),
+            stderr: self.scrubber.scrub_str(&output.stderr),
+            success: output.success,
+            timed_out: output.timed_out,
+            signal: output.signal.unwrap_or(0),
+            duration_ms: output.duration.as_millis() as u64,
+            truncated: output.truncated,
+            stdout_bytes: output.stdout_bytes,
+            stderr_bytes: output.stderr_bytes,
+            artifact_id: artifact_id.unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    /// Audit entry recording how a command execution ended
+    fn execution_entry(
+        command_line: &str,
+        output: &SandboxOutput,
+        sandbox_config: &SandboxConfig,
+        authorization: &Authorization,
+        cancelled: bool,
+    ) -> AuditEntry {
+        let mut entry = AuditLogger::create_entry("command", "execute");
+        entry.details = if output.timed_out {
+            format!(
+                "Timed out after {}s: {} (process group killed)",
+                sandbox_config.timeout_secs, command_line
+            )
+        } else if cancelled {
+            format!("Cancelled by client: {}", command_line)
+        } else if let Some(signal) = output.signal {
+            format!("Executed: {} (killed by signal {})", command_line, signal)
+        } else {
+            format!("Executed: {} (exit: {})", command_line, output.exit_code)
+        };
+        if let Some(program) = &sandbox_config.program {
+            entry.details.push_str(&format!(" [binary: {}]", program.display()));
+        }
+        if sandbox_config.isolate_network {
+            entry.details.push_str(" [network isolated]");
+        }
+        if output.truncated {
+            entry.details.push_str(&format!(
+                " [output truncated: {} bytes of stdout, {} bytes of stderr]",
+                output.stdout_bytes, output.stderr_bytes
+            ));
+        }
+        authorization.apply_to(&mut entry);
+        entry.snapshot_id = authorization.snapshot_id.clone();
+        entry.duration_ms = Some(output.duration.as_millis() as u64);
+        entry.result = if output.timed_out {
+            "timed_out"
+        } else if cancelled {
+            "cancelled"
+        } else if output.success {
+            "success"
+        } else {
+            "failed"
+        }.to_string();
+        entry
+    }
+}
+
+/// Sender side of a `RunStream` response, numbering events in order
+struct EventStream {
+    tx: mpsc::Sender<Result<CommandEvent, Status>>,
+    sequence: u64,
+    client_gone: bool,
+}
+
+impl EventStream {
+    async fn send(&mut self, event: command_event::Event) {
+        let event = CommandEvent { sequence: self.sequence, event: Some(event) };
+        self.sequence += 1;
+        if self.tx.send(Ok(event)).await.is_err() {
+            self.client_gone = true;
+        }
+    }
+
+    async fn send_chunk(&mut self, chunk: OutputChunk) {
+        let event = match chunk {
+            OutputChunk::Stdout(data) => command_event::Event::Stdout(data),
+            OutputChunk::Stderr(data) => command_event::Event::Stderr(data),
+        };
+        self.send(event).await;
+    }
+}
+
+/// The client side of a `RunInteractive` call
+struct InteractiveSession {
+    inbound: Streaming<InteractiveInput>,
+    redact_stdin: bool,
+}
+
+/// Run a command and stream its events to `tx`, feeding it the client's
+/// input for interactive sessions. The command is killed if the client goes
+/// away, and the execution is audited once it ends. Secrets are scrubbed from
+/// the output before it is sent or recorded.
+async fn stream_command(
+    req: RunCommandRequest,
+    sandbox_config: SandboxConfig,
+    authorization: Authorization,
+    audit: Arc<AuditLogger>,
+    scrubber: Arc<SecretScrubber>,
+    tx: mpsc::Sender<Result<CommandEvent, Status>>,
+    session: Option<InteractiveSession>,
+) {
+    let (chunk_tx, mut chunk_rx) = mpsc::channel(STREAM_BUFFER);
+    let (mut inbound, mut transcript, mut stdin, stdin_rx) = match session {
+        Some(session) => {
+            let (stdin_tx, stdin_rx) = mpsc::channel(STREAM_BUFFER);
+            (Some(session.inbound), Some(Transcript::new(session.redact_stdin)), Some(stdin_tx), Some(stdin_rx))
+        }
+        None => (None, None, None, None),
+    };
+
+    let execution = async {
+        match stdin_rx {
+            Some(stdin_rx) => SandboxExecutor::execute_interactive(
+                &req.command,
+                &req.args,
+                &sandbox_config,
+                stdin_rx,
+                chunk_tx,
+            ).await,
+            None => SandboxExecutor::execute_streaming(
+                &req.command,
+                &req.args,
+                &sandbox_config,
+                Some(chunk_tx),
+            ).await,
+        }
+    };
+    tokio::pin!(execution);
+
+    let started = Instant::now();
+    let mut heartbeat = tokio::time::interval_at(
+        tokio::time::Instant::now() + HEARTBEAT_INTERVAL,
+        HEARTBEAT_INTERVAL,
+    );
+    let client = tx.clone();
+    let mut stream = EventStream { tx, sequence: 0, client_gone: false };
+
+    let result = loop {
+        // Only take more input once there is room for it, so a command that
+        // stops reading stdin cannot stall its output
+        let stdin_ready = stdin.as_ref().is_none_or(|s| s.capacity() > 0);
+
+        tokio::select! {
+            result = &mut execution => break result,
+            Some(chunk) = chunk_rx.recv() => {
+                let chunk = scrubber.scrub_chunk(chunk);
+                if let Some(transcript) = &mut transcript {
+                    transcript.record_output(&chunk);
+                }
+                stream.send_chunk(chunk).await;
+            }
+            message = next_input(&mut inbound), if inbound.is_some() && stdin_ready => {
+                match message.and_then(|m| m.input) {
+                    Some(interactive_input::Input::Stdin(data)) => {
+                        if let (Some(stdin), Some(transcript)) = (&stdin, &mut transcript) {
+                            transcript.record_stdin(&scrubber.scrub(&data));
+                            let _ = stdin.send(CommandInput::Data(data)).await;
+                        }
+                    }
+                    Some(interactive_input::Input::Resize(size)) => {
+                        if let (Some(stdin), Ok(size)) = (&stdin, terminal_size(&size)) {
+                            let _ = stdin.send(CommandInput::Resize(size)).await;
+                        }
+                    }
+                    Some(interactive_input::Input::CloseStdin(true)) => stdin = None,
+                    Some(_) => {}
+                    // The client finished sending; that ends the command's input too
+                    None => {
+                        inbound = None;
+                        stdin = None;
+                    }
+                }
+            }
+            _ = heartbeat.tick() => {
+                let elapsed_ms = started.elapsed().as_millis() as u64;
+                stream.send(command_event::Event::Heartbeat(CommandHeartbeat { elapsed_ms })).await;
+            }
+            _ = client.closed(), if !stream.client_gone => {
+                stream.client_gone = true;
+            }
+        }
+        // A disconnected client cancels the command
+        if stream.client_gone {
+            chunk_rx.close();
+        }
+    };
+
+    // Forward whatever was read after the last select
+    while let Ok(chunk) = chunk_rx.try_recv() {
+        let chunk = scrubber.scrub_chunk(chunk);
+        if let Some(transcript) = &mut transcript {
+            transcript.record_output(&chunk);
+        }
+        stream.send_chunk(chunk).await;
+    }
+
+    let command_line = format!("{} {}", req.command, req.args.join(" "));
+    match result {
+        Ok(output) => {
+            let mut entry = CommandServiceImpl::execution_entry(
+                &command_line,
+                &output,
+                &sandbox_config,
+                &authorization,
+                stream.client_gone,
+            );
+            if let Some(transcript) = transcript {
+                entry.details = format!("{}\nTranscript:\n{}", entry.details, transcript.finish());
+            }
+            let _ = audit.log(entry);
+
+            stream.send(command_event::Event::Exit(CommandExit {
+                exit_code: output.exit_code,
+                success: output.success,
+                timed_out: output.timed_out,
+                signal: output.signal.unwrap_or(0),
+                duration_ms: output.duration.as_millis() as u64,
+                snapshot_id: authorization.snapshot_id.unwrap_or_default(),
+            })).await;
+        }
+        Err(e) => {
+            let _ = stream.tx.send(Err(e.into())).await;
+        }
+    }
+}
+
+/// Validate a terminal size sent by the client
+fn terminal_size(size: &TerminalSize) -> McpResult<sandbox::TerminalSize> {
+    match (u16::try_from(size.rows), u16::try_from(size.cols)) {
+        (Ok(rows), Ok(cols)) if rows > 0 && cols > 0 => Ok(sandbox::TerminalSize { rows, cols }),
+        _ => Err(McpError::InvalidArgument(format!(
+            "Invalid terminal size {}x{}",
+            size.cols, size.rows
+        ))),
+    }
+}
+
+/// Next message from an interactive client, `None` once it stops sending
+async fn next_input(inbound: &mut Option<Streaming<InteractiveInput>>) -> Option<InteractiveInput> {
+    inbound.as_mut()?.message().await.ok().flatten()
+}
+
+/// Record of an interactive session for the audit log, bounded in size
+struct Transcript {
+    text: String,
+    stream: Option<&'static str>,
+    omitted: usize,
+    redact_stdin: bool,
+}
+
+impl Transcript {
+    fn new(redact_stdin: bool) -> Self {
+        Self { text: String::new(), stream: None, omitted: 0, redact_stdin }
+    }
+
+    fn record_stdin(&mut self, data: &[u8]) {
+        if self.redact_stdin {
+            self.record("stdin", format!("<{} bytes redacted>\n", data.len()).as_bytes());
+        } else {
+            self.record("stdin", data);
+        }
+    }
+
+    fn record_output(&mut self, chunk: &OutputChunk) {
+        match chunk {
+            OutputChunk::Stdout(data) => self.record("stdout", data),
+            OutputChunk::Stderr(data) => self.record("stderr", data),
+        }
+    }
+
+    fn record(&mut self, stream: &'static str, data: &[u8]) {
+        let room = TRANSCRIPT_LIMIT.saturating_sub(self.text.len());
+        let kept = data.len().min(room);
+        self.omitted += data.len() - kept;
+        if kept == 0 {
+            return;
+        }
+        if self.stream != Some(stream) {
+            if !self.text.is_empty() && !self.text.ends_with('\n') {
+                self.text.push('\n');
+            }
+            self.text.push_str(&format!("[{}]\n", stream));
+            self.stream = Some(stream);
+        }
+        self.text.push_str(&String::from_utf8_lossy(&data[..kept]));
+    }
+
+    fn finish(mut self) -> String {
+        if self.omitted > 0 {
+            self.text.push_str(&format!("\n[{} more bytes not recorded]", self.omitted));
+        }
+        self.text
+    }
+}
+
+impl From<Effect> for PredictedEffect {
+    fn from(effect: Effect) -> Self {
+        Self {
+            kind: effect.kind.as_str().to_string(),
+            path: effect.path.unwrap_or_default(),
+            description: effect.description,
+        }
+    }
+}
+
+impl From<simulate::FileChange> for FileChange {
+    fn from(change: simulate::FileChange) -> Self {
+        Self {
+            path: change.path,
+            kind: change.kind.as_str().to_string(),
+            diff: change.diff,
+        }
+    }
+}
+
+impl From<Job> for JobInfo {
+    fn from(job: Job) -> Self {
+        Self {
+            job_id: job.id,
+            command_line: job.command_line,
+            cwd: job.cwd.unwrap_or_default(),
+            state: job.state.as_str().to_string(),
+            exit_code: job.exit_code.unwrap_or(0),
+            signal: job.signal.unwrap_or(0),
+            started_at: job.started_at.to_rfc3339(),
+            duration_ms: job.duration.as_millis() as u64,
+            output_start: job.output_start,
+            output_end: job.output_end,
+            error: job.error.unwrap_or_default(),
+            snapshot_id: job.snapshot_id.unwrap_or_default(),
+        }
+    }
+}
+
+impl From<JobChunk> for JobOutput {
+    fn from(output: JobChunk) -> Self {
+        let data = match output.chunk {
+            OutputChunk::Stdout(data) => job_output::Data::Stdout(data),
+            OutputChunk::Stderr(data) => job_output::Data::Stderr(data),
+        };
+        Self { offset: output.offset, data: Some(data) }
+    }
+}
+
+impl RunCommandRequest {
+    /// The action an approval token for this request must be bound to
+    pub fn approval_action(&self) -> ApprovalAction {
+        // Approving a simulation does not approve running the command for real
+        let operation = if self.simulate { "simulate" } else { "execute" };
+        // Variables come first, as in `NAME=value command args`
+        let mut env: Vec<String> = self.env.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+        env.sort();
+        let mut action = ApprovalAction::new("command", operation)
+            .with_arguments(env.into_iter().chain(std::iter::once(self.command.clone())).chain(self.args.iter().cloned()));
+        if !self.cwd.is_empty() {
+            action = action.with_paths([&self.cwd]);
+        }
+        action
     }
 }
 
@@ -36,85 +640,253 @@ impl command_service_server::CommandService for CommandServiceImpl {
     ) -> Result<Response<RunCommandResponse>, Status> {
         let req = request.into_inner();
 
+        let cwd = if req.cwd.is_empty() { None } else { Some(PathBuf::from(&req.cwd)) };
+
         // Check policy
-        match self.policy.check_command(&req.command, &req.args).await? {
-            PolicyDecision::Deny(reason) => return Err(Status::permission_denied(reason)),
-            PolicyDecision::RequireApproval(reason) => {
-                // If dry_run, we don't need approval
-                if !req.dry_run && req.approval_token.is_empty() {
-                    return Err(Status::failed_precondition(format!(
-                        "Approval required: {}. Use dry_run=true to preview, or provide approval_token.",
-                        reason
-                    )));
-                }
-            }
-            PolicyDecision::Allow => {}
+        let verdict = self.check(&req, cwd.as_deref()).await?;
+        if let PolicyDecision::Deny(reason) = &verdict.decision {
+            return Err(Status::permission_denied(reason.clone()));
         }
 
-        let cwd = if req.cwd.is_empty() { None } else { Some(PathBuf::from(&req.cwd)) };
-
         // Dry-run mode: predict effects without executing
         if req.dry_run {
-            let effects = SandboxExecutor::predict_effects(
-                &req.command,
-                &req.args,
-                cwd.as_deref(),
-            );
+            let prediction = self.predictors.predict(&req.command, &req.args, cwd.as_deref());
+
+            // Past runs of the command are a better guide than the predictor
+            let estimated_time = match effects::historical_duration(&self.audit, &req.command, &req.args)? {
+                Some((duration, runs)) => format!(
+                    "{} (median of {} previous run{})",
+                    effects::format_estimate(duration),
+                    runs,
+                    if runs == 1 { "" } else { "s" }
+                ),
+                None => prediction.estimate.clone().unwrap_or_else(|| "varies".to_string()),
+            };
+
+            let mut predicted_effects: Vec<String> = prediction.effects.iter()
+                .map(|e| e.description.clone())
+                .collect();
+            if let Some(dir) = &cwd {
+                predicted_effects.push(format!("Working directory: {}", dir.display()));
+            }
 
             let command_line = format!("{} {}", req.command, req.args.join(" "));
 
             // Log dry-run
             let mut entry = AuditLogger::create_entry("command", "dry_run");
-            entry.details = format!("Dry-run: {}", command_line);
+            entry.details = format!("Dry-run: {} (risk: {})", command_line, prediction.risk.as_str());
             entry.result = "simulated".to_string();
             let _ = self.audit.log(entry);
 
             return Ok(Response::new(RunCommandResponse {
                 dry_run: true,
                 command_line,
-                predicted_effects: effects,
-                estimated_time: "varies".to_string(),
+                predicted_effects,
+                estimated_time,
                 exit_code: 0,
                 stdout: String::new(),
                 stderr: String::new(),
                 success: true,
+                effects: prediction.effects.into_iter().map(PredictedEffect::from).collect(),
+                risk_level: prediction.risk.as_str().to_string(),
+                ..Default::default()
             }));
         }
 
-        // Validate approval token for actual execution
-        if !req.approval_token.is_empty() && !self.policy.validate_approval(&req.approval_token).await {
-            return Err(Status::permission_denied("Invalid approval token"));
+        if req.simulate {
+            return self.simulate(req, verdict, cwd).await;
         }
 
-        // Execute command in sandbox
-        let sandbox_config = SandboxConfig {
-            cwd: cwd.map(|p| p.to_string_lossy().to_string()),
-            timeout_secs: if req.timeout_secs > 0 { req.timeout_secs as u64 } else { 300 },
-            ..Default::default()
-        };
+        // Dry-run needs no approval; actual execution does
+        let snapshot_rule = verdict.snapshot;
+        let mut authorization = self.authorize(&req, verdict).await?;
+        authorization.snapshot_id = self.pre_command_snapshot(&req, snapshot_rule, cwd.as_deref()).await?;
+        let mut sandbox_config = self.sandbox_config(&req, cwd, &authorization, DEFAULT_TIMEOUT_SECS).await;
+        let artifact_id = self.allocate_artifact(&mut sandbox_config);
 
-        let output = SandboxExecutor::execute(&req.command, &req.args, &sandbox_config)
-            .map_err(|e| Status::internal(e.to_string()))?;
+        let output = SandboxExecutor::execute(&req.command, &req.args, &sandbox_config).await?;
+        let artifact_id = artifact_id.filter(|_| output.spilled);
 
         let command_line = format!("{} {}", req.command, req.args.join(" "));
-
-        // Log execution
-        let mut entry = AuditLogger::create_entry("command", "execute");
-        entry.details = format!("Executed: {} (exit: {})", command_line, output.exit_code);
-        entry.user_approved = !req.approval_token.is_empty();
-        entry.approval_token = if req.approval_token.is_empty() { None } else { Some(req.approval_token) };
-        entry.result = if output.success { "success" } else { "failed" }.to_string();
+        let mut entry = Self::execution_entry(&command_line, &output, &sandbox_config, &authorization, false);
+        entry.artifact_id = artifact_id.clone();
         let _ = self.audit.log(entry);
 
         Ok(Response::new(RunCommandResponse {
-            dry_run: false,
-            command_line,
-            predicted_effects: vec![],
-            estimated_time: String::new(),
-            exit_code: output.exit_code,
-            stdout: output.stdout,
-            stderr: output.stderr,
-            success: output.success,
+            snapshot_id: authorization.snapshot_id.unwrap_or_default(),
+            ..self.executed_response(command_line, &output, artifact_id)
+        }))
+    }
+
+    type RunStreamStream = ReceiverStream<Result<CommandEvent, Status>>;
+
+    async fn run_stream(
+        &self,
+        request: Request<RunCommandRequest>,
+    ) -> Result<Response<Self::RunStreamStream>, Status> {
+        let req = request.into_inner();
+
+        if req.dry_run || req.simulate {
+            return Err(Status::invalid_argument("Use Run with dry_run=true or simulate=true to preview a command"));
+        }
+
+        let cwd = if req.cwd.is_empty() { None } else { Some(PathBuf::from(&req.cwd)) };
+
+        // Check policy once, before anything is started
+        let authorization = self.check_and_authorize(&req, cwd.as_deref()).await?;
+        let sandbox_config = self.sandbox_config(&req, cwd, &authorization, DEFAULT_TIMEOUT_SECS).await;
+
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+        tokio::spawn(stream_command(
+            req,
+            sandbox_config,
+            authorization,
+            self.audit.clone(),
+            self.scrubber.clone(),
+            tx,
+            None,
+        ));
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    type RunInteractiveStream = ReceiverStream<Result<CommandEvent, Status>>;
+
+    async fn run_interactive(
+        &self,
+        request: Request<Streaming<InteractiveInput>>,
+    ) -> Result<Response<Self::RunInteractiveStream>, Status> {
+        let mut inbound = request.into_inner();
+
+        let start = match inbound.message().await? {
+            Some(InteractiveInput { input: Some(interactive_input::Input::Start(start)) }) => start,
+            _ => return Err(Status::invalid_argument("The first message must start the command")),
+        };
+        let req = start.command.ok_or_else(|| Status::invalid_argument("No command given"))?;
+
+        if req.dry_run || req.simulate {
+            return Err(Status::invalid_argument("Use Run with dry_run=true or simulate=true to preview a command"));
+        }
+
+        let cwd = if req.cwd.is_empty() { None } else { Some(PathBuf::from(&req.cwd)) };
+
+        // Check policy once; input sent later is not re-checked
+        let pty = start.pty.as_ref().map(terminal_size).transpose()?;
+        let authorization = self.check_and_authorize(&req, cwd.as_deref()).await?;
+        let sandbox_config = SandboxConfig {
+            pty,
+            ..self.sandbox_config(&req, cwd, &authorization, DEFAULT_TIMEOUT_SECS).await
+        };
+
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+        tokio::spawn(stream_command(
+            req,
+            sandbox_config,
+            authorization,
+            self.audit.clone(),
+            self.scrubber.clone(),
+            tx,
+            Some(InteractiveSession { inbound, redact_stdin: start.redact_stdin }),
+        ));
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn start_job(
+        &self,
+        request: Request<RunCommandRequest>,
+    ) -> Result<Response<JobInfo>, Status> {
+        let req = request.into_inner();
+
+        if req.dry_run || req.simulate {
+            return Err(Status::invalid_argument("Use Run with dry_run=true or simulate=true to preview a command"));
+        }
+
+        let cwd = if req.cwd.is_empty() { None } else { Some(PathBuf::from(&req.cwd)) };
+        let authorization = self.check_and_authorize(&req, cwd.as_deref()).await?;
+        let sandbox_config = self.sandbox_config(&req, cwd, &authorization, jobs::DEFAULT_JOB_TIMEOUT_SECS).await;
+
+        let job = self.jobs.start(&req.command, &req.args, sandbox_config, &authorization)?;
+        Ok(Response::new(job.into()))
+    }
+
+    async fn list_jobs(
+        &self,
+        request: Request<ListJobsRequest>,
+    ) -> Result<Response<ListJobsResponse>, Status> {
+        let req = request.into_inner();
+        let jobs = self.jobs.list(req.include_finished)
+            .into_iter()
+            .map(JobInfo::from)
+            .collect();
+
+        Ok(Response::new(ListJobsResponse { jobs }))
+    }
+
+    type TailJobStream = ReceiverStream<Result<TailJobResponse, Status>>;
+
+    async fn tail_job(
+        &self,
+        request: Request<TailJobRequest>,
+    ) -> Result<Response<Self::TailJobStream>, Status> {
+        let req = request.into_inner();
+        let mut tail = self.jobs.tail(&req.job_id, req.offset)?;
+        let scrubber = self.scrubber.clone();
+
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+        tokio::spawn(async move {
+            loop {
+                // Checked before reading so output written just before exit is not missed
+                let running = tail.running();
+                for output in tail.read() {
+                    let output = JobChunk { chunk: scrubber.scrub_chunk(output.chunk), ..output };
+                    let event = tail_job_response::Event::Output(output.into());
+                    if tx.send(Ok(TailJobResponse { event: Some(event) })).await.is_err() {
+                        return;
+                    }
+                }
+                if !running {
+                    let event = tail_job_response::Event::Finished(tail.info().into());
+                    let _ = tx.send(Ok(TailJobResponse { event: Some(event) })).await;
+                    return;
+                }
+                if !req.follow {
+                    return;
+                }
+                tokio::select! {
+                    _ = tail.changed() => {}
+                    _ = tx.closed() => return,
+                }
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn cancel_job(
+        &self,
+        request: Request<CancelJobRequest>,
+    ) -> Result<Response<JobInfo>, Status> {
+        let req = request.into_inner();
+        let job = self.jobs.cancel(&req.job_id).await?;
+        Ok(Response::new(job.into()))
+    }
+
+    async fn read_output(
+        &self,
+        request: Request<ReadOutputRequest>,
+    ) -> Result<Response<ReadOutputResponse>, Status> {
+        let req = request.into_inner();
+        let artifacts = self.artifacts.as_ref()
+            .ok_or_else(|| Status::not_found(format!("Artifact '{}' not found", req.artifact_id)))?;
+        let read = artifacts.read(&req.artifact_id, &req.stream, req.offset, req.max_bytes)?;
+
+        let next_offset = req.offset + read.data.len() as u64;
+        Ok(Response::new(ReadOutputResponse {
+            data: read.data,
+            next_offset,
+            total_bytes: read.total_bytes,
+            eof: next_offset >= read.total_bytes,
         }))
     }
 
@@ -123,9 +895,25 @@ impl command_service_server::CommandService for CommandServiceImpl {
         _request: Request<ListWhitelistedRequest>,
     ) -> Result<Response<ListWhitelistedResponse>, Status> {
         let config = self.config.read().await;
-        
+        let resolved = config.whitelisted_commands.iter()
+            .map(|command| match config.resolve_command(command) {
+                Ok(binary) => WhitelistedCommand {
+                    command: command.clone(),
+                    path: binary.path.to_string_lossy().to_string(),
+                    sha256: binary.sha256.unwrap_or_default(),
+                    error: String::new(),
+                },
+                Err(e) => WhitelistedCommand {
+                    command: command.clone(),
+                    error: e.to_string(),
+                    ..Default::default()
+                },
+            })
+            .collect();
+
         Ok(Response::new(ListWhitelistedResponse {
             commands: config.whitelisted_commands.clone(),
+            resolved,
         }))
     }
 }
@@ -1,25 +1,41 @@
 //! File service implementation
 
-use std::sync::Arc;
-use std::path::PathBuf;
-use tokio::sync::RwLock;
-use tonic::{Request, Response, Status};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::path::{Path, PathBuf};
+use tokio::sync::{mpsc, OwnedMutexGuard, RwLock};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status, Streaming};
+use std::io::{Read, Seek, SeekFrom, Write};
 use sha2::{Sha256, Digest};
 
-use crate::audit::{AuditLogger, AuditEntry};
-use crate::config::Config;
-use crate::policy::{PolicyEngine, PolicyDecision};
+use crate::approval::ApprovalAction;
+use crate::audit::AuditLogger;
+use crate::config::{Config, PathPermission};
+use crate::confine::{self, ConfinedPath, OpenMode};
+use crate::diff;
+use crate::edits::{self, Replacement};
+use crate::policy::{PolicyEngine, PolicyDecision, PolicyVerdict};
+use crate::preview::{FileState, Preview, PreviewChange};
 use crate::snapshot::SnapshotManager;
-use crate::error::McpError;
+use crate::error::{McpError, McpResult};
 
 // Re-export proto types
 pub use crate::file_proto::*;
 
+/// Chunk size for ReadFileChunks when the request sets none
+const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+/// Largest chunk ReadFileChunks sends
+const MAX_CHUNK_SIZE: usize = 1024 * 1024;
+/// Chunks read ahead of a slow client
+const CHUNK_BUFFER: usize = 4;
+
 pub struct FileServiceImpl {
     config: Arc<RwLock<Config>>,
     audit: Arc<AuditLogger>,
     policy: Arc<PolicyEngine>,
     snapshots: Arc<SnapshotManager>,
+    locks: PathLocks,
 }
 
 impl FileServiceImpl {
@@ -29,7 +45,7 @@ impl FileServiceImpl {
         policy: Arc<PolicyEngine>,
         snapshots: Arc<SnapshotManager>,
     ) -> Self {
-        Self { config, audit, policy, snapshots }
+        Self { config, audit, policy, snapshots, locks: PathLocks::default() }
     }
 
     fn compute_sha256(content: &[u8]) -> String {
@@ -37,6 +53,380 @@ impl FileServiceImpl {
         hasher.update(content);
         hex::encode(hasher.finalize())
     }
+
+    /// Check a verdict before the request's own checks, leaving the approval
+    /// token to be consumed by `enforce` once the change is certain to be
+    /// made. Dry runs need no approval, but denied ones still fail.
+    async fn precheck(
+        &self,
+        verdict: &PolicyVerdict,
+        action: &ApprovalAction,
+        approval_token: &str,
+        dry_run: bool,
+    ) -> McpResult<()> {
+        if dry_run && !matches!(verdict.decision, PolicyDecision::Deny(_)) {
+            return Ok(());
+        }
+        self.policy.precheck(verdict, action, approval_token).await
+    }
+
+    /// Preview the change for a dry run, which is answered with the preview.
+    /// Otherwise, if the request names a preview, check that the change is
+    /// still the one previewed.
+    fn preview<F>(
+        &self,
+        action: &ApprovalAction,
+        dry_run: bool,
+        preview_hash: &str,
+        compute: F,
+    ) -> McpResult<Option<FilePreview>>
+    where
+        F: FnOnce() -> McpResult<Preview>,
+    {
+        if !dry_run && preview_hash.is_empty() {
+            return Ok(None);
+        }
+        let preview = compute()?;
+        let hash = preview.hash();
+
+        if !dry_run {
+            if !hash.eq_ignore_ascii_case(preview_hash.trim()) {
+                return Err(McpError::Conflict(format!(
+                    "The change no longer matches its preview (preview hash is {}, expected {})",
+                    hash, preview_hash
+                )));
+            }
+            return Ok(None);
+        }
+
+        let mut entry = AuditLogger::create_entry("file", "dry_run");
+        entry.details = format!("Dry-run: {} (preview {})", action.describe(), hash);
+        entry.result = "simulated".to_string();
+        let _ = self.audit.log(entry);
+
+        Ok(Some(preview.into()))
+    }
+}
+
+/// Serializes writes to the same file, so that a sha256 precondition still
+/// holds when the write happens. Only this service takes the locks: a change
+/// made by an editor is caught by the check, but can still land between the
+/// check and the write.
+#[derive(Default)]
+struct PathLocks {
+    locks: Mutex<HashMap<PathBuf, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl PathLocks {
+    /// Lock every path, always in the same order so two requests cannot deadlock
+    async fn lock(&self, paths: &[&ConfinedPath]) -> Vec<OwnedMutexGuard<()>> {
+        let mut paths: Vec<&Path> = paths.iter().map(|p| p.path()).collect();
+        paths.sort();
+        paths.dedup();
+
+        let mutexes: Vec<_> = {
+            let mut locks = self.locks.lock().unwrap();
+            // Forget the locks nobody holds or waits for
+            locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+            paths.iter().map(|p| locks.entry(p.to_path_buf()).or_default().clone()).collect()
+        };
+
+        let mut guards = Vec::with_capacity(mutexes.len());
+        for mutex in mutexes {
+            guards.push(mutex.lock_owned().await);
+        }
+        guards
+    }
+}
+
+/// The error for a file whose sha256 is not the one the client expects
+fn changed_since_read(actual: &str, expected: &str) -> McpError {
+    McpError::Conflict(format!(
+        "File has changed since it was read (sha256 is {}, expected {})",
+        actual, expected
+    ))
+}
+
+/// Check that the file a request would overwrite still has the sha256 the
+/// client read. An empty `expected` skips the check.
+fn check_expected_sha256(target: &ConfinedPath, expected: &str) -> McpResult<()> {
+    if expected.is_empty() {
+        return Ok(());
+    }
+
+    let mut file = match target.open(OpenMode::Read) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(McpError::Conflict(format!(
+                "File does not exist (expected sha256 {})",
+                expected
+            )));
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+
+    let actual = hex::encode(hasher.finalize());
+    if actual.eq_ignore_ascii_case(expected) {
+        Ok(())
+    } else {
+        Err(changed_since_read(&actual, expected))
+    }
+}
+
+/// Parse the octal permissions of a new file, like "644" or "0755"
+fn parse_mode(mode: &str) -> McpResult<Option<u32>> {
+    let mode = mode.trim();
+    if mode.is_empty() {
+        return Ok(None);
+    }
+    let digits = mode.strip_prefix("0o").unwrap_or(mode);
+    match u32::from_str_radix(digits, 8) {
+        Ok(bits) if bits <= 0o777 => Ok(Some(bits)),
+        _ => Err(McpError::InvalidArgument(format!(
+            "Invalid mode '{}': expected octal permissions like 644, without setuid, setgid or sticky bits",
+            mode
+        ))),
+    }
+}
+
+/// Writing to an existing file needs write access; a new file needs create access
+fn write_permission(path: &Path) -> PathPermission {
+    if path.symlink_metadata().is_ok() {
+        PathPermission::Write
+    } else {
+        PathPermission::Create
+    }
+}
+
+/// Permission bits of a file, given to a new copy of it
+#[cfg(unix)]
+fn file_mode(metadata: &std::fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+
+    Some(metadata.permissions().mode() & 0o777)
+}
+
+#[cfg(not(unix))]
+fn file_mode(_metadata: &std::fs::Metadata) -> Option<u32> {
+    None
+}
+
+/// The bytes a request writes: `data` if set, else the text in `content`
+fn contents<'a>(content: &'a str, data: &'a [u8]) -> McpResult<&'a [u8]> {
+    match (content.is_empty(), data.is_empty()) {
+        (false, false) => Err(McpError::InvalidArgument(
+            "Set either content or data, not both".to_string(),
+        )),
+        (_, true) => Ok(content.as_bytes()),
+        (true, false) => Ok(data),
+    }
+}
+
+/// Read up to `size` bytes, fewer only at the end of the input
+fn read_chunk(reader: &mut impl Read, size: usize) -> std::io::Result<Vec<u8>> {
+    let mut chunk = Vec::with_capacity(size);
+    reader.take(size as u64).read_to_end(&mut chunk)?;
+    Ok(chunk)
+}
+
+/// Send `file` from its current position in chunks, stopping after `length`
+/// bytes or at the end of the file
+fn send_chunks(
+    file: std::fs::File,
+    mut offset: u64,
+    length: u64,
+    chunk_size: usize,
+    size: u64,
+    tx: mpsc::Sender<Result<ReadFileChunksResponse, Status>>,
+) -> std::io::Result<()> {
+    let mut reader = file.take(length);
+    let mut hasher = Sha256::new();
+    let mut chunk = read_chunk(&mut reader, chunk_size)?;
+    loop {
+        // Read ahead, so the last chunk can say it is the last
+        let next = if chunk.len() < chunk_size { Vec::new() } else { read_chunk(&mut reader, chunk_size)? };
+        let last = next.is_empty();
+        hasher.update(&chunk);
+
+        let response = ReadFileChunksResponse {
+            offset,
+            size,
+            last,
+            sha256: if last { hex::encode(hasher.clone().finalize()) } else { String::new() },
+            data: std::mem::replace(&mut chunk, next),
+        };
+        offset += response.data.len() as u64;
+        if tx.blocking_send(Ok(response)).is_err() || last {
+            return Ok(());
+        }
+    }
+}
+
+/// Bind an action to the bytes it writes, unless a previewed change already
+/// does. The argument is `sha256=<hex digest of the bytes>`.
+fn bind_contents(action: ApprovalAction, bytes: &[u8]) -> ApprovalAction {
+    if action.preview_hash.is_some() {
+        return action;
+    }
+    action.with_arguments([format!("sha256={}", FileServiceImpl::compute_sha256(bytes))])
+}
+
+/// The bytes to bind an approval to: `data` if set, else `content`
+fn approved_bytes<'a>(content: &'a str, data: &'a [u8]) -> &'a [u8] {
+    if data.is_empty() { content.as_bytes() } else { data }
+}
+
+impl ReadFileRequest {
+    /// The action an approval token for this request must be bound to
+    pub fn approval_action(&self) -> ApprovalAction {
+        ApprovalAction::new("file", "read").with_paths([&self.path])
+    }
+}
+
+impl ReadFileChunksRequest {
+    /// The action an approval token for this request must be bound to; the
+    /// same as for a ReadFile request
+    pub fn approval_action(&self) -> ApprovalAction {
+        ApprovalAction::new("file", "read").with_paths([&self.path])
+    }
+}
+
+impl ListDirRequest {
+    /// The action an approval token for this request must be bound to
+    pub fn approval_action(&self) -> ApprovalAction {
+        ApprovalAction::new("file", "list").with_paths([&self.path])
+    }
+}
+
+impl StatRequest {
+    /// The action an approval token for this request must be bound to
+    pub fn approval_action(&self) -> ApprovalAction {
+        ApprovalAction::new("file", "stat").with_paths([&self.path])
+    }
+}
+
+impl CreateFileRequest {
+    /// The action an approval token for this request must be bound to
+    pub fn approval_action(&self) -> ApprovalAction {
+        let mut action = ApprovalAction::new("file", "create")
+            .with_paths([&self.path])
+            .with_preview_hash(&self.preview_hash);
+        if !self.mode.is_empty() {
+            action = action.with_arguments([format!("mode={}", self.mode)]);
+        }
+        bind_contents(action, approved_bytes(&self.content, &self.data))
+    }
+}
+
+impl WriteFileStart {
+    /// The action an approval token for this write must be bound to. Like a
+    /// CreateFile approval it is bound to the contents, here by the declared
+    /// `sha256` (and `size`, if given).
+    pub fn approval_action(&self) -> ApprovalAction {
+        let mut action = ApprovalAction::new("file", "write_chunks")
+            .with_paths([&self.path])
+            .with_preview_hash(&self.preview_hash);
+        if !self.mode.is_empty() {
+            action = action.with_arguments([format!("mode={}", self.mode)]);
+        }
+        if self.size != 0 {
+            action = action.with_arguments([format!("size={}", self.size)]);
+        }
+        if action.preview_hash.is_some() {
+            return action;
+        }
+        action.with_arguments([format!("sha256={}", self.sha256.trim().to_ascii_lowercase())])
+    }
+}
+
+impl AppendFileRequest {
+    /// The action an approval token for this request must be bound to
+    pub fn approval_action(&self) -> ApprovalAction {
+        let action = ApprovalAction::new("file", "append")
+            .with_paths([&self.path])
+            .with_preview_hash(&self.preview_hash);
+        bind_contents(action, approved_bytes(&self.content, &self.data))
+    }
+}
+
+impl ApplyEditsRequest {
+    /// The action an approval token for this request must be bound to
+    pub fn approval_action(&self) -> ApprovalAction {
+        let action = ApprovalAction::new("file", "edit")
+            .with_paths([&self.path])
+            .with_preview_hash(&self.preview_hash);
+        bind_contents(action, &self.serialized_edits())
+    }
+
+    /// The diff, or the search/replace edits with each field length-prefixed
+    fn serialized_edits(&self) -> Vec<u8> {
+        if !self.unified_diff.is_empty() {
+            return [b"diff\0", self.unified_diff.as_bytes()].concat();
+        }
+        let mut bytes = b"edits\0".to_vec();
+        for edit in &self.edits {
+            for field in [&edit.search, &edit.replace] {
+                bytes.extend((field.len() as u64).to_le_bytes());
+                bytes.extend(field.as_bytes());
+            }
+            bytes.push(edit.replace_all as u8);
+        }
+        bytes
+    }
+}
+
+impl From<TextEdit> for Replacement {
+    fn from(edit: TextEdit) -> Self {
+        Replacement { search: edit.search, replace: edit.replace, replace_all: edit.replace_all }
+    }
+}
+
+impl From<Preview> for FilePreview {
+    fn from(preview: Preview) -> Self {
+        Self {
+            preview_hash: preview.hash(),
+            changes: preview.changes.iter().map(FileChange::from).collect(),
+        }
+    }
+}
+
+impl From<&PreviewChange> for FileChange {
+    fn from(change: &PreviewChange) -> Self {
+        let before = change.before.as_ref();
+        let after = change.after.as_ref();
+        Self {
+            path: change.path.clone(),
+            kind: change.kind().as_str().to_string(),
+            diff: change.diff(),
+            binary: change.binary(),
+            old_size: before.map_or(0, |s| s.size()),
+            old_sha256: before.map(|s| s.sha256()).unwrap_or_default(),
+            new_size: after.map_or(0, |s| s.size()),
+            new_sha256: after.map(|s| s.sha256()).unwrap_or_default(),
+        }
+    }
+}
+
+impl MoveFileRequest {
+    /// The action an approval token for this request must be bound to
+    pub fn approval_action(&self) -> ApprovalAction {
+        ApprovalAction::new("file", "move")
+            .with_arguments([&self.from_path, &self.to_path])
+            .with_paths([&self.from_path, &self.to_path])
+            .with_preview_hash(&self.preview_hash)
+    }
+}
+
+impl CopyFileRequest {
+    /// The action an approval token for this request must be bound to
+    pub fn approval_action(&self) -> ApprovalAction {
+        ApprovalAction::new("file", "copy")
+            .with_arguments([&self.from_path, &self.to_path])
+            .with_paths([&self.from_path, &self.to_path])
+            .with_preview_hash(&self.preview_hash)
+    }
 }
 
 #[tonic::async_trait]
@@ -49,24 +439,26 @@ impl file_service_server::FileService for FileServiceImpl {
         let path = PathBuf::from(&req.path);
 
         // Check policy
-        match self.policy.check_file_access(&path, false).await? {
-            PolicyDecision::Deny(reason) => return Err(Status::permission_denied(reason)),
-            _ => {}
-        }
+        let verdict = self.policy.check_file_access(&path, PathPermission::Read).await?;
+        let authorization = self.policy.enforce(verdict, &req.approval_action(), &req.approval_token).await?;
 
-        // Read file
+        // Read file through a handle pinned inside the allowed root
         let config = self.config.read().await;
-        let metadata = std::fs::metadata(&path)
+        let target = config.confinement().resolve(&path)?;
+        let mut file = target.open(OpenMode::Read)
             .map_err(|e| Status::not_found(format!("File not found: {}", e)))?;
+        let metadata = file.metadata()
+            .map_err(|e| Status::internal(format!("Failed to get metadata: {}", e)))?;
 
         if metadata.len() > config.max_file_size {
             return Err(Status::invalid_argument(format!(
-                "File exceeds maximum size of {} bytes",
+                "File exceeds maximum size of {} bytes; use ReadFileChunks to read it in parts",
                 config.max_file_size
             )));
         }
 
-        let content = std::fs::read(&path)
+        let mut content = Vec::with_capacity(metadata.len() as usize);
+        file.read_to_end(&mut content)
             .map_err(|e| Status::internal(format!("Failed to read file: {}", e)))?;
 
         let sha256 = Self::compute_sha256(&content);
@@ -74,65 +466,129 @@ impl file_service_server::FileService for FileServiceImpl {
         // Log action
         let mut entry = AuditLogger::create_entry("file", "read");
         entry.details = format!("Read file: {}", path.display());
+        authorization.apply_to(&mut entry);
         entry.result = "success".to_string();
         let _ = self.audit.log(entry);
 
+        // Text is returned as a string, anything else byte for byte
+        let (content, data, encoding) = match String::from_utf8(content) {
+            Ok(text) => (text, Vec::new(), "utf-8"),
+            Err(e) => (String::new(), e.into_bytes(), "binary"),
+        };
+
         Ok(Response::new(ReadFileResponse {
             path: req.path,
-            content: String::from_utf8_lossy(&content).to_string(),
+            content,
             sha256,
             size: metadata.len(),
+            data,
+            encoding: encoding.to_string(),
         }))
     }
 
+    type ReadFileChunksStream = ReceiverStream<Result<ReadFileChunksResponse, Status>>;
+
+    async fn read_file_chunks(
+        &self,
+        request: Request<ReadFileChunksRequest>,
+    ) -> Result<Response<Self::ReadFileChunksStream>, Status> {
+        let req = request.into_inner();
+        let path = PathBuf::from(&req.path);
+
+        // Check policy
+        let verdict = self.policy.check_file_access(&path, PathPermission::Read).await?;
+        let authorization = self.policy.enforce(verdict, &req.approval_action(), &req.approval_token).await?;
+
+        let target = self.config.read().await.confinement().resolve(&path)?;
+        let mut file = target.open(OpenMode::Read)
+            .map_err(|e| Status::not_found(format!("File not found: {}", e)))?;
+        let size = file.metadata()
+            .map_err(|e| Status::internal(format!("Failed to get metadata: {}", e)))?
+            .len();
+
+        if req.offset > size {
+            return Err(Status::invalid_argument(format!(
+                "Offset {} is past the end of the file ({} bytes)",
+                req.offset, size
+            )));
+        }
+        file.seek(SeekFrom::Start(req.offset))
+            .map_err(|e| Status::internal(format!("Failed to read file: {}", e)))?;
+
+        let length = if req.length == 0 { u64::MAX } else { req.length };
+        let chunk_size = match req.chunk_size as usize {
+            0 => DEFAULT_CHUNK_SIZE,
+            n => n.min(MAX_CHUNK_SIZE),
+        };
+
+        // Log action
+        let mut entry = AuditLogger::create_entry("file", "read");
+        entry.details = format!("Read file in chunks: {} (from offset {})", path.display(), req.offset);
+        authorization.apply_to(&mut entry);
+        entry.result = "success".to_string();
+        let _ = self.audit.log(entry);
+
+        let (tx, rx) = mpsc::channel(CHUNK_BUFFER);
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = send_chunks(file, req.offset, length, chunk_size, size, tx.clone()) {
+                let _ = tx.blocking_send(Err(Status::internal(format!("Failed to read file: {}", e))));
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
     async fn create_file(
         &self,
         request: Request<CreateFileRequest>,
     ) -> Result<Response<CreateFileResponse>, Status> {
         let req = request.into_inner();
         let path = PathBuf::from(&req.path);
+        let mode = parse_mode(&req.mode)?;
+        let contents = contents(&req.content, &req.data)?;
 
         // Check policy
-        match self.policy.check_file_access(&path, true).await? {
-            PolicyDecision::Deny(reason) => return Err(Status::permission_denied(reason)),
-            PolicyDecision::RequireApproval(reason) => {
-                if req.approval_token.is_empty() {
-                    return Err(Status::failed_precondition(format!(
-                        "Approval required: {}",
-                        reason
-                    )));
-                }
-                if !self.policy.validate_approval(&req.approval_token).await {
-                    return Err(Status::permission_denied("Invalid approval token"));
-                }
-            }
-            PolicyDecision::Allow => {}
+        let verdict = self.policy.check_file_access(&path, write_permission(&path)).await?;
+        let action = req.approval_action();
+        self.precheck(&verdict, &action, &req.approval_token, req.dry_run).await?;
+
+        let target = self.config.read().await.confinement().resolve(&path)?;
+        let _guards = self.locks.lock(&[&target]).await;
+        check_expected_sha256(&target, &req.expected_sha256)?;
+
+        if let Some(preview) = self.preview(&action, req.dry_run, &req.preview_hash, || {
+            Preview::write(&req.path, &target, contents)
+        })? {
+            return Ok(Response::new(CreateFileResponse {
+                path: req.path,
+                preview: Some(preview),
+                ..Default::default()
+            }));
         }
 
+        let authorization = self.policy.enforce(verdict, &action, &req.approval_token).await?;
+
         // Create snapshot before modification if file exists
-        let snapshot_id = if path.exists() {
-            Some(self.snapshots.create(&[path.clone()], "pre-create")?.id)
+        let snapshot_id = if target.path().exists() {
+            Some(self.snapshots.create(&[target.path().to_path_buf()], "pre-create")?.id)
         } else {
             None
         };
 
         // Create parent directories if needed
-        if let Some(parent) = path.parent() {
-            std::fs::create_dir_all(parent)
-                .map_err(|e| Status::internal(format!("Failed to create directories: {}", e)))?;
-        }
+        target.create_parents()
+            .map_err(|e| Status::internal(format!("Failed to create directories: {}", e)))?;
 
-        // Write file
-        std::fs::write(&path, &req.content)
+        // Write file; an existing one is replaced atomically
+        confine::replace(&target, mode, |file| file.write_all(contents))
             .map_err(|e| Status::internal(format!("Failed to write file: {}", e)))?;
 
-        let sha256 = Self::compute_sha256(req.content.as_bytes());
+        let sha256 = Self::compute_sha256(contents);
 
         // Log action
         let mut entry = AuditLogger::create_entry("file", "create");
         entry.details = format!("Created file: {}", path.display());
-        entry.user_approved = !req.approval_token.is_empty();
-        entry.approval_token = if req.approval_token.is_empty() { None } else { Some(req.approval_token) };
+        authorization.apply_to(&mut entry);
         entry.snapshot_id = snapshot_id.clone();
         entry.result = "success".to_string();
         let _ = self.audit.log(entry);
@@ -142,6 +598,123 @@ impl file_service_server::FileService for FileServiceImpl {
             path: req.path,
             sha256,
             snapshot_id: snapshot_id.unwrap_or_default(),
+            preview: None,
+        }))
+    }
+
+    async fn write_file_chunks(
+        &self,
+        request: Request<Streaming<WriteFileChunksRequest>>,
+    ) -> Result<Response<WriteFileChunksResponse>, Status> {
+        let mut inbound = request.into_inner();
+
+        let start = match inbound.message().await? {
+            Some(WriteFileChunksRequest { input: Some(write_file_chunks_request::Input::Start(start)) }) => start,
+            _ => return Err(Status::invalid_argument("The first message must start the write")),
+        };
+        let path = PathBuf::from(&start.path);
+        let mode = parse_mode(&start.mode)?;
+        if !start.dry_run && start.preview_hash.is_empty() && start.sha256.is_empty() {
+            return Err(Status::invalid_argument("The start of a write must give the sha256 of the contents"));
+        }
+
+        // Check policy once, before any chunk is received
+        let verdict = self.policy.check_file_access(&path, write_permission(&path)).await?;
+        let action = start.approval_action();
+        self.precheck(&verdict, &action, &start.approval_token, start.dry_run).await?;
+
+        let target = self.config.read().await.confinement().resolve(&path)?;
+        // Fail before the upload if the file has changed already; this is
+        // checked again under the lock once all chunks are in
+        check_expected_sha256(&target, &start.expected_sha256)?;
+
+        // The chunks go to a temporary file, which is dropped if the stream
+        // fails. A dry run only hashes them.
+        let mut replacement = if start.dry_run {
+            None
+        } else {
+            target.create_parents()
+                .map_err(|e| Status::internal(format!("Failed to create directories: {}", e)))?;
+            let replacement = confine::Replacement::begin(&target, mode)
+                .map_err(|e| Status::internal(format!("Failed to write file: {}", e)))?;
+            Some(replacement)
+        };
+        let mut contents = FileState::empty();
+        while let Some(message) = inbound.message().await? {
+            let chunk = match message.input {
+                Some(write_file_chunks_request::Input::Chunk(chunk)) => chunk,
+                _ => return Err(Status::invalid_argument("Only chunks may follow the start of a write")),
+            };
+            if chunk.offset != contents.size() {
+                return Err(Status::invalid_argument(format!(
+                    "Chunk at offset {}, expected offset {}",
+                    chunk.offset, contents.size()
+                )));
+            }
+            if let Some(replacement) = &mut replacement {
+                replacement.file().write_all(&chunk.data)
+                    .map_err(|e| Status::internal(format!("Failed to write file: {}", e)))?;
+            }
+            contents.append(&chunk.data);
+        }
+
+        let (size, sha256) = (contents.size(), contents.sha256());
+        if start.size != 0 && size != start.size {
+            return Err(Status::invalid_argument(format!(
+                "Received {} bytes, expected {}",
+                size, start.size
+            )));
+        }
+        if !start.sha256.is_empty() && !sha256.eq_ignore_ascii_case(start.sha256.trim()) {
+            return Err(Status::invalid_argument(format!(
+                "Received contents with sha256 {}, expected {}",
+                sha256, start.sha256
+            )));
+        }
+
+        // The file is only locked once the contents are complete, so a slow
+        // upload does not hold up other writes to it
+        let _guards = self.locks.lock(&[&target]).await;
+        check_expected_sha256(&target, &start.expected_sha256)?;
+
+        if let Some(preview) = self.preview(&action, start.dry_run, &start.preview_hash, || {
+            Preview::replace(&start.path, &target, contents)
+        })? {
+            return Ok(Response::new(WriteFileChunksResponse {
+                path: start.path,
+                preview: Some(preview),
+                ..Default::default()
+            }));
+        }
+        let authorization = self.policy.enforce(verdict, &action, &start.approval_token).await?;
+
+        let replacement = replacement.expect("only dry runs write no temporary file");
+
+        // Create snapshot before modification if file exists
+        let snapshot_id = if target.path().exists() {
+            Some(self.snapshots.create(&[target.path().to_path_buf()], "pre-write")?.id)
+        } else {
+            None
+        };
+
+        replacement.commit()
+            .map_err(|e| Status::internal(format!("Failed to write file: {}", e)))?;
+
+        // Log action
+        let mut entry = AuditLogger::create_entry("file", "write_chunks");
+        entry.details = format!("Wrote file in chunks: {} ({} bytes)", path.display(), size);
+        authorization.apply_to(&mut entry);
+        entry.snapshot_id = snapshot_id.clone();
+        entry.result = "success".to_string();
+        let _ = self.audit.log(entry);
+
+        Ok(Response::new(WriteFileChunksResponse {
+            success: true,
+            path: start.path,
+            size,
+            sha256,
+            snapshot_id: snapshot_id.unwrap_or_default(),
+            preview: None,
         }))
     }
 
@@ -151,47 +724,50 @@ impl file_service_server::FileService for FileServiceImpl {
     ) -> Result<Response<AppendFileResponse>, Status> {
         let req = request.into_inner();
         let path = PathBuf::from(&req.path);
+        let contents = contents(&req.content, &req.data)?;
 
         // Check policy
-        match self.policy.check_file_access(&path, true).await? {
-            PolicyDecision::Deny(reason) => return Err(Status::permission_denied(reason)),
-            PolicyDecision::RequireApproval(reason) => {
-                if req.approval_token.is_empty() {
-                    return Err(Status::failed_precondition(format!(
-                        "Approval required: {}",
-                        reason
-                    )));
-                }
-            }
-            PolicyDecision::Allow => {}
+        let verdict = self.policy.check_file_access(&path, write_permission(&path)).await?;
+        let action = req.approval_action();
+        self.precheck(&verdict, &action, &req.approval_token, req.dry_run).await?;
+
+        let target = self.config.read().await.confinement().resolve(&path)?;
+        let _guards = self.locks.lock(&[&target]).await;
+        check_expected_sha256(&target, &req.expected_sha256)?;
+
+        if let Some(preview) = self.preview(&action, req.dry_run, &req.preview_hash, || {
+            Preview::append(&req.path, &target, contents)
+        })? {
+            return Ok(Response::new(AppendFileResponse {
+                preview: Some(preview),
+                ..Default::default()
+            }));
         }
 
+        let authorization = self.policy.enforce(verdict, &action, &req.approval_token).await?;
+
         // Create snapshot before modification
-        let snapshot_id = if path.exists() {
-            Some(self.snapshots.create(&[path.clone()], "pre-append")?.id)
+        let snapshot_id = if target.path().exists() {
+            Some(self.snapshots.create(&[target.path().to_path_buf()], "pre-append")?.id)
         } else {
             None
         };
 
         // Append to file
-        use std::fs::OpenOptions;
-        use std::io::Write;
-
-        let mut file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&path)
+        let mut file = target.open(OpenMode::Append)
             .map_err(|e| Status::internal(format!("Failed to open file: {}", e)))?;
 
-        file.write_all(req.content.as_bytes())
+        file.write_all(contents)
+            .and_then(|_| file.sync_all())
             .map_err(|e| Status::internal(format!("Failed to append to file: {}", e)))?;
 
-        let metadata = std::fs::metadata(&path)
+        let metadata = file.metadata()
             .map_err(|e| Status::internal(format!("Failed to get metadata: {}", e)))?;
 
         // Log action
         let mut entry = AuditLogger::create_entry("file", "append");
         entry.details = format!("Appended to file: {}", path.display());
+        authorization.apply_to(&mut entry);
         entry.snapshot_id = snapshot_id.clone();
         entry.result = "success".to_string();
         let _ = self.audit.log(entry);
@@ -200,6 +776,100 @@ impl file_service_server::FileService for FileServiceImpl {
             success: true,
             new_size: metadata.len(),
             snapshot_id: snapshot_id.unwrap_or_default(),
+            preview: None,
+        }))
+    }
+
+    async fn apply_edits(
+        &self,
+        request: Request<ApplyEditsRequest>,
+    ) -> Result<Response<ApplyEditsResponse>, Status> {
+        let req = request.into_inner();
+        let path = PathBuf::from(&req.path);
+
+        if req.base_sha256.is_empty() {
+            return Err(Status::invalid_argument("base_sha256 is required; read the file first"));
+        }
+        if req.unified_diff.is_empty() == req.edits.is_empty() {
+            return Err(Status::invalid_argument("Provide either unified_diff or edits"));
+        }
+
+        // Check policy
+        let verdict = self.policy.check_file_access(&path, PathPermission::Write).await?;
+        let action = req.approval_action();
+        self.precheck(&verdict, &action, &req.approval_token, req.dry_run).await?;
+
+        let (target, max_file_size) = {
+            let config = self.config.read().await;
+            (config.confinement().resolve(&path)?, config.max_file_size)
+        };
+        let _guards = self.locks.lock(&[&target]).await;
+
+        let file = target.open(OpenMode::Read)
+            .map_err(|e| Status::not_found(format!("File not found: {}", e)))?;
+        let mut original = Vec::new();
+        file.take(max_file_size + 1).read_to_end(&mut original)
+            .map_err(|e| Status::internal(format!("Failed to read file: {}", e)))?;
+        if original.len() as u64 > max_file_size {
+            return Err(Status::invalid_argument(format!(
+                "File exceeds maximum size of {} bytes",
+                max_file_size
+            )));
+        }
+
+        let base_sha256 = Self::compute_sha256(&original);
+        if !base_sha256.eq_ignore_ascii_case(&req.base_sha256) {
+            return Err(changed_since_read(&base_sha256, &req.base_sha256).into());
+        }
+        let text = std::str::from_utf8(&original)
+            .map_err(|_| Status::invalid_argument("Only UTF-8 text files can be edited"))?;
+
+        // Every edit applies, or the file is left alone
+        let edited = if req.unified_diff.is_empty() {
+            let edits: Vec<Replacement> = req.edits.into_iter().map(Replacement::from).collect();
+            edits::apply_replacements(text, &edits)?
+        } else {
+            edits::apply_unified(text, &req.unified_diff)?
+        };
+        let diff = diff::unified(&req.path, Some(&original), Some(edited.as_bytes()));
+        let sha256 = Self::compute_sha256(edited.as_bytes());
+
+        if let Some(preview) = self.preview(&action, req.dry_run, &req.preview_hash, || {
+            Preview::write(&req.path, &target, edited.as_bytes())
+        })? {
+            return Ok(Response::new(ApplyEditsResponse {
+                sha256,
+                diff,
+                preview: Some(preview),
+                ..Default::default()
+            }));
+        }
+
+        let authorization = self.policy.enforce(verdict, &action, &req.approval_token).await?;
+
+        let snapshot_id = self.snapshots.create(&[target.path().to_path_buf()], "pre-edit")?.id;
+        confine::replace(&target, None, |file| file.write_all(edited.as_bytes()))
+            .map_err(|e| Status::internal(format!("Failed to write file: {}", e)))?;
+
+        // Log action
+        let mut entry = AuditLogger::create_entry("file", "edit");
+        entry.details = format!(
+            "Edited file: {} ({} -> {})",
+            path.display(),
+            &base_sha256[..12],
+            &sha256[..12]
+        );
+        authorization.apply_to(&mut entry);
+        entry.snapshot_id = Some(snapshot_id.clone());
+        entry.result = "success".to_string();
+        let _ = self.audit.log(entry);
+
+        Ok(Response::new(ApplyEditsResponse {
+            success: true,
+            sha256,
+            diff,
+            snapshot_id,
+            preview: None,
         }))
     }
 
@@ -212,34 +882,43 @@ impl file_service_server::FileService for FileServiceImpl {
         let to_path = PathBuf::from(&req.to_path);
 
         // Check policy for both paths
-        match self.policy.check_file_access(&from_path, true).await? {
-            PolicyDecision::Deny(reason) => return Err(Status::permission_denied(reason)),
-            PolicyDecision::RequireApproval(reason) => {
-                if req.approval_token.is_empty() {
-                    return Err(Status::failed_precondition(format!(
-                        "Approval required: {}",
-                        reason
-                    )));
-                }
-            }
-            PolicyDecision::Allow => {}
-        }
+        let verdict = self.policy.check_file_access(&from_path, PathPermission::Delete).await?
+            .and(self.policy.check_file_access(&to_path, write_permission(&to_path)).await?);
+        let action = req.approval_action();
+        self.precheck(&verdict, &action, &req.approval_token, req.dry_run).await?;
+
+        let confinement = self.config.read().await.confinement();
+        let from = confinement.resolve(&from_path)?;
+        let to = confinement.resolve(&to_path)?;
+        let _guards = self.locks.lock(&[&from, &to]).await;
+        check_expected_sha256(&to, &req.expected_sha256)?;
 
-        match self.policy.check_file_access(&to_path, true).await? {
-            PolicyDecision::Deny(reason) => return Err(Status::permission_denied(reason)),
-            _ => {}
+        if let Some(preview) = self.preview(&action, req.dry_run, &req.preview_hash, || {
+            Preview::rename((&req.from_path, &from), (&req.to_path, &to))
+        })? {
+            return Ok(Response::new(MoveFileResponse {
+                preview: Some(preview),
+                ..Default::default()
+            }));
         }
 
-        // Create snapshot
-        let snapshot_id = self.snapshots.create(&[from_path.clone()], "pre-move")?.id;
+        let authorization = self.policy.enforce(verdict, &action, &req.approval_token).await?;
+
+        // Create snapshot, including the destination the move would replace
+        let mut snapshot_paths = vec![from.path().to_path_buf()];
+        if to.path().exists() {
+            snapshot_paths.push(to.path().to_path_buf());
+        }
+        let snapshot_id = self.snapshots.create(&snapshot_paths, "pre-move")?.id;
 
         // Move file
-        std::fs::rename(&from_path, &to_path)
+        confine::rename(&from, &to)
             .map_err(|e| Status::internal(format!("Failed to move file: {}", e)))?;
 
         // Log action
         let mut entry = AuditLogger::create_entry("file", "move");
         entry.details = format!("Moved {} to {}", from_path.display(), to_path.display());
+        authorization.apply_to(&mut entry);
         entry.snapshot_id = Some(snapshot_id.clone());
         entry.result = "success".to_string();
         let _ = self.audit.log(entry);
@@ -247,6 +926,7 @@ impl file_service_server::FileService for FileServiceImpl {
         Ok(Response::new(MoveFileResponse {
             success: true,
             snapshot_id,
+            preview: None,
         }))
     }
 
@@ -259,36 +939,55 @@ impl file_service_server::FileService for FileServiceImpl {
         let to_path = PathBuf::from(&req.to_path);
 
         // Check policy
-        match self.policy.check_file_access(&from_path, false).await? {
-            PolicyDecision::Deny(reason) => return Err(Status::permission_denied(reason)),
-            _ => {}
-        }
+        let verdict = self.policy.check_file_access(&from_path, PathPermission::Read).await?
+            .and(self.policy.check_file_access(&to_path, write_permission(&to_path)).await?);
+        let action = req.approval_action();
+        self.precheck(&verdict, &action, &req.approval_token, req.dry_run).await?;
 
-        match self.policy.check_file_access(&to_path, true).await? {
-            PolicyDecision::Deny(reason) => return Err(Status::permission_denied(reason)),
-            PolicyDecision::RequireApproval(reason) => {
-                if req.approval_token.is_empty() {
-                    return Err(Status::failed_precondition(format!(
-                        "Approval required: {}",
-                        reason
-                    )));
-                }
-            }
-            PolicyDecision::Allow => {}
+        let confinement = self.config.read().await.confinement();
+        let from = confinement.resolve(&from_path)?;
+        let to = confinement.resolve(&to_path)?;
+        let _guards = self.locks.lock(&[&from, &to]).await;
+        check_expected_sha256(&to, &req.expected_sha256)?;
+
+        if let Some(preview) = self.preview(&action, req.dry_run, &req.preview_hash, || {
+            Preview::copy((&req.from_path, &from), (&req.to_path, &to))
+        })? {
+            return Ok(Response::new(CopyFileResponse {
+                preview: Some(preview),
+                ..Default::default()
+            }));
         }
 
-        // Copy file
-        std::fs::copy(&from_path, &to_path)
+        let authorization = self.policy.enforce(verdict, &action, &req.approval_token).await?;
+
+        // Create snapshot before overwriting an existing destination
+        let snapshot_id = if to.path().exists() {
+            Some(self.snapshots.create(&[to.path().to_path_buf()], "pre-copy")?.id)
+        } else {
+            None
+        };
+
+        // Copy file; a new destination gets the source's permissions
+        from.open(OpenMode::Read)
+            .and_then(|mut source| {
+                let mode = file_mode(&source.metadata()?);
+                confine::replace(&to, mode, |dest| std::io::copy(&mut source, dest).map(|_| ()))
+            })
             .map_err(|e| Status::internal(format!("Failed to copy file: {}", e)))?;
 
         // Log action
         let mut entry = AuditLogger::create_entry("file", "copy");
         entry.details = format!("Copied {} to {}", from_path.display(), to_path.display());
+        authorization.apply_to(&mut entry);
+        entry.snapshot_id = snapshot_id.clone();
         entry.result = "success".to_string();
         let _ = self.audit.log(entry);
 
         Ok(Response::new(CopyFileResponse {
             success: true,
+            snapshot_id: snapshot_id.unwrap_or_default(),
+            preview: None,
         }))
     }
 
@@ -300,27 +999,23 @@ impl file_service_server::FileService for FileServiceImpl {
         let path = PathBuf::from(&req.path);
 
         // Check policy
-        match self.policy.check_file_access(&path, false).await? {
-            PolicyDecision::Deny(reason) => return Err(Status::permission_denied(reason)),
-            _ => {}
-        }
+        let verdict = self.policy.check_file_access(&path, PathPermission::Read).await?;
+        self.policy.enforce(verdict, &req.approval_action(), &req.approval_token).await?;
 
-        let entries = std::fs::read_dir(&path)
+        // List the directory through a handle pinned inside the allowed root
+        let target = self.config.read().await.confinement().resolve(&path)?;
+        let entries = target.read_dir()
             .map_err(|e| Status::not_found(format!("Directory not found: {}", e)))?;
 
-        let mut dir_entries = Vec::new();
-        for entry in entries {
-            let entry = entry.map_err(|e| Status::internal(e.to_string()))?;
-            let metadata = entry.metadata().map_err(|e| Status::internal(e.to_string()))?;
-            
-            dir_entries.push(DirEntry {
-                name: entry.file_name().to_string_lossy().to_string(),
-                path: entry.path().to_string_lossy().to_string(),
+        let dir_entries = entries.into_iter()
+            .map(|(name, metadata)| DirEntry {
+                path: path.join(&name).to_string_lossy().to_string(),
+                name: name.to_string_lossy().to_string(),
                 is_dir: metadata.is_dir(),
                 is_file: metadata.is_file(),
                 size: metadata.len(),
-            });
-        }
+            })
+            .collect();
 
         Ok(Response::new(ListDirResponse {
             entries: dir_entries,
@@ -335,12 +1030,11 @@ impl file_service_server::FileService for FileServiceImpl {
         let path = PathBuf::from(&req.path);
 
         // Check policy
-        match self.policy.check_file_access(&path, false).await? {
-            PolicyDecision::Deny(reason) => return Err(Status::permission_denied(reason)),
-            _ => {}
-        }
+        let verdict = self.policy.check_file_access(&path, PathPermission::Read).await?;
+        self.policy.enforce(verdict, &req.approval_action(), &req.approval_token).await?;
 
-        let metadata = std::fs::metadata(&path)
+        let target = self.config.read().await.confinement().resolve(&path)?;
+        let metadata = target.metadata()
             .map_err(|e| Status::not_found(format!("Path not found: {}", e)))?;
 
         use std::time::UNIX_EPOCH;
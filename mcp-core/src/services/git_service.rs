@@ -2,29 +2,51 @@
 
 use std::sync::Arc;
 use std::path::PathBuf;
-use tokio::sync::RwLock;
 use tonic::{Request, Response, Status};
 use git2::{Repository, Signature};
 
+use crate::approval::ApprovalAction;
 use crate::audit::AuditLogger;
-use crate::config::Config;
-use crate::policy::{PolicyEngine, PolicyDecision};
+use crate::policy::PolicyEngine;
 
 pub use crate::git_proto::*;
 
 pub struct GitServiceImpl {
-    config: Arc<RwLock<Config>>,
     audit: Arc<AuditLogger>,
     policy: Arc<PolicyEngine>,
 }
 
 impl GitServiceImpl {
     pub fn new(
-        config: Arc<RwLock<Config>>,
         audit: Arc<AuditLogger>,
         policy: Arc<PolicyEngine>,
     ) -> Self {
-        Self { config, audit, policy }
+        Self { audit, policy }
+    }
+}
+
+impl GitStatusRequest {
+    /// The action an approval token for this request must be bound to
+    pub fn approval_action(&self) -> ApprovalAction {
+        ApprovalAction::new("git", "status").with_paths([&self.repo_path])
+    }
+}
+
+impl GitCommitRequest {
+    /// The action an approval token for this request must be bound to
+    pub fn approval_action(&self) -> ApprovalAction {
+        ApprovalAction::new("git", "commit")
+            .with_arguments(std::iter::once(&self.message).chain(self.files.iter()))
+            .with_paths([&self.repo_path])
+    }
+}
+
+impl CreateBranchRequest {
+    /// The action an approval token for this request must be bound to
+    pub fn approval_action(&self) -> ApprovalAction {
+        ApprovalAction::new("git", "create_branch")
+            .with_arguments([&self.branch_name])
+            .with_paths([&self.repo_path])
     }
 }
 
@@ -38,10 +60,10 @@ impl git_service_server::GitService for GitServiceImpl {
         let repo_path = PathBuf::from(&req.repo_path);
 
         // Check policy
-        match self.policy.check_git_operation(&repo_path, "status").await? {
-            PolicyDecision::Deny(reason) => return Err(Status::permission_denied(reason)),
-            _ => {}
-        }
+        let verdict = self.policy.check_git_operation(&repo_path, "status").await?;
+        let authorization = self.policy
+            .enforce(verdict, &req.approval_action(), &req.approval_token)
+            .await?;
 
         let repo = Repository::open(&repo_path)
             .map_err(|e| Status::not_found(format!("Not a git repository: {}", e)))?;
@@ -76,6 +98,7 @@ impl git_service_server::GitService for GitServiceImpl {
         // Log action
         let mut entry = AuditLogger::create_entry("git", "status");
         entry.details = format!("Git status: {}", repo_path.display());
+        authorization.apply_to(&mut entry);
         entry.result = "success".to_string();
         let _ = self.audit.log(entry);
 
@@ -95,17 +118,10 @@ impl git_service_server::GitService for GitServiceImpl {
         let repo_path = PathBuf::from(&req.repo_path);
 
         // Check policy
-        match self.policy.check_git_operation(&repo_path, "commit").await? {
-            PolicyDecision::Deny(reason) => return Err(Status::permission_denied(reason)),
-            PolicyDecision::RequireApproval(reason) => {
-                if req.approval_token.is_empty() {
-                    return Err(Status::failed_precondition(format!(
-                        "Approval required: {}", reason
-                    )));
-                }
-            }
-            PolicyDecision::Allow => {}
-        }
+        let verdict = self.policy.check_git_operation(&repo_path, "commit").await?;
+        let authorization = self.policy
+            .enforce(verdict, &req.approval_action(), &req.approval_token)
+            .await?;
 
         let repo = Repository::open(&repo_path)
             .map_err(|e| Status::not_found(format!("Not a git repository: {}", e)))?;
@@ -147,7 +163,7 @@ impl git_service_server::GitService for GitServiceImpl {
         // Log action
         let mut entry = AuditLogger::create_entry("git", "commit");
         entry.details = format!("Git commit: {} - {}", commit_id, req.message);
-        entry.user_approved = !req.approval_token.is_empty();
+        authorization.apply_to(&mut entry);
         entry.result = "success".to_string();
         let _ = self.audit.log(entry);
 
@@ -166,10 +182,10 @@ impl git_service_server::GitService for GitServiceImpl {
         let req = request.into_inner();
         let repo_path = PathBuf::from(&req.repo_path);
 
-        match self.policy.check_git_operation(&repo_path, "branch").await? {
-            PolicyDecision::Deny(reason) => return Err(Status::permission_denied(reason)),
-            _ => {}
-        }
+        let verdict = self.policy.check_git_operation(&repo_path, "create_branch").await?;
+        let authorization = self.policy
+            .enforce(verdict, &req.approval_action(), &req.approval_token)
+            .await?;
 
         let repo = Repository::open(&repo_path)
             .map_err(|e| Status::not_found(format!("Not a git repository: {}", e)))?;
@@ -184,6 +200,7 @@ impl git_service_server::GitService for GitServiceImpl {
 
         let mut entry = AuditLogger::create_entry("git", "create_branch");
         entry.details = format!("Created branch: {}", req.branch_name);
+        authorization.apply_to(&mut entry);
         entry.result = "success".to_string();
         let _ = self.audit.log(entry);
 
@@ -5,3 +5,4 @@ pub mod command_service;
 pub mod git_service;
 pub mod snapshot_service;
 pub mod system_service;
+pub mod policy_service;
@@ -0,0 +1,281 @@
+//! Policy service implementation for runtime configuration and approvals
+
+use std::sync::Arc;
+use std::path::PathBuf;
+use tokio::sync::RwLock;
+use tonic::{Request, Response, Status};
+
+use crate::approval::ApprovalAction;
+use crate::audit::AuditLogger;
+use crate::config::{self, Config, PathPermission};
+use crate::error::McpError;
+use crate::policy::PolicyEngine;
+
+pub use crate::policy_proto::*;
+
+pub struct PolicyServiceImpl {
+    config: Arc<RwLock<Config>>,
+    audit: Arc<AuditLogger>,
+    policy: Arc<PolicyEngine>,
+}
+
+impl PolicyServiceImpl {
+    pub fn new(
+        config: Arc<RwLock<Config>>,
+        audit: Arc<AuditLogger>,
+        policy: Arc<PolicyEngine>,
+    ) -> Self {
+        Self { config, audit, policy }
+    }
+
+    /// Validate a requested configuration before it replaces the live one
+    fn validate(req: &SetConfigRequest) -> Result<(), McpError> {
+        for zone in &req.path_zones {
+            zone_from_request(zone)?;
+        }
+        if let Some(env_policy) = &req.env_policy {
+            for glob in env_policy.allowed.iter().chain(&env_policy.denied) {
+                glob::Pattern::new(glob).map_err(|e| {
+                    McpError::InvalidArgument(format!("Invalid environment glob '{}': {}", glob, e))
+                })?;
+            }
+        }
+
+        for path in &req.allowed_paths {
+            if path.trim().is_empty() || !PathBuf::from(path).is_absolute() {
+                return Err(McpError::InvalidArgument(format!(
+                    "Allowed path '{}' must be an absolute path",
+                    path
+                )));
+            }
+        }
+
+        for command in &req.whitelisted_commands {
+            // A relative path would depend on the working directory
+            let is_path = command.contains(['/', '\\']);
+            if command.trim().is_empty()
+                || command.chars().any(char::is_whitespace)
+                || (is_path && !PathBuf::from(command).is_absolute())
+            {
+                return Err(McpError::InvalidArgument(format!(
+                    "Whitelisted command '{}' must be a bare executable name or an absolute path",
+                    command
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Describe the differences between two configurations for the audit log
+    fn describe_changes(old: &Config, new: &Config) -> Vec<String> {
+        let mut changes = Vec::new();
+
+        if old.allowed_paths != new.allowed_paths {
+            changes.push(format!(
+                "allowed_paths: {:?} -> {:?}",
+                old.allowed_paths, new.allowed_paths
+            ));
+        }
+        if old.whitelisted_commands != new.whitelisted_commands {
+            changes.push(format!(
+                "whitelisted_commands: {:?} -> {:?}",
+                old.whitelisted_commands, new.whitelisted_commands
+            ));
+        }
+        if old.path_zones != new.path_zones {
+            changes.push(format!("path_zones: {:?} -> {:?}", old.path_zones, new.path_zones));
+        }
+        if old.env_policy != new.env_policy {
+            changes.push(format!("env_policy: {:?} -> {:?}", old.env_policy, new.env_policy));
+        }
+        if old.dry_run_default != new.dry_run_default {
+            changes.push(format!(
+                "dry_run_default: {} -> {}",
+                old.dry_run_default, new.dry_run_default
+            ));
+        }
+        if old.sandbox_enabled != new.sandbox_enabled {
+            changes.push(format!(
+                "sandbox_enabled: {} -> {}",
+                old.sandbox_enabled, new.sandbox_enabled
+            ));
+        }
+
+        changes
+    }
+}
+
+#[tonic::async_trait]
+impl policy_service_server::PolicyService for PolicyServiceImpl {
+    async fn get_config(
+        &self,
+        _request: Request<GetConfigRequest>,
+    ) -> Result<Response<GetConfigResponse>, Status> {
+        let config = self.config.read().await;
+
+        Ok(Response::new(GetConfigResponse {
+            allowed_paths: config.allowed_paths.iter()
+                .map(|p| p.to_string_lossy().to_string())
+                .collect(),
+            whitelisted_commands: config.whitelisted_commands.clone(),
+            dry_run_default: config.dry_run_default,
+            sandbox_enabled: config.sandbox_enabled,
+            path_zones: config.path_zones.iter().map(PathZone::from).collect(),
+            env_policy: Some(EnvPolicy::from(&config.env_policy)),
+        }))
+    }
+
+    async fn set_config(
+        &self,
+        request: Request<SetConfigRequest>,
+    ) -> Result<Response<SetConfigResponse>, Status> {
+        let req = request.into_inner();
+
+        if let Err(e) = Self::validate(&req) {
+            let mut entry = AuditLogger::create_entry("policy", "set_config");
+            entry.details = format!("Rejected config change: {}", e);
+            entry.result = "rejected".to_string();
+            let _ = self.audit.log(entry);
+            return Err(e.into());
+        }
+
+        let mut config = self.config.write().await;
+
+        let mut updated = config.clone();
+        updated.allowed_paths = req.allowed_paths.iter().map(PathBuf::from).collect();
+        updated.whitelisted_commands = req.whitelisted_commands;
+        updated.dry_run_default = req.dry_run_default;
+        updated.sandbox_enabled = req.sandbox_enabled;
+        updated.path_zones = req.path_zones.iter()
+            .map(zone_from_request)
+            .collect::<Result<_, _>>()?;
+        if let Some(env_policy) = req.env_policy {
+            updated.env_policy = env_policy.into();
+        }
+
+        let changes = Self::describe_changes(&config, &updated);
+
+        // Persist first so the live config never diverges from what is on disk
+        if let Err(e) = updated.save() {
+            let mut entry = AuditLogger::create_entry("policy", "set_config");
+            entry.details = format!("Failed to persist config change: {}", e);
+            entry.result = "failed".to_string();
+            let _ = self.audit.log(entry);
+            return Err(e.into());
+        }
+
+        *config = updated;
+
+        // Log action
+        let mut entry = AuditLogger::create_entry("policy", "set_config");
+        entry.details = if changes.is_empty() {
+            "Config saved without changes".to_string()
+        } else {
+            format!("Updated config: {}", changes.join("; "))
+        };
+        entry.user_approved = true;
+        entry.result = "success".to_string();
+        let _ = self.audit.log(entry);
+
+        Ok(Response::new(SetConfigResponse {
+            success: true,
+        }))
+    }
+
+    async fn request_approval(
+        &self,
+        request: Request<ApprovalRequest>,
+    ) -> Result<Response<ApprovalResponse>, Status> {
+        let req = request.into_inner();
+
+        if req.service.is_empty() || req.operation.is_empty() {
+            return Err(Status::invalid_argument(
+                "Approval request must name the service and operation it approves",
+            ));
+        }
+
+        let action = ApprovalAction::new(&req.service, &req.operation)
+            .with_arguments(req.arguments)
+            .with_paths(req.affected_paths.iter().map(PathBuf::from))
+            .with_preview_hash(&req.preview_hash);
+
+        // Issuance is audited by the policy engine
+        let issued = self.policy.issue_approval(&action).await;
+
+        Ok(Response::new(ApprovalResponse {
+            approved: true,
+            approval_token: issued.token,
+            expires_at: issued.expires_at.to_rfc3339(),
+        }))
+    }
+}
+
+impl From<&config::PathZone> for PathZone {
+    fn from(zone: &config::PathZone) -> Self {
+        Self {
+            path: zone.path.to_string_lossy().to_string(),
+            permissions: zone.permissions.iter().map(|p| p.as_str().to_string()).collect(),
+            deny: zone.deny.clone(),
+        }
+    }
+}
+
+/// A zone from a request, checked like one from the config file
+fn zone_from_request(zone: &PathZone) -> Result<config::PathZone, McpError> {
+    if zone.path.trim().is_empty() || !PathBuf::from(&zone.path).is_absolute() {
+        return Err(McpError::InvalidArgument(format!(
+            "Path zone '{}' must be an absolute path",
+            zone.path
+        )));
+    }
+
+    let permissions = if zone.permissions.is_empty() {
+        vec![PathPermission::Read]
+    } else {
+        zone.permissions.iter()
+            .map(|name| {
+                PathPermission::ALL.into_iter().find(|p| p.as_str() == name).ok_or_else(|| {
+                    McpError::InvalidArgument(format!(
+                        "Unknown permission '{}' for path zone '{}'",
+                        name, zone.path
+                    ))
+                })
+            })
+            .collect::<Result<_, _>>()?
+    };
+
+    for glob in &zone.deny {
+        glob::Pattern::new(glob).map_err(|e| {
+            McpError::InvalidArgument(format!("Invalid deny glob '{}': {}", glob, e))
+        })?;
+    }
+
+    Ok(config::PathZone { path: PathBuf::from(&zone.path), permissions, deny: zone.deny.clone() })
+}
+
+impl From<&config::EnvPolicy> for EnvPolicy {
+    fn from(policy: &config::EnvPolicy) -> Self {
+        Self {
+            passthrough: policy.passthrough.clone(),
+            command_passthrough: policy.command_passthrough.iter()
+                .map(|(command, names)| (command.clone(), VariableNames { names: names.clone() }))
+                .collect(),
+            allowed: policy.allowed.clone(),
+            denied: policy.denied.clone(),
+        }
+    }
+}
+
+impl From<EnvPolicy> for config::EnvPolicy {
+    fn from(policy: EnvPolicy) -> Self {
+        Self {
+            passthrough: policy.passthrough,
+            command_passthrough: policy.command_passthrough.into_iter()
+                .map(|(command, names)| (command, names.names))
+                .collect(),
+            allowed: policy.allowed,
+            denied: policy.denied,
+        }
+    }
+}
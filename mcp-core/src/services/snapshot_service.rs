@@ -4,19 +4,42 @@ use std::sync::Arc;
 use std::path::PathBuf;
 use tonic::{Request, Response, Status};
 
+use crate::approval::ApprovalAction;
 use crate::audit::AuditLogger;
+use crate::policy::PolicyEngine;
 use crate::snapshot::SnapshotManager;
 
 pub use crate::snapshot_proto::*;
 
 pub struct SnapshotServiceImpl {
     audit: Arc<AuditLogger>,
+    policy: Arc<PolicyEngine>,
     snapshots: Arc<SnapshotManager>,
 }
 
 impl SnapshotServiceImpl {
-    pub fn new(audit: Arc<AuditLogger>, snapshots: Arc<SnapshotManager>) -> Self {
-        Self { audit, snapshots }
+    pub fn new(
+        audit: Arc<AuditLogger>,
+        policy: Arc<PolicyEngine>,
+        snapshots: Arc<SnapshotManager>,
+    ) -> Self {
+        Self { audit, policy, snapshots }
+    }
+}
+
+impl RestoreSnapshotRequest {
+    /// The action an approval token for this request must be bound to
+    pub fn approval_action(&self) -> ApprovalAction {
+        ApprovalAction::new("snapshot", "restore")
+            .with_arguments([&self.snapshot_id])
+            .with_paths(self.target_paths.iter())
+    }
+}
+
+impl DeleteSnapshotRequest {
+    /// The action an approval token for this request must be bound to
+    pub fn approval_action(&self) -> ApprovalAction {
+        ApprovalAction::new("snapshot", "delete").with_arguments([&self.snapshot_id])
     }
 }
 
@@ -29,6 +52,11 @@ impl snapshot_service_server::SnapshotService for SnapshotServiceImpl {
         let req = request.into_inner();
         let paths: Vec<PathBuf> = req.paths.iter().map(PathBuf::from).collect();
 
+        // Check policy
+        let verdict = self.policy.check_snapshot_operation("create", &paths).await?;
+        let action = ApprovalAction::new("snapshot", "create").with_paths(&paths);
+        self.policy.enforce(verdict, &action, "").await?;
+
         let snapshot = self.snapshots.create(&paths, &req.label)
             .map_err(|e| Status::internal(e.to_string()))?;
 
@@ -54,11 +82,27 @@ impl snapshot_service_server::SnapshotService for SnapshotServiceImpl {
             Some(req.target_paths.iter().map(PathBuf::from).collect())
         };
 
+        // Check policy against every file the restore would write
+        let snapshot = self.snapshots.get(&req.snapshot_id)
+            .ok_or_else(|| Status::not_found(format!("Snapshot '{}' not found", req.snapshot_id)))?;
+        let affected: Vec<PathBuf> = snapshot.files.keys()
+            .filter(|p| match &target_paths {
+                Some(targets) => targets.iter().any(|t| p.starts_with(t)),
+                None => true,
+            })
+            .cloned()
+            .collect();
+        let verdict = self.policy.check_snapshot_operation("restore", &affected).await?;
+        let authorization = self.policy
+            .enforce(verdict, &req.approval_action(), &req.approval_token)
+            .await?;
+
         let restored = self.snapshots.restore(&req.snapshot_id, target_paths.as_deref())
             .map_err(|e| Status::internal(e.to_string()))?;
 
         let mut entry = AuditLogger::create_entry("snapshot", "restore");
         entry.details = format!("Restored snapshot: {} ({} files)", req.snapshot_id, restored.len());
+        authorization.apply_to(&mut entry);
         entry.result = "success".to_string();
         let _ = self.audit.log(entry);
 
@@ -94,11 +138,18 @@ impl snapshot_service_server::SnapshotService for SnapshotServiceImpl {
     ) -> Result<Response<DeleteSnapshotResponse>, Status> {
         let req = request.into_inner();
 
+        // Check policy
+        let verdict = self.policy.check_snapshot_operation("delete", &[]).await?;
+        let authorization = self.policy
+            .enforce(verdict, &req.approval_action(), &req.approval_token)
+            .await?;
+
         self.snapshots.delete(&req.snapshot_id)
             .map_err(|e| Status::internal(e.to_string()))?;
 
         let mut entry = AuditLogger::create_entry("snapshot", "delete");
         entry.details = format!("Deleted snapshot: {}", req.snapshot_id);
+        authorization.apply_to(&mut entry);
         entry.result = "success".to_string();
         let _ = self.audit.log(entry);
 
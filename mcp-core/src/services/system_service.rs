@@ -2,7 +2,7 @@
 
 use std::sync::Arc;
 use tonic::{Request, Response, Status};
-use sysinfo::{System, Disks, Networks};
+use sysinfo::{System, Disks};
 
 use crate::audit::AuditLogger;
 
@@ -92,6 +92,8 @@ impl system_service_server::SystemService for SystemServiceImpl {
                 details: e.details.clone(),
                 result: e.result.clone(),
                 snapshot_id: e.snapshot_id.clone().unwrap_or_default(),
+                rule_id: e.rule_id.clone().unwrap_or_default(),
+                artifact_id: e.artifact_id.clone().unwrap_or_default(),
             }
         }).collect();
 
@@ -0,0 +1,195 @@
+//! Simulated command runs against a throwaway copy of a working directory
+//!
+//! The copy is a plain recursive copy rather than an overlay mount, so it
+//! works unprivileged on every platform. Only the working directory is
+//! copied: arguments naming paths inside it and absolute symlinks into it
+//! are redirected to the copy, but a command that writes elsewhere (its home
+//! directory, paths it builds itself) is not contained.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::confine;
+use crate::diff;
+use crate::error::{McpError, McpResult};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Created,
+    Modified,
+    Deleted,
+}
+
+impl ChangeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Created => "created",
+            ChangeKind::Modified => "modified",
+            ChangeKind::Deleted => "deleted",
+        }
+    }
+}
+
+/// A file the simulated command created, modified or deleted
+#[derive(Debug, Clone)]
+pub struct FileChange {
+    /// Relative to the working directory
+    pub path: String,
+    pub kind: ChangeKind,
+    /// Unified diff of the change, empty for symlinks
+    pub diff: String,
+}
+
+/// What is at a path in a directory tree
+#[derive(Debug, PartialEq)]
+enum Node {
+    Dir,
+    File,
+    Symlink(PathBuf),
+}
+
+/// A temporary copy of a directory, removed when dropped
+pub struct Workspace {
+    _dir: tempfile::TempDir,
+    source: PathBuf,
+    root: PathBuf,
+}
+
+impl Workspace {
+    /// Copy `source` into a new temporary directory, failing if its files
+    /// add up to more than `max_bytes`
+    pub fn copy_of(source: &Path, max_bytes: u64) -> McpResult<Self> {
+        let dir = tempfile::Builder::new().prefix("mcp-simulate-").tempdir()?;
+        // Keep the directory name, some tools derive defaults from it
+        let root = dir.path().join(source.file_name().unwrap_or(std::ffi::OsStr::new("root")));
+
+        let mut copied = 0u64;
+        for entry in WalkDir::new(source).follow_links(false) {
+            let entry = entry.map_err(|e| McpError::CommandError(e.to_string()))?;
+            let target = root.join(relative(source, entry.path()));
+            let file_type = entry.file_type();
+
+            if file_type.is_dir() {
+                fs::create_dir_all(&target)?;
+            } else if file_type.is_file() {
+                copied += entry.metadata().map_err(|e| McpError::CommandError(e.to_string()))?.len();
+                if copied > max_bytes {
+                    return Err(McpError::InvalidArgument(format!(
+                        "{} is too large to simulate in a copy (limit {} MiB)",
+                        source.display(),
+                        max_bytes / (1024 * 1024)
+                    )));
+                }
+                fs::copy(entry.path(), &target)?;
+            } else if file_type.is_symlink() {
+                // An absolute link into the source would lead the command
+                // back to the real files, so it points into the copy instead
+                #[cfg(unix)]
+                std::os::unix::fs::symlink(relocate(fs::read_link(entry.path())?, source, &root), &target)?;
+            }
+        }
+
+        Ok(Self { _dir: dir, source: source.to_path_buf(), root })
+    }
+
+    /// The copy of the source directory
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Redirect an argument naming a path inside the source directory to
+    /// the same path in the copy
+    pub fn rewrite_arg(&self, arg: &str) -> String {
+        match Path::new(arg).strip_prefix(&self.source) {
+            Ok(rest) if rest.as_os_str().is_empty() => self.root.to_string_lossy().to_string(),
+            Ok(rest) => self.root.join(rest).to_string_lossy().to_string(),
+            Err(_) => arg.to_string(),
+        }
+    }
+
+    /// Files that differ between the copy and the source directory
+    pub fn changes(&self) -> McpResult<Vec<FileChange>> {
+        let before = tree(&self.source, &self.source)?;
+        let after = tree(&self.root, &self.source)?;
+        let mut changes = Vec::new();
+
+        for (path, node) in &after {
+            let kind = match (before.get(path), node) {
+                (_, Node::Dir) => continue,
+                (None, _) => ChangeKind::Created,
+                (Some(Node::File), Node::File) => {
+                    if fs::read(self.source.join(path))? == fs::read(self.root.join(path))? {
+                        continue;
+                    }
+                    ChangeKind::Modified
+                }
+                (Some(old), new) if old == new => continue,
+                (Some(_), _) => ChangeKind::Modified,
+            };
+            changes.push(self.change(path, kind, before.get(path), Some(node))?);
+        }
+
+        for (path, node) in &before {
+            if *node != Node::Dir && !after.contains_key(path) {
+                changes.push(self.change(path, ChangeKind::Deleted, Some(node), None)?);
+            }
+        }
+
+        changes.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(changes)
+    }
+
+    fn change(&self, path: &str, kind: ChangeKind, old: Option<&Node>, new: Option<&Node>) -> McpResult<FileChange> {
+        let content = |node: Option<&Node>, base: &Path| -> McpResult<Option<Vec<u8>>> {
+            match node {
+                Some(Node::File) => Ok(Some(fs::read(base.join(path))?)),
+                _ => Ok(None),
+            }
+        };
+        let old = content(old, &self.source)?;
+        let new = content(new, &self.root)?;
+        let diff = if old.is_some() || new.is_some() {
+            diff::unified(path, old.as_deref(), new.as_deref())
+        } else {
+            String::new()
+        };
+        Ok(FileChange { path: path.to_string(), kind, diff })
+    }
+}
+
+/// Every entry below `root`, keyed by its relative path. Links into `root`
+/// are given as links into `source`, so a copy compares equal to its source.
+fn tree(root: &Path, source: &Path) -> McpResult<BTreeMap<String, Node>> {
+    let mut nodes = BTreeMap::new();
+    for entry in WalkDir::new(root).min_depth(1).follow_links(false) {
+        let entry = entry.map_err(|e| McpError::CommandError(e.to_string()))?;
+        let file_type = entry.file_type();
+        let node = if file_type.is_dir() {
+            Node::Dir
+        } else if file_type.is_file() {
+            Node::File
+        } else if file_type.is_symlink() {
+            Node::Symlink(relocate(fs::read_link(entry.path())?, root, source))
+        } else {
+            continue;
+        };
+        let path = relative(root, entry.path()).to_string_lossy().replace('\\', "/");
+        nodes.insert(path, node);
+    }
+    Ok(nodes)
+}
+
+/// Point an absolute link target inside `from` at the same path inside `to`
+fn relocate(link: PathBuf, from: &Path, to: &Path) -> PathBuf {
+    match confine::normalize(&link).strip_prefix(from) {
+        Ok(rest) if rest.as_os_str().is_empty() => to.to_path_buf(),
+        Ok(rest) => to.join(rest),
+        Err(_) => link,
+    }
+}
+
+fn relative<'a>(root: &Path, path: &'a Path) -> &'a Path {
+    path.strip_prefix(root).unwrap_or(path)
+}
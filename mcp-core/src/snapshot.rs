@@ -72,6 +72,22 @@ impl SnapshotManager {
     }
 
     pub fn create(&self, paths: &[PathBuf], label: &str) -> McpResult<Snapshot> {
+        self.create_excluding(paths, label, &[])
+    }
+
+    /// Like `create`, but leaves out files and directories below `paths`
+    /// whose name matches one of the `ignore` globs
+    pub fn create_excluding(&self, paths: &[PathBuf], label: &str, ignore: &[String]) -> McpResult<Snapshot> {
+        let ignore = ignore.iter()
+            .map(|p| glob::Pattern::new(p).map_err(|e| {
+                McpError::SnapshotError(format!("Invalid ignore pattern '{}': {}", p, e))
+            }))
+            .collect::<McpResult<Vec<_>>>()?;
+        let ignored = |name: &std::ffi::OsStr| {
+            let name = name.to_string_lossy();
+            ignore.iter().any(|p| p.matches(&name))
+        };
+
         let id = Uuid::new_v4().to_string();
         let snapshot_dir = self.base_dir.join(&id);
         fs::create_dir_all(&snapshot_dir)
@@ -84,7 +100,9 @@ impl SnapshotManager {
                 let file_snapshot = self.snapshot_file(path, &snapshot_dir)?;
                 files.insert(path.clone(), file_snapshot);
             } else if path.is_dir() {
-                for entry in WalkDir::new(path).into_iter().filter_map(|e| e.ok()) {
+                let entries = WalkDir::new(path).into_iter()
+                    .filter_entry(|e| e.depth() == 0 || !ignored(e.file_name()));
+                for entry in entries.filter_map(|e| e.ok()) {
                     if entry.file_type().is_file() {
                         let file_path = entry.path().to_path_buf();
                         let file_snapshot = self.snapshot_file(&file_path, &snapshot_dir)?;
@@ -0,0 +1,140 @@
+//! Tests for applying diffs and search/replace edits to files
+
+#[cfg(test)]
+mod tests {
+    use sha2::{Digest, Sha256};
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+    use tonic::{Code, Request};
+
+    use mcp_core::edits::{apply_replacements, apply_unified, Replacement};
+    use mcp_core::services::file_service::{file_service_server::FileService, *};
+    use mcp_core::{AuditLogger, Config, McpError, PolicyEngine, SnapshotManager};
+
+    const TEXT: &str = "one\ntwo\nthree\nfour\nfive\nsix\nseven\n";
+
+    fn conflict(result: Result<String, McpError>) -> String {
+        match result {
+            Err(McpError::Conflict(reason)) => reason,
+            other => panic!("Expected a conflict, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_unified_diff_applies_hunks() {
+        let patch = "--- a/f.txt\n+++ b/f.txt\n@@ -1,3 +1,3 @@\n one\n-two\n+TWO\n three\n@@ -6,2 +6,3 @@\n six\n seven\n+eight\n";
+        assert_eq!(apply_unified(TEXT, patch).unwrap(), "one\nTWO\nthree\nfour\nfive\nsix\nseven\neight\n");
+
+        // Hunks whose line numbers drifted are found after the previous hunk
+        let patch = "@@ -10,3 +10,2 @@\n three\n-four\n five\n";
+        assert_eq!(apply_unified(TEXT, patch).unwrap(), "one\ntwo\nthree\nfive\nsix\nseven\n");
+
+        // The last line can lack a newline on either side
+        let patch = "@@ -7 +7 @@\n-seven\n+seven\n\\ No newline at end of file\n";
+        assert_eq!(apply_unified(TEXT, patch).unwrap(), TEXT.trim_end());
+    }
+
+    #[test]
+    fn test_unified_diff_conflicts_are_precise() {
+        let reason = conflict(apply_unified(TEXT, "@@ -2,2 +2,2 @@\n two\n-3\n+THREE\n"));
+        assert_eq!(reason, "Hunk 1 (@@ -2,2 +2,2 @@) does not apply: line 3 is \"three\", expected \"3\"");
+
+        let reason = conflict(apply_unified("a\nx\na\nx\n", "@@ -9,2 +9,2 @@\n a\n-x\n+y\n"));
+        assert!(reason.contains("match at lines 1, 3"), "{}", reason);
+
+        // A hunk that does not fit its header is refused outright
+        let err = apply_unified(TEXT, "@@ -1,2 +1,2 @@\n one\n-two\n+TWO\n three\n").unwrap_err();
+        assert!(matches!(err, McpError::InvalidArgument(_)), "{:?}", err);
+        let err = apply_unified(TEXT, "not a patch\n").unwrap_err();
+        assert!(matches!(err, McpError::InvalidArgument(_)), "{:?}", err);
+    }
+
+    #[test]
+    fn test_replacements() {
+        let edit = |search: &str, replace: &str, replace_all| Replacement {
+            search: search.to_string(),
+            replace: replace.to_string(),
+            replace_all,
+        };
+
+        let edited = apply_replacements(TEXT, &[edit("two\n", "2\n", false), edit("2\nthree", "2\n3", false)]).unwrap();
+        assert!(edited.starts_with("one\n2\n3\nfour\n"));
+
+        assert!(conflict(apply_replacements(TEXT, &[edit("ten", "10", false)])).contains("not found"));
+        let reason = conflict(apply_replacements(TEXT, &[edit("e\n", "E\n", false)]));
+        assert!(reason.contains("occurs 3 times"), "{}", reason);
+        assert_eq!(apply_replacements("a a a", &[edit("a", "b", true)]).unwrap(), "b b b");
+    }
+
+    fn sha256(content: &[u8]) -> String {
+        hex::encode(Sha256::digest(content))
+    }
+
+    #[tokio::test]
+    async fn test_apply_edits_rpc() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let state = root.join(".state");
+        let config = Config {
+            allowed_paths: vec![root.clone()],
+            audit_db_path: state.join("audit.db"),
+            snapshot_dir: state.join("snapshots"),
+            ..Config::default()
+        };
+        let audit = Arc::new(AuditLogger::new(&config.audit_db_path).unwrap());
+        let snapshots = Arc::new(SnapshotManager::new(&config.snapshot_dir).unwrap());
+        let config = Arc::new(RwLock::new(config));
+        let policy = Arc::new(PolicyEngine::new(config.clone(), audit.clone()));
+        let svc = FileServiceImpl::new(config, audit, policy.clone(), snapshots.clone());
+
+        let path = root.join("script.sh");
+        std::fs::write(&path, TEXT).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o750)).unwrap();
+        }
+
+        let apply = |base_sha256: String, unified_diff: &str| {
+            let svc = &svc;
+            let policy = &policy;
+            let mut req = ApplyEditsRequest {
+                path: path.to_string_lossy().to_string(),
+                base_sha256,
+                unified_diff: unified_diff.to_string(),
+                ..Default::default()
+            };
+            async move {
+                req.approval_token = policy.issue_approval(&req.approval_action()).await.token;
+                svc.apply_edits(Request::new(req)).await
+            }
+        };
+
+        // Edits made against an older version are refused
+        let err = apply(sha256(b"stale"), "@@ -2 +2 @@\n-two\n+TWO\n").await.unwrap_err();
+        assert_eq!(err.code(), Code::FailedPrecondition);
+        assert!(err.message().contains("changed since it was read"), "{}", err.message());
+
+        // So are conflicting hunks, leaving the file alone
+        let err = apply(sha256(TEXT.as_bytes()), "@@ -2 +2 @@\n-two\n+TWO\n@@ -9 +9 @@\n-nine\n+NINE\n").await.unwrap_err();
+        assert_eq!(err.code(), Code::FailedPrecondition);
+        assert!(err.message().starts_with("Hunk 2"), "{}", err.message());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), TEXT);
+
+        let response = apply(sha256(TEXT.as_bytes()), "@@ -2 +2 @@\n-two\n+TWO\n").await.unwrap().into_inner();
+        let edited = TEXT.replace("two", "TWO");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), edited);
+        assert_eq!(response.sha256, sha256(edited.as_bytes()));
+        assert!(response.diff.contains("-two\n+TWO\n"), "{}", response.diff);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o750);
+        }
+        // No temporary files are left behind
+        assert_eq!(std::fs::read_dir(&root).unwrap().count(), 2);
+
+        snapshots.restore(&response.snapshot_id, None).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), TEXT);
+    }
+}
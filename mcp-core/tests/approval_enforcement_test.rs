@@ -0,0 +1,371 @@
+//! Tests that every mutating RPC enforces approval tokens
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use tempfile::TempDir;
+    use tokio::sync::RwLock;
+    use tonic::{Code, Request, Response, Status};
+
+    use mcp_core::services::command_service::{command_service_server::CommandService, *};
+    use mcp_core::services::file_service::{file_service_server::FileService, *};
+    use mcp_core::services::git_service::{git_service_server::GitService, *};
+    use mcp_core::services::snapshot_service::{snapshot_service_server::SnapshotService, *};
+    use mcp_core::{ApprovalAction, AuditLogger, Config, PolicyEngine, RuleSet, SnapshotManager};
+
+    struct Harness {
+        _dir: TempDir,
+        root: PathBuf,
+        config: Arc<RwLock<Config>>,
+        audit: Arc<AuditLogger>,
+        policy: Arc<PolicyEngine>,
+        snapshots: Arc<SnapshotManager>,
+    }
+
+    impl Harness {
+        fn new() -> Self {
+            let dir = tempfile::tempdir().expect("Failed to create temp dir");
+            let root = dir.path().canonicalize().unwrap();
+            let state_dir = dir.path().join(".mcp-state");
+
+            let config = Config {
+                allowed_paths: vec![root.clone()],
+                whitelisted_commands: vec!["echo".to_string()],
+                auto_approve_patterns: vec![],
+                audit_db_path: state_dir.join("audit.db"),
+                snapshot_dir: state_dir.join("snapshots"),
+                ..Config::default()
+            };
+
+            let audit = Arc::new(AuditLogger::new(&config.audit_db_path).unwrap());
+            let snapshots = Arc::new(SnapshotManager::new(&config.snapshot_dir).unwrap());
+            let config = Arc::new(RwLock::new(config));
+            let policy = Arc::new(PolicyEngine::new(config.clone(), audit.clone()));
+
+            Self { _dir: dir, root, config, audit, policy, snapshots }
+        }
+
+        fn file_service(&self) -> FileServiceImpl {
+            FileServiceImpl::new(self.config.clone(), self.audit.clone(), self.policy.clone(), self.snapshots.clone())
+        }
+
+        fn git_service(&self) -> GitServiceImpl {
+            GitServiceImpl::new(self.audit.clone(), self.policy.clone())
+        }
+
+        fn command_service(&self) -> CommandServiceImpl {
+            CommandServiceImpl::new(self.config.clone(), self.audit.clone(), self.policy.clone())
+        }
+
+        fn snapshot_service(&self) -> SnapshotServiceImpl {
+            SnapshotServiceImpl::new(self.audit.clone(), self.policy.clone(), self.snapshots.clone())
+        }
+
+        fn write(&self, name: &str, content: &str) -> String {
+            let path = self.root.join(name);
+            std::fs::write(&path, content).unwrap();
+            path.to_string_lossy().to_string()
+        }
+
+        async fn approve(&self, action: &ApprovalAction) -> String {
+            self.policy.issue_approval(action).await.token
+        }
+
+        /// A token that is valid, but for an unrelated action
+        async fn foreign_token(&self) -> String {
+            let action = ApprovalAction::new("file", "create").with_paths([self.root.join("elsewhere.txt")]);
+            self.approve(&action).await
+        }
+    }
+
+    fn init_repo(path: &Path) -> git2::Repository {
+        let repo = git2::Repository::init(path).unwrap();
+        std::fs::write(path.join("README.md"), "# test\n").unwrap();
+        repo
+    }
+
+    fn code<T>(result: Result<Response<T>, Status>) -> Code {
+        match result {
+            Ok(_) => Code::Ok,
+            Err(status) => status.code(),
+        }
+    }
+
+    /// Run an RPC with a missing, a foreign, a valid, and a replayed token
+    async fn assert_enforced<F, Fut, T>(h: &Harness, action: ApprovalAction, call: F)
+    where
+        F: Fn(String) -> Fut,
+        Fut: Future<Output = Result<Response<T>, Status>>,
+    {
+        assert_eq!(code(call(String::new()).await), Code::FailedPrecondition, "missing token");
+        assert_eq!(code(call(h.foreign_token().await).await), Code::PermissionDenied, "foreign token");
+        assert_eq!(code(call("not-a-token".to_string()).await), Code::PermissionDenied, "invalid token");
+
+        let token = h.approve(&action).await;
+        let first = call(token.clone()).await;
+        assert!(first.is_ok(), "valid token rejected: {:?}", first.err());
+        assert_eq!(code(call(token).await), Code::PermissionDenied, "reused token");
+    }
+
+    #[tokio::test]
+    async fn test_create_file_enforces_approval() {
+        let h = Harness::new();
+        let svc = h.file_service();
+        let path = h.write("create.txt", "old");
+
+        let make = |token: String| CreateFileRequest {
+            path: path.clone(),
+            content: "new".to_string(),
+            approval_token: token,
+            ..Default::default()
+        };
+        let action = make(String::new()).approval_action();
+        assert_enforced(&h, action, |t| svc.create_file(Request::new(make(t)))).await;
+    }
+
+    #[tokio::test]
+    async fn test_append_file_enforces_approval() {
+        let h = Harness::new();
+        let svc = h.file_service();
+        let path = h.write("append.txt", "line\n");
+
+        let make = |token: String| AppendFileRequest {
+            path: path.clone(),
+            content: "more\n".to_string(),
+            approval_token: token,
+            ..Default::default()
+        };
+        let action = make(String::new()).approval_action();
+        assert_enforced(&h, action, |t| svc.append_file(Request::new(make(t)))).await;
+    }
+
+    #[tokio::test]
+    async fn test_apply_edits_enforces_approval() {
+        let h = Harness::new();
+        let svc = h.file_service();
+        let path = h.write("edit.txt", "old\n");
+
+        let make = |token: String| ApplyEditsRequest {
+            path: path.clone(),
+            base_sha256: "01d09d19c2139a46aebfb577780d123d7396e97201bc7ead210a2ebff8239dee".to_string(),
+            edits: vec![TextEdit { search: "old".to_string(), replace: "new".to_string(), replace_all: false }],
+            approval_token: token,
+            ..Default::default()
+        };
+        let action = make(String::new()).approval_action();
+        assert_enforced(&h, action, |t| svc.apply_edits(Request::new(make(t)))).await;
+    }
+
+    #[tokio::test]
+    async fn test_ask_rules_on_reads_are_enforced() {
+        let mut h = Harness::new();
+        let secrets = h.root.join("secrets");
+        std::fs::create_dir(&secrets).unwrap();
+        init_repo(&secrets);
+        let rules = RuleSet::from_json(&format!(
+            r#"{{ "rules": [ {{ "id": "ask-secrets", "effect": "ask", "paths": ["{}/**", "{}"] }} ] }}"#,
+            secrets.display(),
+            secrets.display()
+        )).unwrap();
+        h.policy = Arc::new(PolicyEngine::new(h.config.clone(), h.audit.clone()).with_rules(rules));
+        let files = h.file_service();
+        let git = h.git_service();
+
+        let key = h.write("secrets/key.txt", "hunter2");
+        let dir = secrets.to_string_lossy().to_string();
+
+        let make = |token: String| ReadFileRequest { path: key.clone(), approval_token: token, ..Default::default() };
+        let action = make(String::new()).approval_action();
+        assert_enforced(&h, action, |t| files.read_file(Request::new(make(t)))).await;
+
+        let make = |token: String| ListDirRequest { path: dir.clone(), approval_token: token, ..Default::default() };
+        let action = make(String::new()).approval_action();
+        assert_enforced(&h, action, |t| files.list_dir(Request::new(make(t)))).await;
+
+        let make = |token: String| StatRequest { path: key.clone(), approval_token: token };
+        let action = make(String::new()).approval_action();
+        assert_enforced(&h, action, |t| files.stat(Request::new(make(t)))).await;
+
+        let make = |token: String| GitStatusRequest { repo_path: dir.clone(), approval_token: token };
+        let action = make(String::new()).approval_action();
+        assert_enforced(&h, action, |t| git.status(Request::new(make(t)))).await;
+
+        // Reads elsewhere still need nothing
+        let readme = h.write("README.md", "# readme");
+        let plain = ReadFileRequest { path: readme, ..Default::default() };
+        assert!(files.read_file(Request::new(plain)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_file_tokens_are_bound_to_contents() {
+        let h = Harness::new();
+        let svc = h.file_service();
+        let path = h.write("bound.txt", "old\n");
+
+        let approved = CreateFileRequest { path: path.clone(), content: "approved".to_string(), ..Default::default() };
+        let token = h.approve(&approved.approval_action()).await;
+        let swapped = CreateFileRequest { content: "swapped".to_string(), approval_token: token, ..approved.clone() };
+        assert_eq!(code(svc.create_file(Request::new(swapped)).await), Code::PermissionDenied);
+
+        let approved = AppendFileRequest { path: path.clone(), content: "approved\n".to_string(), ..Default::default() };
+        let token = h.approve(&approved.approval_action()).await;
+        let swapped = AppendFileRequest { data: b"swapped\n".to_vec(), content: String::new(), approval_token: token, ..approved.clone() };
+        assert_eq!(code(svc.append_file(Request::new(swapped)).await), Code::PermissionDenied);
+
+        let edit = |replace: &str| TextEdit { search: "old".to_string(), replace: replace.to_string(), replace_all: false };
+        let approved = ApplyEditsRequest {
+            path: path.clone(),
+            base_sha256: "01d09d19c2139a46aebfb577780d123d7396e97201bc7ead210a2ebff8239dee".to_string(),
+            edits: vec![edit("approved")],
+            ..Default::default()
+        };
+        let token = h.approve(&approved.approval_action()).await;
+        let swapped = ApplyEditsRequest { edits: vec![edit("swapped")], approval_token: token, ..approved.clone() };
+        assert_eq!(code(svc.apply_edits(Request::new(swapped)).await), Code::PermissionDenied);
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "old\n");
+    }
+
+    #[tokio::test]
+    async fn test_move_file_enforces_approval() {
+        let h = Harness::new();
+        let svc = h.file_service();
+        let from = h.write("from.txt", "content");
+        let to = h.write("to.txt", "");
+
+        let make = |token: String| MoveFileRequest {
+            from_path: from.clone(),
+            to_path: to.clone(),
+            approval_token: token,
+            ..Default::default()
+        };
+        let action = make(String::new()).approval_action();
+        assert_enforced(&h, action, |t| {
+            // Recreate the source so a replay fails on the token, not the path
+            std::fs::write(&from, "content").unwrap();
+            svc.move_file(Request::new(make(t)))
+        }).await;
+    }
+
+    #[tokio::test]
+    async fn test_copy_file_enforces_approval() {
+        let h = Harness::new();
+        let svc = h.file_service();
+        let from = h.write("source.txt", "content");
+        let to = h.write("dest.txt", "");
+
+        let make = |token: String| CopyFileRequest {
+            from_path: from.clone(),
+            to_path: to.clone(),
+            approval_token: token,
+            ..Default::default()
+        };
+        let action = make(String::new()).approval_action();
+        assert_enforced(&h, action, |t| svc.copy_file(Request::new(make(t)))).await;
+    }
+
+    #[tokio::test]
+    async fn test_git_commit_enforces_approval() {
+        let h = Harness::new();
+        let svc = h.git_service();
+        let repo_path = h.root.join("repo");
+        std::fs::create_dir(&repo_path).unwrap();
+        init_repo(&repo_path);
+
+        let make = |token: String| GitCommitRequest {
+            repo_path: repo_path.to_string_lossy().to_string(),
+            message: "Initial commit".to_string(),
+            files: vec!["README.md".to_string()],
+            approval_token: token,
+        };
+        let action = make(String::new()).approval_action();
+        assert_enforced(&h, action, |t| svc.commit(Request::new(make(t)))).await;
+    }
+
+    #[tokio::test]
+    async fn test_create_branch_enforces_approval() {
+        let h = Harness::new();
+        let svc = h.git_service();
+        let repo_path = h.root.join("repo");
+        std::fs::create_dir(&repo_path).unwrap();
+        init_repo(&repo_path);
+
+        // Branches need a commit to point at
+        let commit = GitCommitRequest {
+            repo_path: repo_path.to_string_lossy().to_string(),
+            message: "Initial commit".to_string(),
+            files: vec!["README.md".to_string()],
+            ..Default::default()
+        };
+        let token = h.approve(&commit.approval_action()).await;
+        svc.commit(Request::new(GitCommitRequest { approval_token: token, ..commit })).await.unwrap();
+
+        let make = |token: String| CreateBranchRequest {
+            repo_path: repo_path.to_string_lossy().to_string(),
+            branch_name: "feature".to_string(),
+            approval_token: token,
+        };
+        let action = make(String::new()).approval_action();
+        assert_enforced(&h, action, |t| {
+            let repo = git2::Repository::open(&repo_path).unwrap();
+            if let Ok(mut branch) = repo.find_branch("feature", git2::BranchType::Local) {
+                branch.delete().unwrap();
+            }
+            svc.create_branch(Request::new(make(t)))
+        }).await;
+    }
+
+    #[tokio::test]
+    async fn test_run_command_enforces_approval() {
+        let h = Harness::new();
+        let svc = h.command_service();
+
+        let make = |token: String| RunCommandRequest {
+            command: "echo".to_string(),
+            args: vec!["hello".to_string()],
+            cwd: h.root.to_string_lossy().to_string(),
+            approval_token: token,
+            timeout_secs: 10,
+            ..Default::default()
+        };
+        let action = make(String::new()).approval_action();
+        assert_enforced(&h, action, |t| svc.run(Request::new(make(t)))).await;
+
+        // Dry-run previews never need a token
+        let preview = RunCommandRequest { dry_run: true, ..make(String::new()) };
+        assert!(svc.run(Request::new(preview)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_restore_snapshot_enforces_approval() {
+        let h = Harness::new();
+        let svc = h.snapshot_service();
+        let path = h.write("tracked.txt", "v1");
+        let snapshot = h.snapshots.create(&[PathBuf::from(&path)], "test").unwrap();
+
+        let make = |token: String| RestoreSnapshotRequest {
+            snapshot_id: snapshot.id.clone(),
+            approval_token: token,
+            ..Default::default()
+        };
+        let action = make(String::new()).approval_action();
+        assert_enforced(&h, action, |t| svc.restore(Request::new(make(t)))).await;
+    }
+
+    #[tokio::test]
+    async fn test_delete_snapshot_enforces_approval() {
+        let h = Harness::new();
+        let svc = h.snapshot_service();
+        let path = h.write("tracked.txt", "v1");
+        let snapshot = h.snapshots.create(&[PathBuf::from(&path)], "test").unwrap();
+
+        let make = |token: String| DeleteSnapshotRequest {
+            snapshot_id: snapshot.id.clone(),
+            approval_token: token,
+        };
+        let action = make(String::new()).approval_action();
+        assert_enforced(&h, action, |t| svc.delete(Request::new(make(t)))).await;
+    }
+}
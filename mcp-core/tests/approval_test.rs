@@ -0,0 +1,97 @@
+//! Unit tests for approval token issuance and validation
+
+#[cfg(test)]
+mod tests {
+    use mcp_core::{ApprovalAction, ApprovalStore, McpError};
+
+    fn create_action() -> ApprovalAction {
+        ApprovalAction::new("file", "create").with_paths(["/home/user/projects/app/index.js"])
+    }
+
+    #[test]
+    fn test_token_consumed_once() {
+        let store = ApprovalStore::new();
+        let issued = store.issue(&create_action(), 60);
+
+        assert!(store.consume(&issued.token, &create_action()).is_ok());
+
+        // Replaying the same token must fail
+        let replay = store.consume(&issued.token, &create_action());
+        assert!(matches!(replay, Err(McpError::InvalidApproval(_))));
+    }
+
+    #[test]
+    fn test_token_bound_to_action() {
+        let store = ApprovalStore::new();
+        let issued = store.issue(&create_action(), 60);
+
+        let other = ApprovalAction::new("file", "create").with_paths(["/home/user/.ssh/authorized_keys"]);
+        let result = store.consume(&issued.token, &other);
+        assert!(matches!(result, Err(McpError::InvalidApproval(_))));
+
+        // A mismatched presentation burns the token
+        assert!(store.consume(&issued.token, &create_action()).is_err());
+    }
+
+    #[test]
+    fn test_arguments_are_part_of_binding() {
+        let store = ApprovalStore::new();
+        let approved = ApprovalAction::new("command", "execute").with_arguments(["cargo", "test"]);
+        let issued = store.issue(&approved, 60);
+
+        let smuggled = ApprovalAction::new("command", "execute").with_arguments(["cargo", "publish"]);
+        assert!(store.consume(&issued.token, &smuggled).is_err());
+
+        // Joining arguments must not produce the same fingerprint
+        let split = ApprovalAction::new("command", "execute").with_arguments(["cargo test"]);
+        assert_ne!(approved.fingerprint(), split.fingerprint());
+    }
+
+    #[test]
+    fn test_expired_token_rejected() {
+        let store = ApprovalStore::new();
+        let issued = store.issue(&create_action(), 0);
+
+        let result = store.consume(&issued.token, &create_action());
+        assert!(matches!(result, Err(McpError::InvalidApproval(_))));
+    }
+
+    #[test]
+    fn test_missing_and_unknown_tokens() {
+        let store = ApprovalStore::new();
+
+        let missing = store.consume("", &create_action());
+        assert!(matches!(missing, Err(McpError::ApprovalRequired(_))));
+
+        let unknown = store.consume("not-a-real-token", &create_action());
+        assert!(matches!(unknown, Err(McpError::InvalidApproval(_))));
+    }
+
+    #[test]
+    fn test_fingerprint_normalizes_paths() {
+        let a = ApprovalAction::new("File", "move")
+            .with_paths(["/home/user/projects/a.txt", "/home/user/projects/b.txt"]);
+        let b = ApprovalAction::new("file", "move")
+            .with_paths(["/home/user/projects/./b.txt", "/home/user/projects/src/../a.txt"]);
+
+        assert_eq!(a.fingerprint(), b.fingerprint());
+    }
+
+    #[test]
+    fn test_preview_hash_is_part_of_binding() {
+        let store = ApprovalStore::new();
+        let previewed = create_action().with_preview_hash("ABC123");
+        let issued = store.issue(&previewed, 60);
+
+        // A token for one preview approves neither another nor an unbound action
+        assert!(store.consume(&issued.token, &create_action().with_preview_hash("def456")).is_err());
+        let issued = store.issue(&previewed, 60);
+        assert!(store.consume(&issued.token, &create_action()).is_err());
+
+        let issued = store.issue(&previewed, 60);
+        assert!(store.consume(&issued.token, &create_action().with_preview_hash("abc123")).is_ok());
+
+        // An empty hash binds nothing, keeping existing tokens valid
+        assert_eq!(create_action().with_preview_hash("").fingerprint(), create_action().fingerprint());
+    }
+}
@@ -0,0 +1,127 @@
+//! Tests for atomic file writes that keep permissions and ownership
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+    use tonic::{Code, Request};
+
+    use mcp_core::services::file_service::{file_service_server::FileService, *};
+    use mcp_core::{AuditLogger, Config, PolicyEngine, SnapshotManager};
+
+    struct Harness {
+        _dirs: [tempfile::TempDir; 2],
+        root: PathBuf,
+        svc: FileServiceImpl,
+        policy: Arc<PolicyEngine>,
+    }
+
+    impl Harness {
+        fn new() -> Self {
+            let dir = tempfile::tempdir().unwrap();
+            let root = dir.path().canonicalize().unwrap();
+            // State is kept outside the root, so the root holds only what tests write
+            let state_dir = tempfile::tempdir().unwrap();
+            let state = state_dir.path();
+            let config = Config {
+                allowed_paths: vec![root.clone()],
+                audit_db_path: state.join("audit.db"),
+                snapshot_dir: state.join("snapshots"),
+                ..Config::default()
+            };
+            let audit = Arc::new(AuditLogger::new(&config.audit_db_path).unwrap());
+            let snapshots = Arc::new(SnapshotManager::new(&config.snapshot_dir).unwrap());
+            let config = Arc::new(RwLock::new(config));
+            let policy = Arc::new(PolicyEngine::new(config.clone(), audit.clone()));
+            let svc = FileServiceImpl::new(config, audit, policy.clone(), snapshots);
+            Self { _dirs: [dir, state_dir], root, svc, policy }
+        }
+
+        async fn create(&self, path: &Path, content: &str, mode: &str) -> Result<CreateFileResponse, tonic::Status> {
+            let mut req = CreateFileRequest {
+                path: path.to_string_lossy().to_string(),
+                content: content.to_string(),
+                mode: mode.to_string(),
+                ..Default::default()
+            };
+            req.approval_token = self.policy.issue_approval(&req.approval_action()).await.token;
+            self.svc.create_file(Request::new(req)).await.map(|r| r.into_inner())
+        }
+    }
+
+    fn mode(path: &Path) -> u32 {
+        std::fs::metadata(path).unwrap().permissions().mode() & 0o7777
+    }
+
+    #[tokio::test]
+    async fn test_overwrite_is_atomic_and_keeps_permissions() {
+        let h = Harness::new();
+        let path = h.root.join("deploy.sh");
+        std::fs::write(&path, "old\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o750)).unwrap();
+        let inode = std::fs::metadata(&path).unwrap().ino();
+
+        // A mode only applies to new files
+        h.create(&path, "new\n", "600").await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new\n");
+        assert_eq!(mode(&path), 0o750);
+        // The file was replaced by a rename, leaving no temporary file behind
+        assert_ne!(std::fs::metadata(&path).unwrap().ino(), inode);
+        assert_eq!(std::fs::read_dir(&h.root).unwrap().count(), 1);
+
+        // Ownership is kept too, where the test may change it
+        if std::os::unix::fs::chown(&path, Some(4242), Some(4242)).is_ok() {
+            h.create(&path, "newer\n", "").await.unwrap();
+            let metadata = std::fs::metadata(&path).unwrap();
+            assert_eq!((metadata.uid(), metadata.gid()), (4242, 4242));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_new_files_get_the_requested_mode() {
+        let h = Harness::new();
+
+        let script = h.root.join("bin/run.sh");
+        h.create(&script, "#!/bin/sh\n", "0755").await.unwrap();
+        assert_eq!(mode(&script), 0o755);
+
+        let secret = h.root.join("token.txt");
+        h.create(&secret, "s3cret", "0o600").await.unwrap();
+        assert_eq!(mode(&secret), 0o600);
+
+        for invalid in ["4755", "999", "rwxr-xr-x"] {
+            let err = h.create(&h.root.join("bad"), "", invalid).await.unwrap_err();
+            assert_eq!(err.code(), Code::InvalidArgument, "{}", invalid);
+        }
+        assert!(!h.root.join("bad").exists());
+    }
+
+    #[tokio::test]
+    async fn test_files_in_read_only_directories_are_not_rewritten_in_place() {
+        let h = Harness::new();
+        let dir = h.root.join("locked");
+        std::fs::create_dir(&dir).unwrap();
+        let path = dir.join("shared.txt");
+        std::fs::write(&path, "old").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o666)).unwrap();
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o555)).unwrap();
+
+        // Privileged users can write to the directory anyway
+        let writable = std::fs::write(dir.join("probe"), "").is_ok();
+
+        let result = h.create(&path, "new", "").await;
+        if writable {
+            result.unwrap();
+        } else {
+            // The write fails instead of falling back to truncating the file
+            let err = result.unwrap_err();
+            assert!(err.message().contains("atomically"), "{}", err.message());
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), "old");
+            assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        }
+
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+}
@@ -0,0 +1,99 @@
+//! Tests for resolving whitelisted commands to pinned binaries
+
+mod common;
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::{Path, PathBuf};
+    use tonic::Request;
+
+    use crate::common::{self, Harness};
+    use mcp_core::binaries::sha256_file;
+    use mcp_core::services::command_service::{command_service_server::CommandService, *};
+    use mcp_core::CommandPin;
+
+    fn script(path: &Path, output: &str) -> PathBuf {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, format!("#!/bin/sh\necho {}\n", output)).unwrap();
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path.to_path_buf()
+    }
+
+    fn harness(root: &Path, whitelist: &[&str]) -> (Harness, CommandServiceImpl) {
+        let harness = Harness::new(root, whitelist, |config| {
+            config.auto_approve_patterns = whitelist.iter().map(|c| c.to_string()).collect();
+        });
+        let svc = harness.command_service();
+        (harness, svc)
+    }
+
+    async fn run(svc: &CommandServiceImpl, cwd: &Path, command: &str) -> Result<RunCommandResponse, tonic::Status> {
+        let req = common::command(cwd, command, &[]);
+        svc.run(Request::new(req)).await.map(|r| r.into_inner())
+    }
+
+    #[tokio::test]
+    async fn test_relative_path_entries_are_not_searched() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let real = script(&root.join("bin/mcp-pin-tool"), "real");
+        script(&root.join("node_modules/.bin/mcp-pin-tool"), "fake");
+
+        let path = std::env::var("PATH").unwrap_or_default();
+        std::env::set_var("PATH", format!("node_modules/.bin:{}:{}", root.join("bin").display(), path));
+
+        let (harness, svc) = harness(&root, &["mcp-pin-tool"]);
+        let response = run(&svc, &root, "mcp-pin-tool").await.unwrap();
+        assert_eq!(response.stdout, "real\n");
+
+        let logs = harness.audit.query(Some("command"), Some("execute"), None, None, 10).unwrap();
+        assert!(logs[0].details.contains(&format!("[binary: {}]", real.display())), "{}", logs[0].details);
+
+        // Path-qualified commands must be whitelisted as such
+        let fake = root.join("node_modules/.bin/mcp-pin-tool");
+        let err = run(&svc, &root, &fake.to_string_lossy()).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+        let err = run(&svc, &root, "./bin/mcp-pin-tool").await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+
+        std::env::set_var("PATH", path);
+    }
+
+    #[tokio::test]
+    async fn test_pinned_binary_must_match_hash() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let tool = script(&root.join("pinned/tool"), "pinned");
+        let sha256 = sha256_file(&tool).unwrap();
+
+        let (harness, svc) = harness(&root, &["mcp-pinned-tool"]);
+        {
+            let mut config = harness.config.write().await;
+            config.command_pins.insert(
+                "mcp-pinned-tool".to_string(),
+                CommandPin { path: Some(tool.clone()), sha256: Some(sha256.to_uppercase()) },
+            );
+            config.auto_approve_patterns.push(tool.to_string_lossy().to_string());
+        }
+
+        assert_eq!(run(&svc, &root, "mcp-pinned-tool").await.unwrap().stdout, "pinned\n");
+        // The pinned path itself is allowed too
+        assert_eq!(run(&svc, &root, &tool.to_string_lossy()).await.unwrap().stdout, "pinned\n");
+
+        let listed = svc.list_whitelisted(Request::new(ListWhitelistedRequest {})).await.unwrap().into_inner();
+        assert_eq!(listed.resolved.len(), 1);
+        assert_eq!(listed.resolved[0].path, tool.to_string_lossy());
+        assert_eq!(listed.resolved[0].sha256, sha256);
+
+        // A modified binary no longer runs
+        script(&tool, "tampered");
+        let err = run(&svc, &root, "mcp-pinned-tool").await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+        assert!(err.message().contains("pinned sha256"), "{}", err.message());
+
+        let listed = svc.list_whitelisted(Request::new(ListWhitelistedRequest {})).await.unwrap().into_inner();
+        assert!(listed.resolved[0].path.is_empty());
+        assert!(!listed.resolved[0].error.is_empty());
+    }
+}
@@ -0,0 +1,165 @@
+//! Tests for interactive commands over CommandService.RunInteractive
+
+mod common;
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::path::Path;
+    use tokio::sync::mpsc;
+    use tokio_stream::wrappers::ReceiverStream;
+    use tonic::transport::{server::TcpIncoming, Channel, Server};
+
+    use crate::common;
+    use mcp_core::services::command_service::{
+        command_service_client::CommandServiceClient,
+        command_service_server::CommandServiceServer,
+        *,
+    };
+
+    struct Harness {
+        client: CommandServiceClient<Channel>,
+        base: common::Harness,
+    }
+
+    async fn serve(root: &Path) -> Harness {
+        let base = common::Harness::new(root, &["sh", "cat"], |_| {});
+        let svc = base.command_service();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+        tokio::spawn(Server::builder()
+            .add_service(CommandServiceServer::new(svc))
+            .serve_with_incoming(incoming));
+
+        let client = CommandServiceClient::connect(format!("http://{}", addr)).await.unwrap();
+        Harness { client, base }
+    }
+
+    async fn start(
+        harness: &Harness,
+        root: &Path,
+        command: &str,
+        args: &[&str],
+        redact_stdin: bool,
+        pty: Option<TerminalSize>,
+    ) -> InteractiveInput {
+        let req = harness.base.approve(common::command(root, command, args)).await;
+        InteractiveInput {
+            input: Some(interactive_input::Input::Start(InteractiveStart { command: Some(req), redact_stdin, pty })),
+        }
+    }
+
+    fn stdin(data: &str) -> InteractiveInput {
+        InteractiveInput { input: Some(interactive_input::Input::Stdin(data.as_bytes().to_vec())) }
+    }
+
+    #[tokio::test]
+    async fn test_stdin_is_forwarded_and_transcribed() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let mut harness = serve(&root).await;
+
+        let (tx, rx) = mpsc::channel(8);
+        tx.send(start(&harness, &root, "sh", &["-c", "echo 'name?'; read name; echo hello $name"], false, None).await).await.unwrap();
+        let mut events = harness.client.run_interactive(ReceiverStream::new(rx)).await.unwrap().into_inner();
+
+        // Answer the prompt only once it has been shown
+        let prompt = events.message().await.unwrap().unwrap();
+        assert_eq!(prompt.event, Some(command_event::Event::Stdout(b"name?\n".to_vec())));
+        tx.send(stdin("world\n")).await.unwrap();
+
+        let mut stdout = Vec::new();
+        let mut exit = None;
+        while let Some(event) = events.message().await.unwrap() {
+            match event.event.unwrap() {
+                command_event::Event::Stdout(data) => stdout.extend_from_slice(&data),
+                command_event::Event::Exit(e) => exit = Some(e),
+                _ => {}
+            }
+        }
+        assert_eq!(stdout, b"hello world\n");
+        assert!(exit.unwrap().success);
+
+        let logs = harness.base.audit.query(Some("command"), Some("execute"), None, None, 10).unwrap();
+        assert_eq!(logs.len(), 1);
+        assert!(
+            logs[0].details.contains("[stdout]\nname?\n[stdin]\nworld\n[stdout]\nhello world\n"),
+            "details: {}",
+            logs[0].details
+        );
+    }
+
+    #[tokio::test]
+    async fn test_redacted_stdin_and_close() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let mut harness = serve(&root).await;
+
+        let (tx, rx) = mpsc::channel(8);
+        tx.send(start(&harness, &root, "cat", &[], true, None).await).await.unwrap();
+        tx.send(stdin("hunter2")).await.unwrap();
+        tx.send(InteractiveInput { input: Some(interactive_input::Input::CloseStdin(true)) }).await.unwrap();
+
+        // The request stream stays open; closing stdin alone lets `cat` exit
+        let mut events = harness.client.run_interactive(ReceiverStream::new(rx)).await.unwrap().into_inner();
+        let mut stdout = Vec::new();
+        while let Some(event) = events.message().await.unwrap() {
+            if let Some(command_event::Event::Stdout(data)) = event.event {
+                stdout.extend_from_slice(&data);
+            }
+        }
+        assert_eq!(stdout, b"hunter2");
+        drop(tx);
+
+        let logs = harness.base.audit.query(Some("command"), Some("execute"), None, None, 10).unwrap();
+        assert_eq!(logs[0].result, "success");
+        assert!(logs[0].details.contains("<7 bytes redacted>"), "details: {}", logs[0].details);
+        // Only the echoed output is recorded, not the input itself
+        assert_eq!(logs[0].details.matches("hunter2").count(), 1);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_pty_mode_with_resize() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let mut harness = serve(&root).await;
+
+        let script = "test -t 0 && test -t 1 && echo tty; stty size; read line; stty size";
+        let size = TerminalSize { rows: 24, cols: 80 };
+        let (tx, rx) = mpsc::channel(8);
+        tx.send(start(&harness, &root, "sh", &["-c", script], false, Some(size)).await).await.unwrap();
+        let mut events = harness.client.run_interactive(ReceiverStream::new(rx)).await.unwrap().into_inner();
+
+        let mut output = String::new();
+        let mut resized = false;
+        while let Some(event) = events.message().await.unwrap() {
+            if let Some(command_event::Event::Stdout(data)) = event.event {
+                output.push_str(&String::from_utf8_lossy(&data));
+            }
+            // Resize once the initial size has been reported, then let `read` return
+            if !resized && output.contains("24 80") {
+                resized = true;
+                let size = TerminalSize { rows: 40, cols: 100 };
+                tx.send(InteractiveInput { input: Some(interactive_input::Input::Resize(size)) }).await.unwrap();
+                tx.send(stdin("\n")).await.unwrap();
+            }
+        }
+
+        // The terminal translates newlines to CRLF
+        assert!(output.starts_with("tty\r\n24 80\r\n"), "output: {:?}", output);
+        assert!(output.ends_with("40 100\r\n"), "output: {:?}", output);
+    }
+
+    #[tokio::test]
+    async fn test_first_message_must_start_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let mut harness = serve(&root).await;
+
+        let input = tokio_stream::iter(vec![stdin("too early")]);
+        let err = harness.client.run_interactive(input).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    }
+}
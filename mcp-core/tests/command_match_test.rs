@@ -0,0 +1,158 @@
+//! Unit tests for argument-aware command matching
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    use mcp_core::{AuditLogger, CommandPattern, Config, ParsedCommand, PolicyDecision, PolicyEngine};
+
+    fn parse(command_line: &str) -> ParsedCommand {
+        let mut tokens = command_line.split_whitespace();
+        let command = tokens.next().unwrap();
+        let args: Vec<String> = tokens.map(str::to_string).collect();
+        ParsedCommand::parse(command, &args)
+    }
+
+    fn pattern(source: &str) -> CommandPattern {
+        CommandPattern::parse(source).unwrap()
+    }
+
+    #[test]
+    fn test_sensitive_flag_spellings() {
+        let rm_rf = pattern("rm -rf");
+        for command in [
+            "rm -rf /",
+            "rm -r -f /",
+            "rm -fr /",
+            "rm --recursive --force /",
+            "rm -R -f /",
+            "rm -v -r --force /tmp/x",
+            "/usr/bin/rm -rf /",
+        ] {
+            assert!(rm_rf.matches(&parse(command)), "'{}' should match 'rm -rf'", command);
+        }
+
+        for command in ["rm -r /tmp/x", "rm -f file", "rmdir -rf x", "echo rm -rf /"] {
+            assert!(!rm_rf.matches(&parse(command)), "'{}' should not match 'rm -rf'", command);
+        }
+
+        let force_push = pattern("git push --force");
+        for command in [
+            "git push --force",
+            "git push -f",
+            "git push origin main --force",
+            "git -C repo push -f",
+            "git push --force-with-lease",
+            "git push --force-with-lease=main origin main",
+            "git push --force-if-includes origin main",
+        ] {
+            assert!(force_push.matches(&parse(command)), "'{}' should match 'git push --force'", command);
+        }
+        assert!(!force_push.matches(&parse("git push origin main")));
+        assert!(!force_push.matches(&parse("git commit -m --force")));
+
+        // Positional arguments in a pattern are globs
+        let force_refspec = pattern("git push +*");
+        assert!(force_refspec.matches(&parse("git push origin +main")));
+        assert!(force_refspec.matches(&parse("git push origin +HEAD:refs/heads/main")));
+        assert!(!force_refspec.matches(&parse("git push origin main")));
+    }
+
+    #[test]
+    fn test_no_substring_false_positives() {
+        // The old substring check flagged anything containing "format"
+        let format = pattern("format");
+        assert!(format.matches(&parse("format C:")));
+        assert!(!format.matches(&parse("cargo fmt -- --format")));
+        assert!(!format.matches(&parse("clang-format -i main.c")));
+        assert!(!format.matches(&parse("git log --format=%H")));
+
+        let status = pattern("git status");
+        assert!(!status.matches(&parse("git statusx")));
+        assert!(!status.matches(&parse("git log status")));
+    }
+
+    #[test]
+    fn test_auto_approve_rejects_smuggled_arguments() {
+        let status = pattern("git status");
+        assert!(status.allows(&parse("git status")));
+        assert!(status.allows(&parse("git status --short")));
+
+        // Shell metacharacters are just arguments, and never turn into a new command
+        let args: Vec<String> = ["status;", "rm", "-rf", "/"].iter().map(|s| s.to_string()).collect();
+        assert!(!status.allows(&ParsedCommand::parse("git", &args)));
+
+        // Global options can run arbitrary programs before the subcommand
+        assert!(!status.allows(&parse("git -c core.pager=sh status")));
+        assert!(!status.allows(&parse("git -c core.fsmonitor=./evil status")));
+        assert!(!status.allows(&parse("git --exec-path=/tmp status")));
+
+        // Read-only subcommands with flags that write files or run helpers
+        let diff = pattern("git diff");
+        assert!(diff.allows(&parse("git diff HEAD~1")));
+        assert!(!diff.allows(&parse("git diff --output=/home/user/.bashrc")));
+        assert!(!diff.allows(&parse("git diff --ext-diff")));
+
+        let log = pattern("git log");
+        assert!(log.allows(&parse("git log --oneline -n 5")));
+        assert!(!log.allows(&parse("git log --output /tmp/x")));
+
+        let list = pattern("npm list");
+        assert!(list.allows(&parse("npm list --depth=0")));
+        assert!(!list.allows(&parse("npm --prefix /tmp/evil list")));
+        assert!(!list.allows(&parse("npm install")));
+    }
+
+    #[test]
+    fn test_option_values_are_not_flags() {
+        // `-m` consumes its value, so a message mentioning --force is not a flag
+        let parsed = parse("git commit -m --force");
+        assert_eq!(parsed.subcommand.as_deref(), Some("commit"));
+        assert!(parsed.flags.contains("-m"));
+        assert!(!parsed.flags.contains("-f"));
+
+        // Everything after `--` is positional
+        let parsed = parse("cargo fmt -- --format -rf");
+        assert_eq!(parsed.subcommand.as_deref(), Some("fmt"));
+        assert!(parsed.flags.is_empty());
+        assert_eq!(parsed.positionals, vec!["--format", "-rf"]);
+
+        // Global options with values don't become the subcommand
+        let parsed = parse("git -C /repo log");
+        assert_eq!(parsed.subcommand.as_deref(), Some("log"));
+        assert!(parsed.global_flags.contains("-C"));
+    }
+
+    #[tokio::test]
+    async fn test_policy_engine_uses_parsed_matching() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            allowed_paths: vec![dir.path().to_path_buf()],
+            audit_db_path: dir.path().join("audit.db"),
+            whitelisted_commands: vec!["git".to_string(), "rm".to_string(), "cargo".to_string()],
+            ..Config::default()
+        };
+        let audit = Arc::new(AuditLogger::new(&config.audit_db_path).unwrap());
+        let policy = PolicyEngine::new(Arc::new(RwLock::new(config)), audit);
+
+        let check = |command: &'static str, args: &'static [&'static str]| {
+            let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+            let policy = &policy;
+            async move { policy.check_command(command, &args, None).await.unwrap().decision }
+        };
+
+        assert!(matches!(check("git", &["status", "--short"]).await, PolicyDecision::Allow));
+        assert!(matches!(check("git", &["-c", "core.pager=sh", "status"]).await, PolicyDecision::RequireApproval(_)));
+        assert!(matches!(check("git", &["status;", "rm", "-rf", "/"]).await, PolicyDecision::RequireApproval(_)));
+
+        match check("rm", &["--recursive", "--force", "/tmp/x"]).await {
+            PolicyDecision::RequireApproval(reason) => assert!(reason.starts_with("Sensitive command detected")),
+            other => panic!("Expected sensitive approval, got {:?}", other),
+        }
+        match check("cargo", &["fmt", "--", "--format"]).await {
+            PolicyDecision::RequireApproval(reason) => assert!(reason.starts_with("Execute command")),
+            other => panic!("Expected default approval, got {:?}", other),
+        }
+    }
+}
@@ -4,7 +4,7 @@
 mod tests {
     #[test]
     fn test_command_whitelist() {
-        let whitelist = vec![
+        let whitelist = [
             "git", "npm", "pnpm", "yarn", "node", "python", "cargo", "rustc"
         ];
 
@@ -27,7 +27,7 @@ mod tests {
 
     #[test]
     fn test_sensitive_command_detection() {
-        let sensitive_patterns = vec![
+        let sensitive_patterns = [
             "rm -rf",
             "del /s",
             "format",
@@ -50,7 +50,7 @@ mod tests {
 
     #[test]
     fn test_auto_approve_patterns() {
-        let auto_approve = vec![
+        let auto_approve = [
             "git status",
             "git log",
             "git diff",
@@ -0,0 +1,149 @@
+//! Tests for snapshots taken before commands run
+
+mod common;
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::path::Path;
+    use std::sync::Arc;
+    use tokio_stream::StreamExt;
+    use tonic::Request;
+
+    use crate::common;
+    use mcp_core::services::command_service::{command_service_server::CommandService, *};
+    use mcp_core::SnapshotManager;
+
+    struct Harness {
+        base: common::Harness,
+        svc: CommandServiceImpl,
+        snapshots: Arc<SnapshotManager>,
+    }
+
+    fn harness(root: &Path, rules: &str) -> Harness {
+        let state = root.join("state");
+        let base = common::Harness::new(root, &["sh", "touch", "ls"], |config| {
+            config.audit_db_path = state.join("audit.db");
+            config.snapshot_dir = state.join("snapshots");
+        })
+        .with_rules(rules);
+        let snapshots = Arc::new(SnapshotManager::new(&state.join("snapshots")).unwrap());
+        let svc = base.command_service().with_snapshots(snapshots.clone());
+        Harness { base, svc, snapshots }
+    }
+
+    fn project(root: &Path) -> std::path::PathBuf {
+        let project = root.join("project");
+        std::fs::create_dir_all(project.join("node_modules/dep")).unwrap();
+        std::fs::write(project.join("a.txt"), "original\n").unwrap();
+        std::fs::write(project.join("node_modules/dep/index.js"), "x").unwrap();
+        project
+    }
+
+    async fn run(harness: &Harness, cwd: &Path, command: &str, args: &[&str]) -> RunCommandResponse {
+        let req = harness.base.approve(common::command(cwd, command, args)).await;
+        harness.svc.run(Request::new(req)).await.unwrap().into_inner()
+    }
+
+    #[tokio::test]
+    async fn test_mutating_command_is_snapshotted() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let project = project(&root);
+        let harness = harness(&root, r#"{ "rules": [] }"#);
+
+        let response = run(&harness, &project, "touch", &["b.txt"]).await;
+        assert!(response.success);
+        assert!(!response.snapshot_id.is_empty());
+
+        // Ignored directories are left out
+        let snapshot = harness.snapshots.get(&response.snapshot_id).unwrap();
+        assert!(snapshot.files.contains_key(&project.join("a.txt")));
+        assert!(!snapshot.files.keys().any(|p| p.starts_with(project.join("node_modules"))));
+
+        let logs = harness.base.audit.query(Some("command"), Some("execute"), None, None, 10).unwrap();
+        assert_eq!(logs[0].snapshot_id.as_deref(), Some(response.snapshot_id.as_str()));
+
+        // Commands not predicted to change files are not snapshotted
+        let response = run(&harness, &project, "ls", &[]).await;
+        assert!(response.success);
+        assert!(response.snapshot_id.is_empty());
+        assert_eq!(harness.snapshots.list().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rules_decide_snapshots() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let project = project(&root);
+        let harness = harness(&root, r#"{
+            "rules": [
+                { "id": "sh-snapshot", "effect": "ask", "argv": ["sh", "**"], "snapshot": true },
+                { "id": "touch-no-snapshot", "effect": "ask", "argv": ["touch", "**"], "snapshot": false }
+            ]
+        }"#);
+
+        let response = run(&harness, &project, "sh", &["-c", "echo changed > a.txt"]).await;
+        assert!(response.success);
+        assert!(!response.snapshot_id.is_empty());
+        assert_eq!(std::fs::read_to_string(project.join("a.txt")).unwrap(), "changed\n");
+
+        // The snapshot rolls the command back
+        harness.snapshots.restore(&response.snapshot_id, None).unwrap();
+        assert_eq!(std::fs::read_to_string(project.join("a.txt")).unwrap(), "original\n");
+
+        let response = run(&harness, &project, "touch", &["b.txt"]).await;
+        assert!(response.snapshot_id.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_streamed_command_is_snapshotted() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let project = project(&root);
+        let harness = harness(&root, r#"{ "rules": [] }"#);
+
+        let req = harness.base.approve(common::command(&project, "touch", &["b.txt"])).await;
+        let events: Vec<CommandEvent> = harness.svc.run_stream(Request::new(req)).await.unwrap()
+            .into_inner()
+            .map(|e| e.unwrap())
+            .collect()
+            .await;
+        let exit = match events.last().unwrap().event.as_ref().unwrap() {
+            command_event::Event::Exit(exit) => exit.clone(),
+            other => panic!("expected exit event, got {:?}", other),
+        };
+        assert!(exit.success);
+        assert!(!exit.snapshot_id.is_empty());
+        assert!(harness.snapshots.get(&exit.snapshot_id).unwrap().files.contains_key(&project.join("a.txt")));
+
+        let logs = harness.base.audit.query(Some("command"), Some("execute"), None, None, 10).unwrap();
+        assert_eq!(logs[0].snapshot_id.as_deref(), Some(exit.snapshot_id.as_str()));
+    }
+
+    #[tokio::test]
+    async fn test_job_is_snapshotted() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let project = project(&root);
+        let harness = harness(&root, r#"{
+            "rules": [{ "id": "sh-snapshot", "effect": "ask", "argv": ["sh", "**"], "snapshot": true }]
+        }"#);
+
+        let req = harness.base.approve(common::sh(&project, "echo changed > a.txt")).await;
+        let job = harness.svc.start_job(Request::new(req)).await.unwrap().into_inner();
+        assert!(!job.snapshot_id.is_empty());
+
+        let logs = harness.base.audit.query(Some("job"), Some("start"), None, None, 10).unwrap();
+        assert_eq!(logs[0].snapshot_id.as_deref(), Some(job.snapshot_id.as_str()));
+
+        // The snapshot was taken before the job could change anything
+        let listed = harness.svc.list_jobs(Request::new(ListJobsRequest { include_finished: true }))
+            .await.unwrap().into_inner();
+        assert_eq!(listed.jobs[0].snapshot_id, job.snapshot_id);
+        let tail = TailJobRequest { job_id: job.job_id.clone(), offset: 0, follow: true };
+        let _: Vec<_> = harness.svc.tail_job(Request::new(tail)).await.unwrap().into_inner().collect().await;
+        assert_eq!(std::fs::read_to_string(project.join("a.txt")).unwrap(), "changed\n");
+        harness.snapshots.restore(&job.snapshot_id, None).unwrap();
+        assert_eq!(std::fs::read_to_string(project.join("a.txt")).unwrap(), "original\n");
+    }
+}
@@ -0,0 +1,104 @@
+//! Tests for streaming command output in CommandService
+
+mod common;
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use tokio_stream::StreamExt;
+    use tonic::Request;
+
+    use crate::common::{self, Harness};
+    use mcp_core::services::command_service::{command_service_server::CommandService, *};
+    use mcp_core::AuditLogger;
+
+    fn setup(root: &Path) -> (CommandServiceImpl, Arc<AuditLogger>, Harness) {
+        let harness = Harness::new(root, &["sh"], |_| {});
+        (harness.command_service(), harness.audit.clone(), harness)
+    }
+
+    async fn approved_request(harness: &Harness, root: &Path, script: &str) -> RunCommandRequest {
+        harness.approve(RunCommandRequest { timeout_secs: 30, ..common::sh(root, script) }).await
+    }
+
+    #[tokio::test]
+    async fn test_stream_emits_ordered_output_heartbeat_and_exit() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let (svc, audit, harness) = setup(&root);
+
+        let req = approved_request(&harness, &root, "echo one; sleep 0.2; echo two >&2; sleep 5.5; echo three").await;
+        let stream = svc.run_stream(Request::new(req)).await.unwrap().into_inner();
+        let events: Vec<CommandEvent> = stream.map(|e| e.unwrap()).collect().await;
+
+        let sequences: Vec<u64> = events.iter().map(|e| e.sequence).collect();
+        assert_eq!(sequences, (0..events.len() as u64).collect::<Vec<_>>());
+
+        let mut output = Vec::new();
+        let mut heartbeats = 0;
+        for event in &events[..events.len() - 1] {
+            match event.event.as_ref().unwrap() {
+                command_event::Event::Stdout(data) => output.push(format!("out:{}", String::from_utf8_lossy(data).trim())),
+                command_event::Event::Stderr(data) => output.push(format!("err:{}", String::from_utf8_lossy(data).trim())),
+                command_event::Event::Heartbeat(_) => heartbeats += 1,
+                command_event::Event::Exit(_) => panic!("exit event before the end of the stream"),
+            }
+        }
+        assert_eq!(output, ["out:one", "err:two", "out:three"]);
+        assert!(heartbeats >= 1);
+
+        match events.last().unwrap().event.as_ref().unwrap() {
+            command_event::Event::Exit(exit) => {
+                assert!(exit.success);
+                assert_eq!(exit.exit_code, 0);
+                assert!(!exit.timed_out);
+            }
+            other => panic!("expected exit event, got {:?}", other),
+        }
+
+        let logs = audit.query(Some("command"), Some("execute"), None, None, 10).unwrap();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].result, "success");
+        assert!(logs[0].user_approved);
+    }
+
+    #[tokio::test]
+    async fn test_stream_requires_approval() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let (svc, _audit, harness) = setup(&root);
+
+        let mut req = approved_request(&harness, &root, "echo hi").await;
+        req.approval_token = String::new();
+        let err = svc.run_stream(Request::new(req)).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+    }
+
+    #[tokio::test]
+    async fn test_dropping_stream_cancels_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let (svc, audit, harness) = setup(&root);
+
+        let req = approved_request(&harness, &root, "echo started; sleep 30").await;
+        let mut stream = svc.run_stream(Request::new(req)).await.unwrap().into_inner();
+        let first = stream.next().await.unwrap().unwrap();
+        assert!(matches!(first.event, Some(command_event::Event::Stdout(_))));
+
+        let started = Instant::now();
+        drop(stream);
+
+        // The command is killed and recorded as cancelled
+        loop {
+            let logs = audit.query(Some("command"), Some("execute"), None, None, 10).unwrap();
+            if let Some(entry) = logs.first() {
+                assert_eq!(entry.result, "cancelled");
+                break;
+            }
+            assert!(started.elapsed() < Duration::from_secs(10), "command was not cancelled");
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+}
@@ -0,0 +1,36 @@
+//! Tests for command timeouts in CommandService
+
+mod common;
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::time::{Duration, Instant};
+    use tonic::Request;
+
+    use crate::common::{self, Harness};
+    use mcp_core::services::command_service::{command_service_server::CommandService, *};
+
+    #[tokio::test]
+    async fn test_timed_out_command_is_reported_and_audited() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let harness = Harness::new(&root, &["sh"], |_| {});
+        let svc = harness.command_service();
+
+        let req = RunCommandRequest { timeout_secs: 1, ..common::sh(&root, "echo waiting; sleep 30") };
+        let req = harness.approve(req).await;
+
+        let started = Instant::now();
+        let response = svc.run(Request::new(req)).await.unwrap().into_inner();
+
+        assert!(response.timed_out);
+        assert!(!response.success);
+        assert_eq!(response.stdout.trim(), "waiting");
+        assert!(started.elapsed() < Duration::from_secs(10));
+
+        let logs = harness.audit.query(Some("command"), Some("execute"), None, None, 10).unwrap();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].result, "timed_out");
+        assert!(logs[0].details.contains("Timed out after 1s"), "details: {}", logs[0].details);
+    }
+}
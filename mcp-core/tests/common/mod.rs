@@ -0,0 +1,71 @@
+//! Fixtures shared by the command service tests
+//!
+//! Requests are built with `..Default::default()`, so a new request field
+//! only touches the tests that set it.
+
+#![allow(dead_code)]
+
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+use mcp_core::services::command_service::{CommandServiceImpl, RunCommandRequest};
+use mcp_core::{AuditLogger, Config, PolicyEngine, RuleSet};
+
+/// The configuration, audit log and policy a command service runs on
+pub struct Harness {
+    pub config: Arc<RwLock<Config>>,
+    pub audit: Arc<AuditLogger>,
+    pub policy: Arc<PolicyEngine>,
+}
+
+impl Harness {
+    /// Confine commands to `root` and whitelist `commands`; `configure` can
+    /// change anything else
+    pub fn new(root: &Path, commands: &[&str], configure: impl FnOnce(&mut Config)) -> Self {
+        let mut config = Config {
+            allowed_paths: vec![root.to_path_buf()],
+            whitelisted_commands: commands.iter().map(|c| c.to_string()).collect(),
+            audit_db_path: root.join("audit.db"),
+            ..Config::default()
+        };
+        configure(&mut config);
+        let audit = Arc::new(AuditLogger::new(&config.audit_db_path).unwrap());
+        let config = Arc::new(RwLock::new(config));
+        let policy = Arc::new(PolicyEngine::new(config.clone(), audit.clone()));
+        Self { config, audit, policy }
+    }
+
+    /// Evaluate the given JSON rules before the built-in ones
+    pub fn with_rules(self, rules: &str) -> Self {
+        let policy = PolicyEngine::new(self.config.clone(), self.audit.clone())
+            .with_rules(RuleSet::from_json(rules).unwrap());
+        Self { policy: Arc::new(policy), ..self }
+    }
+
+    pub fn command_service(&self) -> CommandServiceImpl {
+        CommandServiceImpl::new(self.config.clone(), self.audit.clone(), self.policy.clone())
+    }
+
+    /// The request with a token approving exactly it
+    pub async fn approve(&self, mut req: RunCommandRequest) -> RunCommandRequest {
+        req.approval_token = self.policy.issue_approval(&req.approval_action()).await.token;
+        req
+    }
+}
+
+/// A request to run `command` in `cwd` with a 10 second timeout
+pub fn command(cwd: &Path, command: &str, args: &[&str]) -> RunCommandRequest {
+    RunCommandRequest {
+        command: command.to_string(),
+        args: args.iter().map(|a| a.to_string()).collect(),
+        cwd: cwd.to_string_lossy().to_string(),
+        timeout_secs: 10,
+        ..Default::default()
+    }
+}
+
+/// A request to run `sh -c script` in `cwd`
+pub fn sh(cwd: &Path, script: &str) -> RunCommandRequest {
+    command(cwd, "sh", &["-c", script])
+}
@@ -0,0 +1,248 @@
+//! Unit tests for path confinement
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use tempfile::TempDir;
+    use tokio::sync::RwLock;
+    use tonic::Request;
+
+    use mcp_core::services::file_service::{file_service_server::FileService, *};
+    use mcp_core::{AuditLogger, Confinement, Config, OpenMode, PolicyEngine, SnapshotManager};
+
+    /// An allowed root next to a directory outside of it
+    fn setup() -> (TempDir, PathBuf, PathBuf, Confinement) {
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let base = dir.path().canonicalize().unwrap();
+        let root = base.join("root");
+        let outside = base.join("outside");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::write(outside.join("secret.txt"), "secret").unwrap();
+        let confinement = Confinement::new(std::slice::from_ref(&root));
+        (dir, root, outside, confinement)
+    }
+
+    #[test]
+    fn test_new_paths_resolve_through_existing_ancestor() {
+        let (_dir, root, _outside, confinement) = setup();
+
+        let target = confinement.resolve(&root.join("a/b/new.txt")).unwrap();
+        assert_eq!(target.path(), root.join("a/b/new.txt"));
+        assert_eq!(target.relative(), PathBuf::from("a/b/new.txt"));
+
+        target.create_parents().unwrap();
+        target.open(OpenMode::Write).unwrap().write_all(b"hello").unwrap();
+        assert_eq!(std::fs::read_to_string(root.join("a/b/new.txt")).unwrap(), "hello");
+    }
+
+    #[test]
+    fn test_parent_dir_escapes_rejected() {
+        let (_dir, root, _outside, confinement) = setup();
+
+        assert!(confinement.resolve(&root.join("../outside/secret.txt")).is_err());
+        assert!(confinement.resolve(&root.join("sub/../../outside/new.txt")).is_err());
+        assert!(confinement.resolve(&root.join("sub/../inside.txt")).is_ok());
+        assert!(confinement.resolve(&PathBuf::from("relative.txt")).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinks_out_of_root_rejected() {
+        use std::os::unix::fs::symlink;
+        let (_dir, root, outside, confinement) = setup();
+
+        symlink(outside.join("secret.txt"), root.join("file-link")).unwrap();
+        symlink(&outside, root.join("dir-link")).unwrap();
+        symlink(outside.join("missing.txt"), root.join("dangling-link")).unwrap();
+
+        assert!(confinement.resolve(&root.join("file-link")).is_err());
+        assert!(confinement.resolve(&root.join("dir-link/secret.txt")).is_err());
+        assert!(confinement.resolve(&root.join("dir-link/new.txt")).is_err());
+        assert!(confinement.resolve(&root.join("dangling-link")).is_err());
+
+        // Symlinks that stay inside the root resolve to their target
+        std::fs::write(root.join("real.txt"), "real").unwrap();
+        symlink(root.join("real.txt"), root.join("inner-link")).unwrap();
+        let target = confinement.resolve(&root.join("inner-link")).unwrap();
+        assert_eq!(target.path(), root.join("real.txt"));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_symlink_swapped_in_after_check_is_not_followed() {
+        use std::os::unix::fs::symlink;
+        let (_dir, root, outside, confinement) = setup();
+
+        std::fs::create_dir(root.join("docs")).unwrap();
+        std::fs::write(root.join("docs/secret.txt"), "inside").unwrap();
+        let target = confinement.resolve(&root.join("docs/secret.txt")).unwrap();
+
+        // Replace the checked directory with a symlink to outside the root
+        std::fs::rename(root.join("docs"), root.join("docs-old")).unwrap();
+        symlink(&outside, root.join("docs")).unwrap();
+
+        assert!(target.open(OpenMode::Read).is_err());
+        assert!(target.open(OpenMode::Write).is_err());
+        assert_eq!(std::fs::read_to_string(outside.join("secret.txt")).unwrap(), "secret");
+
+        // Swapping the leaf itself is caught as well
+        std::fs::remove_file(root.join("docs")).unwrap();
+        std::fs::rename(root.join("docs-old"), root.join("docs")).unwrap();
+        std::fs::remove_file(root.join("docs/secret.txt")).unwrap();
+        symlink(outside.join("secret.txt"), root.join("docs/secret.txt")).unwrap();
+
+        let mut content = String::new();
+        let read = target.open(OpenMode::Read).and_then(|mut f| f.read_to_string(&mut content));
+        assert!(read.is_err());
+        assert!(content.is_empty());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_listing_and_metadata_do_not_follow_swapped_symlinks() {
+        use std::os::unix::fs::symlink;
+        let (_dir, root, outside, confinement) = setup();
+
+        std::fs::create_dir(root.join("docs")).unwrap();
+        std::fs::write(root.join("docs/readme.txt"), "inside").unwrap();
+        symlink(outside.join("secret.txt"), root.join("docs/link")).unwrap();
+        let dir = confinement.resolve(&root.join("docs")).unwrap();
+        let file = confinement.resolve(&root.join("docs/readme.txt")).unwrap();
+
+        let mut names: Vec<String> = dir.read_dir().unwrap().into_iter()
+            .map(|(name, metadata)| {
+                // The symlink is listed as itself, not as the file outside
+                assert_eq!(name == "link", metadata.file_type().is_symlink());
+                name.to_string_lossy().to_string()
+            })
+            .collect();
+        names.sort();
+        assert_eq!(names, ["link", "readme.txt"]);
+        assert!(file.metadata().unwrap().is_file());
+        assert!(confinement.resolve(&root).unwrap().metadata().unwrap().is_dir());
+
+        // Replace the checked directory with a symlink to outside the root
+        std::fs::rename(root.join("docs"), root.join("docs-old")).unwrap();
+        symlink(&outside, root.join("docs")).unwrap();
+
+        assert!(dir.read_dir().is_err());
+        assert!(file.metadata().is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_list_dir_and_stat_are_confined() {
+        use std::os::unix::fs::symlink;
+        let (_dir, root, outside, _confinement) = setup();
+        let state_dir = root.join(".mcp-state");
+        let config = Config {
+            allowed_paths: vec![root.clone()],
+            audit_db_path: state_dir.join("audit.db"),
+            snapshot_dir: state_dir.join("snapshots"),
+            ..Config::default()
+        };
+        let audit = Arc::new(AuditLogger::new(&config.audit_db_path).unwrap());
+        let snapshots = Arc::new(SnapshotManager::new(&config.snapshot_dir).unwrap());
+        let config = Arc::new(RwLock::new(config));
+        let policy = Arc::new(PolicyEngine::new(config.clone(), audit.clone()));
+        let svc = FileServiceImpl::new(config, audit, policy, snapshots);
+
+        symlink(&outside, root.join("dir-link")).unwrap();
+        let path = |p: PathBuf| p.to_string_lossy().to_string();
+
+        let list = ListDirRequest { path: path(root.join("dir-link")), ..Default::default() };
+        let err = svc.list_dir(Request::new(list)).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+
+        let stat = StatRequest { path: path(root.join("dir-link/secret.txt")), ..Default::default() };
+        let err = svc.stat(Request::new(stat)).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+
+        let list = ListDirRequest { path: path(root.clone()), ..Default::default() };
+        let entries = svc.list_dir(Request::new(list)).await.unwrap().into_inner().entries;
+        let link = entries.iter().find(|e| e.name == "dir-link").unwrap();
+        assert!(!link.is_dir && !link.is_file);
+        assert_eq!(link.path, path(root.join("dir-link")));
+    }
+
+    fn file_service(root: &Path) -> (FileServiceImpl, Arc<PolicyEngine>, Arc<SnapshotManager>) {
+        let state_dir = root.join(".mcp-state");
+        let config = Config {
+            allowed_paths: vec![root.to_path_buf()],
+            audit_db_path: state_dir.join("audit.db"),
+            snapshot_dir: state_dir.join("snapshots"),
+            ..Config::default()
+        };
+        let audit = Arc::new(AuditLogger::new(&config.audit_db_path).unwrap());
+        let snapshots = Arc::new(SnapshotManager::new(&config.snapshot_dir).unwrap());
+        let config = Arc::new(RwLock::new(config));
+        let policy = Arc::new(PolicyEngine::new(config.clone(), audit.clone()));
+        let svc = FileServiceImpl::new(config, audit, policy.clone(), snapshots.clone());
+        (svc, policy, snapshots)
+    }
+
+    #[tokio::test]
+    async fn test_create_file_on_new_path() {
+        let (_dir, root, outside, _confinement) = setup();
+        let (svc, policy, _snapshots) = file_service(&root);
+
+        let make = |path: PathBuf, token: String| CreateFileRequest {
+            path: path.to_string_lossy().to_string(),
+            content: "fresh".to_string(),
+            approval_token: token,
+            ..Default::default()
+        };
+
+        let new_path = root.join("nested/dir/new.txt");
+        let token = policy.issue_approval(&make(new_path.clone(), String::new()).approval_action()).await.token;
+        svc.create_file(Request::new(make(new_path.clone(), token))).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&new_path).unwrap(), "fresh");
+
+        let escape = root.join("../outside/new.txt");
+        let token = policy.issue_approval(&make(escape.clone(), String::new()).approval_action()).await.token;
+        let err = svc.create_file(Request::new(make(escape, token))).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+        assert!(!outside.join("new.txt").exists());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_copy_file_keeps_mode_and_snapshots_destination() {
+        use std::os::unix::fs::PermissionsExt;
+        let (_dir, root, _outside, _confinement) = setup();
+        let (svc, policy, snapshots) = file_service(&root);
+
+        let script = root.join("build.sh");
+        std::fs::write(&script, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let copy = |to: &str| {
+            let mut req = CopyFileRequest {
+                from_path: script.to_string_lossy().to_string(),
+                to_path: root.join(to).to_string_lossy().to_string(),
+                ..Default::default()
+            };
+            let policy = policy.clone();
+            async move {
+                req.approval_token = policy.issue_approval(&req.approval_action()).await.token;
+                req
+            }
+        };
+
+        // A new copy is as executable as its source
+        let response = svc.copy_file(Request::new(copy("run.sh").await)).await.unwrap().into_inner();
+        let mode = std::fs::metadata(root.join("run.sh")).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o755);
+        assert!(response.snapshot_id.is_empty());
+
+        // Overwriting a file snapshots it first
+        std::fs::write(root.join("old.sh"), "old").unwrap();
+        let response = svc.copy_file(Request::new(copy("old.sh").await)).await.unwrap().into_inner();
+        assert_eq!(std::fs::read_to_string(root.join("old.sh")).unwrap(), "#!/bin/sh\n");
+        snapshots.restore(&response.snapshot_id, None).unwrap();
+        assert_eq!(std::fs::read_to_string(root.join("old.sh")).unwrap(), "old");
+    }
+}
@@ -0,0 +1,101 @@
+//! Tests for dry-run effect prediction
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use tonic::Request;
+
+    use crate::common::{self, Harness};
+    use mcp_core::services::command_service::{command_service_server::CommandService, *};
+    use mcp_core::{EffectKind, EffectRegistry, RiskLevel};
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn test_builtin_predictors() {
+        let registry = EffectRegistry::builtin();
+
+        let prediction = registry.predict("rm", &args(&["-fr", "build", "/tmp/cache"]), Some(Path::new("/work")));
+        assert_eq!(prediction.predictor_id.as_deref(), Some("builtin:rm-recursive"));
+        assert_eq!(prediction.risk, RiskLevel::High);
+        let paths: Vec<_> = prediction.effects.iter().map(|e| e.path.clone().unwrap()).collect();
+        assert_eq!(paths, ["/work/build", "/tmp/cache"]);
+        assert!(prediction.effects.iter().all(|e| e.kind == EffectKind::Delete));
+        assert_eq!(prediction.effects[0].description, "Will recursively delete /work/build");
+
+        // Flag spellings are normalized before matching
+        let prediction = registry.predict("git", &args(&["push", "origin", "main", "-f"]), None);
+        assert_eq!(prediction.predictor_id.as_deref(), Some("builtin:git-force-push"));
+        assert_eq!(prediction.risk, RiskLevel::High);
+
+        let prediction = registry.predict("npm", &args(&["run", "build"]), None);
+        assert_eq!(prediction.effects[0].description, "Will run npm script: build");
+
+        let prediction = registry.predict("git", &args(&["status"]), None);
+        assert!(prediction.effects.is_empty());
+        assert_eq!(prediction.risk, RiskLevel::Low);
+
+        let prediction = registry.predict("python", &args(&["script.py"]), None);
+        assert_eq!(prediction.predictor_id, None);
+        assert_eq!(prediction.effects[0].description, "Will execute: python script.py");
+    }
+
+    #[test]
+    fn test_user_predictors_take_precedence() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("effects.json");
+        std::fs::write(&path, r#"{
+            "predictors": [
+                { "id": "cargo-build-fast", "command": "cargo build", "risk": "low", "estimate": "5s",
+                  "effects": [{ "kind": "create", "path": "target/{arg1}", "description": "Builds into {path}" }] }
+            ]
+        }"#).unwrap();
+
+        let registry = EffectRegistry::load(&path).unwrap();
+        assert_eq!(registry.len(), EffectRegistry::builtin().len() + 1);
+
+        let prediction = registry.predict("cargo", &args(&["build", "debug"]), Some(Path::new("/p")));
+        assert_eq!(prediction.predictor_id.as_deref(), Some("cargo-build-fast"));
+        assert_eq!(prediction.estimate.as_deref(), Some("5s"));
+        assert_eq!(prediction.effects[0].description, "Builds into /p/target/debug");
+
+        // Effects that need a missing argument are left out
+        let prediction = registry.predict("cargo", &args(&["build"]), None);
+        assert!(prediction.effects.is_empty());
+
+        // Built-in predictors still apply to other commands
+        let prediction = registry.predict("cargo", &args(&["test"]), None);
+        assert_eq!(prediction.predictor_id.as_deref(), Some("builtin:cargo-build"));
+
+        std::fs::write(&path, r#"{ "predictors": [{ "id": "bad", "command": "x", "risk": "extreme" }] }"#).unwrap();
+        assert!(EffectRegistry::load(&path).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_uses_history_for_estimate() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let harness = Harness::new(&root, &["sh"], |_| {});
+        let svc = harness.command_service();
+
+        let request = |dry_run: bool| RunCommandRequest { dry_run, ..common::sh(&root, "sleep 0.1") };
+
+        let response = svc.run(Request::new(request(true))).await.unwrap().into_inner();
+        assert_eq!(response.estimated_time, "varies");
+        assert_eq!(response.risk_level, "medium");
+        assert_eq!(response.effects[0].kind, "other");
+
+        for _ in 0..2 {
+            let req = harness.approve(request(false)).await;
+            assert!(svc.run(Request::new(req)).await.unwrap().into_inner().success);
+        }
+
+        let response = svc.run(Request::new(request(true))).await.unwrap().into_inner();
+        assert!(response.estimated_time.ends_with("(median of 2 previous runs)"), "estimate: {}", response.estimated_time);
+        assert!(response.estimated_time.starts_with('~'), "estimate: {}", response.estimated_time);
+    }
+}
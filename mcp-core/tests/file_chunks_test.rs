@@ -0,0 +1,286 @@
+//! Tests for binary file contents and chunked transfers over FileService
+
+#[cfg(test)]
+mod tests {
+    use sha2::{Digest, Sha256};
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+    use tonic::transport::{server::TcpIncoming, Channel, Server};
+    use tonic::Code;
+
+    use mcp_core::services::file_service::{
+        file_service_client::FileServiceClient,
+        file_service_server::FileServiceServer,
+        *,
+    };
+    use mcp_core::{AuditLogger, Config, PolicyEngine, SnapshotManager};
+
+    /// Bytes that are not valid UTF-8
+    const BINARY: &[u8] = &[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0xff, 0x00];
+
+    struct Harness {
+        _dirs: [tempfile::TempDir; 2],
+        root: PathBuf,
+        client: FileServiceClient<Channel>,
+        policy: Arc<PolicyEngine>,
+    }
+
+    impl Harness {
+        async fn serve(max_file_size: u64) -> Self {
+            let dir = tempfile::tempdir().unwrap();
+            let root = dir.path().canonicalize().unwrap();
+            // State is kept outside the root, so the root holds only what tests write
+            let state_dir = tempfile::tempdir().unwrap();
+            let state = state_dir.path();
+            let config = Config {
+                allowed_paths: vec![root.clone()],
+                audit_db_path: state.join("audit.db"),
+                snapshot_dir: state.join("snapshots"),
+                max_file_size,
+                ..Config::default()
+            };
+            let audit = Arc::new(AuditLogger::new(&config.audit_db_path).unwrap());
+            let snapshots = Arc::new(SnapshotManager::new(&config.snapshot_dir).unwrap());
+            let config = Arc::new(RwLock::new(config));
+            let policy = Arc::new(PolicyEngine::new(config.clone(), audit.clone()));
+            let svc = FileServiceImpl::new(config, audit, policy.clone(), snapshots);
+
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+            tokio::spawn(Server::builder()
+                .add_service(FileServiceServer::new(svc))
+                .serve_with_incoming(incoming));
+
+            let client = FileServiceClient::connect(format!("http://{}", addr)).await.unwrap();
+            Self { _dirs: [dir, state_dir], root, client, policy }
+        }
+
+        fn path(&self, name: &str) -> String {
+            self.root.join(name).to_string_lossy().to_string()
+        }
+
+        async fn read(&self, path: &str) -> Result<ReadFileResponse, tonic::Status> {
+            let req = ReadFileRequest { path: path.to_string(), ..Default::default() };
+            self.client.clone().read_file(req).await.map(|r| r.into_inner())
+        }
+
+        async fn read_chunks(&self, req: ReadFileChunksRequest) -> Result<Vec<ReadFileChunksResponse>, tonic::Status> {
+            let mut stream = self.client.clone().read_file_chunks(req).await?.into_inner();
+            let mut chunks = Vec::new();
+            while let Some(chunk) = stream.message().await? {
+                chunks.push(chunk);
+            }
+            Ok(chunks)
+        }
+
+        /// An approved start of a write declaring `content`
+        async fn start(&self, path: &str, content: &[u8]) -> WriteFileChunksRequest {
+            let start = WriteFileStart {
+                path: path.to_string(),
+                size: content.len() as u64,
+                sha256: sha256(content),
+                ..Default::default()
+            };
+            self.approved(start).await
+        }
+
+        async fn approved(&self, mut start: WriteFileStart) -> WriteFileChunksRequest {
+            start.approval_token = self.policy.issue_approval(&start.approval_action()).await.token;
+            WriteFileChunksRequest { input: Some(write_file_chunks_request::Input::Start(start)) }
+        }
+
+        async fn write_chunks(&self, messages: Vec<WriteFileChunksRequest>) -> Result<WriteFileChunksResponse, tonic::Status> {
+            let stream = tokio_stream::iter(messages);
+            self.client.clone().write_file_chunks(stream).await.map(|r| r.into_inner())
+        }
+    }
+
+    fn chunk(offset: u64, data: &[u8]) -> WriteFileChunksRequest {
+        let chunk = FileChunk { offset, data: data.to_vec() };
+        WriteFileChunksRequest { input: Some(write_file_chunks_request::Input::Chunk(chunk)) }
+    }
+
+    fn sha256(content: &[u8]) -> String {
+        hex::encode(Sha256::digest(content))
+    }
+
+    #[tokio::test]
+    async fn test_binary_contents_round_trip() {
+        let h = Harness::serve(1024 * 1024).await;
+        let path = h.path("logo.png");
+
+        let mut req = CreateFileRequest { path: path.clone(), data: BINARY.to_vec(), ..Default::default() };
+        req.approval_token = h.policy.issue_approval(&req.approval_action()).await.token;
+        let response = h.client.clone().create_file(req).await.unwrap().into_inner();
+        assert_eq!(response.sha256, sha256(BINARY));
+        assert_eq!(std::fs::read(&path).unwrap(), BINARY);
+
+        // Binary files come back byte for byte
+        let file = h.read(&path).await.unwrap();
+        assert_eq!(file.encoding, "binary");
+        assert_eq!(file.data, BINARY);
+        assert!(file.content.is_empty());
+
+        let text = h.path("notes.txt");
+        std::fs::write(&text, "héllo\n").unwrap();
+        let file = h.read(&text).await.unwrap();
+        assert_eq!((file.encoding.as_str(), file.content.as_str()), ("utf-8", "héllo\n"));
+        assert!(file.data.is_empty());
+
+        // Contents are given one way or the other
+        let mut req = AppendFileRequest { path: text, content: "a".to_string(), data: b"b".to_vec(), ..Default::default() };
+        req.approval_token = h.policy.issue_approval(&req.approval_action()).await.token;
+        let err = h.client.clone().append_file(req).await.unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn test_large_files_are_read_in_chunks() {
+        let h = Harness::serve(1000).await;
+        let path = h.path("data.bin");
+        let content: Vec<u8> = (0..5000u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&path, &content).unwrap();
+
+        let err = h.read(&path).await.unwrap_err();
+        assert!(err.message().contains("ReadFileChunks"), "{}", err.message());
+
+        let chunks = h.read_chunks(ReadFileChunksRequest { path: path.clone(), chunk_size: 1024, ..Default::default() }).await.unwrap();
+        let offsets: Vec<u64> = chunks.iter().map(|c| c.offset).collect();
+        assert_eq!(offsets, [0, 1024, 2048, 3072, 4096]);
+        assert!(chunks.iter().all(|c| c.size == 5000));
+        assert_eq!(chunks.iter().filter(|c| c.last).count(), 1);
+        let last = chunks.last().unwrap();
+        assert!(last.last);
+        assert_eq!(last.sha256, sha256(&content));
+        assert_eq!(chunks.into_iter().flat_map(|c| c.data).collect::<Vec<u8>>(), content);
+
+        // A range is paged the same way
+        let req = ReadFileChunksRequest { path: path.clone(), offset: 4000, length: 500, ..Default::default() };
+        let chunks = h.read_chunks(req).await.unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].data, &content[4000..4500]);
+        assert_eq!(chunks[0].sha256, sha256(&content[4000..4500]));
+
+        let req = ReadFileChunksRequest { path, offset: 5001, ..Default::default() };
+        let err = h.read_chunks(req).await.unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn test_files_are_written_from_chunks() {
+        let h = Harness::serve(1000).await;
+        let path = h.path("upload.bin");
+        let content: Vec<u8> = (0..3000u32).map(|i| (i % 256) as u8).collect();
+
+        let mut messages = vec![h.start(&path, &content).await];
+        for (i, part) in content.chunks(1024).enumerate() {
+            messages.push(chunk(i as u64 * 1024, part));
+        }
+        let response = h.write_chunks(messages).await.unwrap();
+        assert_eq!((response.size, response.sha256.as_str()), (3000, sha256(&content).as_str()));
+        assert_eq!(std::fs::read(&path).unwrap(), content);
+
+        // A missing chunk or a short upload leaves the file alone
+        let gap = vec![h.start(&path, b"abcde").await, chunk(0, b"abc"), chunk(4, b"e")];
+        assert_eq!(h.write_chunks(gap).await.unwrap_err().code(), Code::InvalidArgument);
+        let short = vec![h.start(&path, b"abcdefghij").await, chunk(0, b"abc")];
+        assert_eq!(h.write_chunks(short).await.unwrap_err().code(), Code::InvalidArgument);
+        assert_eq!(std::fs::read(&path).unwrap(), content);
+        assert_eq!(std::fs::read_dir(&h.root).unwrap().count(), 1);
+
+        // The write must start first, and be approved
+        let err = h.write_chunks(vec![chunk(0, b"abc")]).await.unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+        let start = WriteFileStart { path: path.clone(), sha256: sha256(b"abc"), ..Default::default() };
+        let unapproved = WriteFileChunksRequest { input: Some(write_file_chunks_request::Input::Start(start)) };
+        let err = h.write_chunks(vec![unapproved, chunk(0, b"abc")]).await.unwrap_err();
+        assert_eq!(err.code(), Code::FailedPrecondition);
+    }
+
+    #[tokio::test]
+    async fn test_chunk_approvals_are_bound_to_the_contents() {
+        let h = Harness::serve(1000).await;
+        let path = h.path("upload.txt");
+        std::fs::write(&path, "old\n").unwrap();
+
+        // The contents must be declared, and match what is sent
+        let undeclared = h.approved(WriteFileStart { path: path.clone(), ..Default::default() }).await;
+        let err = h.write_chunks(vec![undeclared, chunk(0, b"new\n")]).await.unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+        let err = h.write_chunks(vec![h.start(&path, b"new\n").await, chunk(0, b"evil")]).await.unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "old\n");
+
+        // A token approves only the contents it was issued for
+        let mut start = WriteFileStart { path: path.clone(), sha256: sha256(b"new\n"), ..Default::default() };
+        start.approval_token = h.policy.issue_approval(&start.approval_action()).await.token;
+        let start = WriteFileStart { sha256: sha256(b"evil"), ..start };
+        let swapped = WriteFileChunksRequest { input: Some(write_file_chunks_request::Input::Start(start)) };
+        let err = h.write_chunks(vec![swapped, chunk(0, b"evil")]).await.unwrap_err();
+        assert_eq!(err.code(), Code::PermissionDenied);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "old\n");
+
+        // A dry run previews the write without approval, and its preview
+        // hash can be approved instead of the sha256
+        let start = WriteFileStart { path: path.clone(), dry_run: true, ..Default::default() };
+        let dry_run = WriteFileChunksRequest { input: Some(write_file_chunks_request::Input::Start(start)) };
+        let preview = h.write_chunks(vec![dry_run, chunk(0, b"new\n")]).await.unwrap().preview.unwrap();
+        assert_eq!(preview.changes[0].kind, "modified");
+        assert!(preview.changes[0].diff.contains("-old\n+new\n"), "{}", preview.changes[0].diff);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "old\n");
+
+        let start = h.approved(WriteFileStart {
+            path: path.clone(),
+            preview_hash: preview.preview_hash.clone(),
+            ..Default::default()
+        }).await;
+        let response = h.write_chunks(vec![start, chunk(0, b"new\n")]).await.unwrap();
+        assert!(!response.snapshot_id.is_empty());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new\n");
+    }
+
+    #[tokio::test]
+    async fn test_chunked_write_checks_precondition_after_upload() {
+        let h = Harness::serve(1000).await;
+        let path = h.path("shared.txt");
+        std::fs::write(&path, "v1\n").unwrap();
+
+        let (tx, rx) = tokio::sync::mpsc::channel(4);
+        let mut start = WriteFileStart {
+            path: path.clone(),
+            sha256: sha256(b"upload\n"),
+            expected_sha256: sha256(b"v1\n"),
+            ..Default::default()
+        };
+        start.approval_token = h.policy.issue_approval(&start.approval_action()).await.token;
+        tx.send(WriteFileChunksRequest { input: Some(write_file_chunks_request::Input::Start(start)) })
+            .await.unwrap();
+        tx.send(chunk(0, b"upload\n")).await.unwrap();
+        let mut client = h.client.clone();
+        let upload = tokio::spawn(async move {
+            client.write_file_chunks(tokio_stream::wrappers::ReceiverStream::new(rx)).await
+        });
+
+        // Wait for the upload to reach its temporary file
+        let started = std::time::Instant::now();
+        while std::fs::read_dir(&h.root).unwrap().count() < 2 {
+            assert!(started.elapsed() < std::time::Duration::from_secs(5), "upload did not start");
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        // The unfinished upload does not lock the file against other writes
+        let mut req = CreateFileRequest { path: path.clone(), content: "v2\n".to_string(), ..Default::default() };
+        req.approval_token = h.policy.issue_approval(&req.approval_action()).await.token;
+        tokio::time::timeout(std::time::Duration::from_secs(5), h.client.clone().create_file(req))
+            .await.unwrap().unwrap();
+
+        // So the upload, once complete, finds the file changed and leaves it
+        drop(tx);
+        let err = upload.await.unwrap().unwrap_err();
+        assert_eq!(err.code(), Code::FailedPrecondition);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "v2\n");
+        assert_eq!(std::fs::read_dir(&h.root).unwrap().count(), 1);
+    }
+}
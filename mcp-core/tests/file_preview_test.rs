@@ -0,0 +1,162 @@
+//! Tests for dry-run previews of file mutations
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+    use tonic::{Code, Request};
+
+    use mcp_core::services::file_service::{file_service_server::FileService, *};
+    use mcp_core::{AuditLogger, Config, PolicyEngine, SnapshotManager};
+
+    struct Harness {
+        _dir: tempfile::TempDir,
+        root: PathBuf,
+        svc: FileServiceImpl,
+        audit: Arc<AuditLogger>,
+        policy: Arc<PolicyEngine>,
+    }
+
+    impl Harness {
+        fn new() -> Self {
+            let dir = tempfile::tempdir().unwrap();
+            let root = dir.path().canonicalize().unwrap();
+            let config = Config {
+                allowed_paths: vec![root.clone()],
+                deny_globs: vec!["**/*.pem".to_string()],
+                audit_db_path: root.join(".state/audit.db"),
+                snapshot_dir: root.join(".state/snapshots"),
+                ..Config::default()
+            };
+            let audit = Arc::new(AuditLogger::new(&config.audit_db_path).unwrap());
+            let snapshots = Arc::new(SnapshotManager::new(&config.snapshot_dir).unwrap());
+            let config = Arc::new(RwLock::new(config));
+            let policy = Arc::new(PolicyEngine::new(config.clone(), audit.clone()));
+            let svc = FileServiceImpl::new(config, audit.clone(), policy.clone(), snapshots);
+            Self { _dir: dir, root, svc, audit, policy }
+        }
+
+        fn write(&self, name: &str, content: &[u8]) -> String {
+            let path = self.root.join(name);
+            std::fs::write(&path, content).unwrap();
+            path.to_string_lossy().to_string()
+        }
+    }
+
+    fn create_request(path: &str, content: &str) -> CreateFileRequest {
+        CreateFileRequest {
+            path: path.to_string(),
+            content: content.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_previews_without_writing() {
+        let h = Harness::new();
+        let path = h.write("notes.txt", b"one\ntwo\n");
+
+        // No approval is needed to preview
+        let req = CreateFileRequest { dry_run: true, ..create_request(&path, "one\nTWO\n") };
+        let response = h.svc.create_file(Request::new(req)).await.unwrap().into_inner();
+        assert!(!response.success);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "one\ntwo\n");
+
+        let preview = response.preview.unwrap();
+        assert_eq!(preview.preview_hash.len(), 64);
+        let change = &preview.changes[0];
+        assert_eq!(change.kind, "modified");
+        assert!(!change.binary);
+        assert!(change.diff.contains("-two\n+TWO\n"), "{}", change.diff);
+        assert_eq!((change.old_size, change.new_size), (8, 8));
+
+        let logs = h.audit.query(Some("file"), Some("dry_run"), None, None, 10).unwrap();
+        assert!(logs[0].details.contains(&preview.preview_hash), "{}", logs[0].details);
+
+        // Denied paths cannot be previewed either
+        let req = CreateFileRequest { dry_run: true, ..create_request(&h.root.join("key.pem").to_string_lossy(), "") };
+        let err = h.svc.create_file(Request::new(req)).await.unwrap_err();
+        assert_eq!(err.code(), Code::PermissionDenied);
+    }
+
+    #[tokio::test]
+    async fn test_approval_bound_to_preview() {
+        let h = Harness::new();
+        let path = h.write("config.toml", b"debug = false\n");
+
+        let preview = |content: &'static str| {
+            let h = &h;
+            let req = CreateFileRequest { dry_run: true, ..create_request(&path, content) };
+            async move {
+                h.svc.create_file(Request::new(req)).await.unwrap().into_inner().preview.unwrap().preview_hash
+            }
+        };
+        let approved = |content: &'static str, preview_hash: String| {
+            let h = &h;
+            let mut req = CreateFileRequest { preview_hash, ..create_request(&path, content) };
+            async move {
+                req.approval_token = h.policy.issue_approval(&req.approval_action()).await.token;
+                req
+            }
+        };
+
+        let req = approved("debug = true\n", preview("debug = true\n").await).await;
+        h.svc.create_file(Request::new(req)).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "debug = true\n");
+
+        // A token for one preview does not approve other contents
+        let mut req = approved("debug = false\n", preview("debug = false\n").await).await;
+        req.content = "rm -rf /\n".to_string();
+        let err = h.svc.create_file(Request::new(req)).await.unwrap_err();
+        assert_eq!(err.code(), Code::FailedPrecondition);
+
+        // Nor the same contents once the file changed after the preview
+        let req = approved("debug = false\n", preview("debug = false\n").await).await;
+        std::fs::write(&path, "edited = true\n").unwrap();
+        let err = h.svc.create_file(Request::new(req)).await.unwrap_err();
+        assert_eq!(err.code(), Code::FailedPrecondition);
+        assert!(err.message().contains("no longer matches its preview"), "{}", err.message());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "edited = true\n");
+
+        // And a preview-bound token cannot be used without its preview
+        let mut req = approved("debug = false\n", preview("debug = false\n").await).await;
+        req.preview_hash.clear();
+        let err = h.svc.create_file(Request::new(req)).await.unwrap_err();
+        assert_eq!(err.code(), Code::PermissionDenied);
+    }
+
+    #[tokio::test]
+    async fn test_binary_and_move_previews() {
+        let h = Harness::new();
+        let image = h.write("logo.png", &[0x89, b'P', b'N', b'G', 0, 1, 2]);
+        let copy = h.root.join("copy.png").to_string_lossy().to_string();
+
+        let req = CopyFileRequest {
+            from_path: image.clone(),
+            to_path: copy.clone(),
+            dry_run: true,
+            ..Default::default()
+        };
+        let preview = h.svc.copy_file(Request::new(req)).await.unwrap().into_inner().preview.unwrap();
+        let change = &preview.changes[0];
+        assert_eq!(change.kind, "created");
+        assert!(change.binary);
+        assert!(change.diff.starts_with("Binary files /dev/null and b/"), "{}", change.diff);
+        assert_eq!((change.old_size, change.new_size), (0, 7));
+        assert!(change.old_sha256.is_empty());
+        assert!(!std::path::Path::new(&copy).exists());
+
+        let req = MoveFileRequest {
+            from_path: image.clone(),
+            to_path: copy.clone(),
+            dry_run: true,
+            ..Default::default()
+        };
+        let preview = h.svc.move_file(Request::new(req)).await.unwrap().into_inner().preview.unwrap();
+        let kinds: Vec<&str> = preview.changes.iter().map(|c| c.kind.as_str()).collect();
+        assert_eq!(kinds, ["deleted", "created"]);
+        assert_eq!(preview.changes[0].old_sha256, preview.changes[1].new_sha256);
+        assert!(std::path::Path::new(&image).exists());
+    }
+}
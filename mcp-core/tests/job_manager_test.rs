@@ -0,0 +1,181 @@
+//! Tests for background jobs
+
+mod common;
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use tokio_stream::StreamExt;
+    use tonic::Request;
+
+    use mcp_core::services::command_service::{command_service_server::CommandService, *};
+    use mcp_core::{AuditLogger, Authorization, JobManager, JobState, OutputChunk, SandboxConfig};
+
+    use crate::common;
+
+    fn audit(root: &Path) -> Arc<AuditLogger> {
+        Arc::new(AuditLogger::new(&root.join("audit.db")).unwrap())
+    }
+
+    fn sh(jobs: &JobManager, script: &str) -> mcp_core::JobInfo {
+        let config = SandboxConfig { process_group: true, ..Default::default() };
+        let args = vec!["-c".to_string(), script.to_string()];
+        jobs.start("sh", &args, config, &Authorization::default()).unwrap()
+    }
+
+    async fn wait_finished(jobs: &JobManager, id: &str) -> mcp_core::JobInfo {
+        let started = Instant::now();
+        loop {
+            let info = jobs.get(id).unwrap();
+            if info.state != JobState::Running {
+                return info;
+            }
+            assert!(started.elapsed() < Duration::from_secs(10), "job did not finish");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }
+
+    fn stdout(chunks: &[mcp_core::JobOutput]) -> String {
+        chunks.iter()
+            .map(|c| match &c.chunk {
+                OutputChunk::Stdout(data) => String::from_utf8_lossy(data).to_string(),
+                OutputChunk::Stderr(_) => String::new(),
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_job_lifecycle_is_audited() {
+        let dir = tempfile::tempdir().unwrap();
+        let audit = audit(dir.path());
+        let jobs = JobManager::new(audit.clone());
+
+        let job = sh(&jobs, "echo hello; exit 3");
+        assert_eq!(job.state, JobState::Running);
+
+        let info = wait_finished(&jobs, &job.id).await;
+        assert_eq!(info.state, JobState::Exited);
+        assert_eq!(info.exit_code, Some(3));
+        assert_eq!(stdout(&jobs.tail(&job.id, 0).unwrap().read()), "hello\n");
+
+        let started = audit.query(Some("job"), Some("start"), None, None, 10).unwrap();
+        assert_eq!(started.len(), 1);
+        assert!(started[0].details.contains(&job.id));
+        let finished = audit.query(Some("job"), Some("finish"), None, None, 10).unwrap();
+        assert_eq!(finished.len(), 1);
+        assert_eq!(finished[0].result, "failed");
+        assert!(finished[0].details.contains("exit: 3"), "details: {}", finished[0].details);
+    }
+
+    #[tokio::test]
+    async fn test_output_buffer_is_bounded() {
+        let dir = tempfile::tempdir().unwrap();
+        let jobs = JobManager::new(audit(dir.path())).with_output_capacity(16);
+
+        let job = sh(&jobs, "i=0; while [ $i -lt 20 ]; do echo line$i; i=$((i+1)); done");
+        let info = wait_finished(&jobs, &job.id).await;
+
+        assert_eq!(info.output_end - info.output_start, 16);
+        let output = stdout(&jobs.tail(&job.id, 0).unwrap().read());
+        assert_eq!(output.len(), 16);
+        assert!(output.ends_with("line18\nline19\n"), "output: {:?}", output);
+
+        // Tailing from an offset only returns what came after it
+        let output = stdout(&jobs.tail(&job.id, info.output_end - 7).unwrap().read());
+        assert_eq!(output, "line19\n");
+    }
+
+    #[tokio::test]
+    async fn test_cancel_kills_job() {
+        let dir = tempfile::tempdir().unwrap();
+        let audit = audit(dir.path());
+        let jobs = JobManager::new(audit.clone());
+
+        let job = sh(&jobs, "echo ready; sleep 60");
+        let started = Instant::now();
+        let info = jobs.cancel(&job.id).await.unwrap();
+
+        assert_eq!(info.state, JobState::Cancelled);
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert!(jobs.list(false).is_empty());
+        assert_eq!(jobs.list(true).len(), 1);
+
+        let cancel = audit.query(Some("job"), Some("cancel"), None, None, 10).unwrap();
+        assert_eq!(cancel[0].result, "success");
+        let finished = audit.query(Some("job"), Some("finish"), None, None, 10).unwrap();
+        assert_eq!(finished[0].result, "cancelled");
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_stops_jobs() {
+        let dir = tempfile::tempdir().unwrap();
+        let audit = audit(dir.path());
+        let jobs = JobManager::new(audit.clone());
+
+        let first = sh(&jobs, "sleep 60");
+        let second = sh(&jobs, "sleep 60");
+        jobs.shutdown().await;
+
+        for id in [&first.id, &second.id] {
+            assert_eq!(jobs.get(id).unwrap().state, JobState::Cancelled);
+        }
+        let finished = audit.query(Some("job"), Some("finish"), None, None, 10).unwrap();
+        assert_eq!(finished.len(), 2);
+        assert!(finished.iter().all(|e| e.details.contains("server shutdown")));
+
+        let config = SandboxConfig::default();
+        assert!(jobs.start("true", &[], config, &Authorization::default()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_job_rpcs() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let harness = common::Harness::new(&root, &["sh"], |_| {});
+        let svc = harness.command_service();
+
+        let req = RunCommandRequest { timeout_secs: 0, ..common::sh(&root, "echo one; sleep 0.3; echo two") };
+        let err = svc.start_job(Request::new(req.clone())).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+
+        let req = harness.approve(req).await;
+        let job = svc.start_job(Request::new(req)).await.unwrap().into_inner();
+        assert_eq!(job.state, "running");
+
+        let listed = svc.list_jobs(Request::new(ListJobsRequest { include_finished: false }))
+            .await.unwrap().into_inner();
+        assert_eq!(listed.jobs.len(), 1);
+        assert_eq!(listed.jobs[0].job_id, job.job_id);
+
+        let tail = TailJobRequest { job_id: job.job_id.clone(), offset: 0, follow: true };
+        let events: Vec<TailJobResponse> = svc.tail_job(Request::new(tail)).await.unwrap()
+            .into_inner()
+            .map(|e| e.unwrap())
+            .collect()
+            .await;
+
+        let mut output = Vec::new();
+        for event in &events[..events.len() - 1] {
+            match event.event.as_ref().unwrap() {
+                tail_job_response::Event::Output(JobOutput { data: Some(job_output::Data::Stdout(data)), .. }) => {
+                    output.extend_from_slice(data)
+                }
+                other => panic!("unexpected event {:?}", other),
+            }
+        }
+        assert_eq!(output, b"one\ntwo\n");
+        match events.last().unwrap().event.as_ref().unwrap() {
+            tail_job_response::Event::Finished(info) => {
+                assert_eq!(info.state, "exited");
+                assert_eq!(info.output_end, 8);
+            }
+            other => panic!("expected finished event, got {:?}", other),
+        }
+
+        let missing = CancelJobRequest { job_id: "nope".to_string() };
+        let err = svc.cancel_job(Request::new(missing)).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::NotFound);
+    }
+}
@@ -0,0 +1,83 @@
+//! Tests for running commands without network access
+
+mod common;
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use std::path::Path;
+    use std::sync::Arc;
+    use tonic::Request;
+
+    use mcp_core::services::command_service::{command_service_server::CommandService, *};
+    use mcp_core::{AuditLogger, McpError, SandboxConfig, SandboxExecutor};
+
+    use crate::common;
+
+    /// Lists interfaces, then tries loopback and an external address
+    const PROBE: &str = "tail -n +3 /proc/net/dev | cut -d: -f1 | tr -d ' '; \
+                         (exec 3<>/dev/tcp/127.0.0.1/9) 2>&1 | sed -n '1s/.*: //p'; \
+                         (exec 3<>/dev/tcp/192.0.2.1/9) 2>&1 | sed -n '1s/.*: //p'";
+
+    fn bash(script: &str) -> Vec<String> {
+        vec!["-c".to_string(), script.to_string()]
+    }
+
+    #[tokio::test]
+    async fn test_isolated_command_only_has_loopback() {
+        let config = SandboxConfig {
+            isolate_network: true,
+            timeout_secs: 10,
+            ..SandboxConfig::default()
+        };
+        let output = match SandboxExecutor::execute("bash", &bash(PROBE), &config).await {
+            Err(McpError::Unsupported(reason)) => {
+                eprintln!("skipping: {}", reason);
+                return;
+            }
+            result => result.unwrap(),
+        };
+
+        let lines: Vec<&str> = output.stdout.lines().collect();
+        assert_eq!(lines, ["lo", "Connection refused", "Network is unreachable"], "{}", output.stdout);
+    }
+
+    fn harness(root: &Path) -> (CommandServiceImpl, Arc<AuditLogger>) {
+        let rules = r#"{ "rules": [
+            { "id": "offline-bash", "effect": "allow", "argv": ["bash", "-c", "*offline*"], "offline": true },
+            { "id": "bash", "effect": "allow", "argv": ["bash", "**"] }
+        ] }"#;
+        let harness = common::Harness::new(root, &["bash"], |_| {}).with_rules(rules);
+        (harness.command_service(), harness.audit)
+    }
+
+    fn request(root: &Path, script: &str) -> RunCommandRequest {
+        common::command(root, "bash", &["-c", script])
+    }
+
+    #[tokio::test]
+    async fn test_offline_rule_isolates_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let (svc, audit) = harness(&root);
+        let count = "grep -c : /proc/net/dev";
+
+        let response = match svc.run(Request::new(request(&root, &format!("{} # offline", count)))).await {
+            Err(status) if status.code() == tonic::Code::Unimplemented => {
+                eprintln!("skipping: {}", status.message());
+                return;
+            }
+            result => result.unwrap().into_inner(),
+        };
+        assert!(response.success, "{}", response.stderr);
+        assert_eq!(response.stdout, "1\n");
+
+        let logs = audit.query(Some("command"), Some("execute"), None, None, 10).unwrap();
+        assert!(logs[0].details.ends_with("[network isolated]"), "{}", logs[0].details);
+
+        // Commands matched by other rules keep the host's network
+        let response = svc.run(Request::new(request(&root, count))).await.unwrap().into_inner();
+        assert!(response.success);
+        let logs = audit.query(Some("command"), Some("execute"), None, None, 10).unwrap();
+        assert!(!logs[0].details.contains("[network isolated]"));
+    }
+}
@@ -0,0 +1,138 @@
+//! Tests for capping command output and keeping the rest as an artifact
+
+mod common;
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+    use std::sync::Arc;
+    use tonic::Request;
+
+    use mcp_core::services::command_service::{command_service_server::CommandService, *};
+    use mcp_core::{ArtifactStore, AuditLogger, PolicyEngine, SandboxConfig, SandboxExecutor};
+
+    use crate::common;
+
+    fn sh(script: &str) -> Vec<String> {
+        vec!["-c".to_string(), script.to_string()]
+    }
+
+    #[tokio::test]
+    async fn test_output_keeps_head_and_tail() {
+        let dir = tempfile::tempdir().unwrap();
+        let spill_dir = dir.path().join("spill");
+        let config = SandboxConfig {
+            max_output_bytes: 100,
+            spill_dir: Some(spill_dir.clone()),
+            ..SandboxConfig::default()
+        };
+
+        let output = SandboxExecutor::execute("sh", &sh("seq 1 1000; echo done >&2"), &config).await.unwrap();
+        let full: String = (1..=1000).map(|n| format!("{}\n", n)).collect();
+
+        assert!(output.truncated);
+        assert!(output.spilled);
+        assert_eq!(output.stdout_bytes, full.len() as u64);
+        assert_eq!(output.stderr_bytes, 5);
+        let omitted = full.len() - 100;
+        assert_eq!(
+            output.stdout,
+            format!("{}\n[... {} bytes omitted ...]\n{}", &full[..50], omitted, &full[full.len() - 50..])
+        );
+        assert_eq!(output.stderr, "done\n");
+
+        // The spill holds both streams in full, capped or not, and only the
+        // server can read it
+        assert_eq!(std::fs::read_to_string(spill_dir.join("stdout")).unwrap(), full);
+        assert_eq!(std::fs::read_to_string(spill_dir.join("stderr")).unwrap(), "done\n");
+        for stream in ["stdout", "stderr"] {
+            let mode = std::fs::metadata(spill_dir.join(stream)).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600, "{}", stream);
+        }
+
+        // Output under the cap is neither truncated nor spilled
+        let spill_dir = dir.path().join("small");
+        let config = SandboxConfig { spill_dir: Some(spill_dir.clone()), ..config };
+        let output = SandboxExecutor::execute("sh", &sh("echo hello"), &config).await.unwrap();
+        assert!(!output.truncated && !output.spilled);
+        assert_eq!(output.stdout, "hello\n");
+        assert!(!spill_dir.exists());
+    }
+
+    struct Harness {
+        svc: CommandServiceImpl,
+        audit: Arc<AuditLogger>,
+        policy: Arc<PolicyEngine>,
+    }
+
+    fn harness(root: &Path) -> Harness {
+        let base = common::Harness::new(root, &["sh"], |config| config.max_output_bytes = 64);
+        let artifacts = Arc::new(ArtifactStore::new(&root.join("artifacts")).unwrap());
+        let svc = base.command_service().with_artifacts(artifacts);
+        Harness { svc, audit: base.audit, policy: base.policy }
+    }
+
+    async fn run(harness: &Harness, root: &Path, script: &str) -> RunCommandResponse {
+        let mut req = common::sh(root, script);
+        req.approval_token = harness.policy.issue_approval(&req.approval_action()).await.token;
+        harness.svc.run(Request::new(req)).await.unwrap().into_inner()
+    }
+
+    async fn read_output(harness: &Harness, artifact_id: &str, offset: u64, max_bytes: u64) -> ReadOutputResponse {
+        let req = ReadOutputRequest {
+            artifact_id: artifact_id.to_string(),
+            stream: "stdout".to_string(),
+            offset,
+            max_bytes,
+        };
+        harness.svc.read_output(Request::new(req)).await.unwrap().into_inner()
+    }
+
+    #[tokio::test]
+    async fn test_truncated_run_output_is_readable_from_artifact() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let harness = harness(&root);
+
+        let script = "seq 1 200; echo password=hunter22";
+        let response = run(&harness, &root, script).await;
+        // Secrets are scrubbed from the whole stream before it is capped or stored
+        let full: String = (1..=200).map(|n| format!("{}\n", n)).collect::<String>() + "password=[REDACTED]\n";
+        assert!(response.truncated);
+        assert_eq!(response.stdout_bytes, full.len() as u64);
+        assert!(response.stdout.starts_with("1\n2\n3\n"));
+        assert!(response.stdout.ends_with("199\n200\npassword=[REDACTED]\n"));
+        assert!(!response.artifact_id.is_empty());
+
+        let logs = harness.audit.query(Some("command"), Some("execute"), None, None, 10).unwrap();
+        assert_eq!(logs[0].artifact_id.as_deref(), Some(response.artifact_id.as_str()));
+        assert!(logs[0].details.contains("[output truncated"));
+
+        let stored = root.join("artifacts").join(&response.artifact_id).join("stdout");
+        assert_eq!(std::fs::read_to_string(stored).unwrap(), full);
+
+        // Read it back in pieces small enough to split the secret
+        let mut offset = 0;
+        let mut read = Vec::new();
+        loop {
+            let chunk = read_output(&harness, &response.artifact_id, offset, 7).await;
+            assert_eq!(chunk.total_bytes, full.len() as u64);
+            read.extend(chunk.data);
+            offset = chunk.next_offset;
+            if chunk.eof {
+                break;
+            }
+        }
+        assert_eq!(String::from_utf8(read).unwrap(), full);
+
+        // Small output needs no artifact
+        let response = run(&harness, &root, "echo small").await;
+        assert!(!response.truncated);
+        assert!(response.artifact_id.is_empty());
+
+        let bad = ReadOutputRequest { artifact_id: "../etc".to_string(), stream: "stdout".to_string(), offset: 0, max_bytes: 0 };
+        let err = harness.svc.read_output(Request::new(bad)).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    }
+}
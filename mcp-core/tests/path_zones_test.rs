@@ -0,0 +1,135 @@
+//! Unit tests for per-path permission zones and deny globs
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+    use tokio::sync::RwLock;
+
+    use mcp_core::{AuditLogger, Config, PathPermission, PathZone, PolicyDecision, PolicyEngine};
+
+    /// A writable workspace with a read-only `vendor` zone and a read-only
+    /// `docs` root next to it
+    fn setup() -> (TempDir, PathBuf, PolicyEngine) {
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let base = dir.path().canonicalize().unwrap();
+        let workspace = base.join("workspace");
+        std::fs::create_dir_all(workspace.join("vendor")).unwrap();
+        std::fs::create_dir_all(workspace.join(".ssh")).unwrap();
+        std::fs::create_dir_all(base.join("docs")).unwrap();
+        std::fs::write(workspace.join("vendor/lib.rs"), "").unwrap();
+        std::fs::write(workspace.join(".env"), "TOKEN=1").unwrap();
+        std::fs::write(workspace.join(".ssh/id_ed25519"), "key").unwrap();
+        std::fs::write(base.join("docs/guide.md"), "").unwrap();
+
+        let config = Config {
+            allowed_paths: vec![workspace.clone()],
+            path_zones: vec![
+                PathZone {
+                    path: workspace.join("vendor"),
+                    permissions: vec![PathPermission::Read],
+                    deny: vec![],
+                },
+                PathZone {
+                    path: base.join("docs"),
+                    permissions: vec![PathPermission::Read, PathPermission::Execute],
+                    deny: vec!["**/*.draft".to_string()],
+                },
+            ],
+            audit_db_path: base.join("audit.db"),
+            whitelisted_commands: vec!["ls".to_string()],
+            ..Config::default()
+        };
+        let audit = Arc::new(AuditLogger::new(&config.audit_db_path).unwrap());
+        let policy = PolicyEngine::new(Arc::new(RwLock::new(config)), audit);
+        (dir, base, policy)
+    }
+
+    fn is_denied(decision: &PolicyDecision) -> bool {
+        matches!(decision, PolicyDecision::Deny(_))
+    }
+
+    #[tokio::test]
+    async fn test_zone_permissions() {
+        let (_dir, base, policy) = setup();
+        let workspace = base.join("workspace");
+
+        let check = |path: PathBuf, permission| {
+            let policy = &policy;
+            async move { policy.check_file_access(&path, permission).await.unwrap().decision }
+        };
+
+        assert!(matches!(check(workspace.join("src/main.rs"), PathPermission::Create).await, PolicyDecision::RequireApproval(_)));
+        assert!(matches!(check(workspace.join("vendor/lib.rs"), PathPermission::Read).await, PolicyDecision::Allow));
+
+        // The more specific read-only zone wins over the writable workspace
+        assert!(is_denied(&check(workspace.join("vendor/lib.rs"), PathPermission::Write).await));
+        assert!(is_denied(&check(workspace.join("vendor/new.rs"), PathPermission::Create).await));
+        assert!(is_denied(&check(workspace.join("vendor/lib.rs"), PathPermission::Delete).await));
+
+        assert!(matches!(check(base.join("docs/guide.md"), PathPermission::Read).await, PolicyDecision::Allow));
+        assert!(is_denied(&check(base.join("docs/guide.md"), PathPermission::Write).await));
+        assert!(is_denied(&check(base.join("elsewhere.txt"), PathPermission::Read).await));
+    }
+
+    #[tokio::test]
+    async fn test_deny_globs() {
+        let (_dir, base, policy) = setup();
+        let workspace = base.join("workspace");
+
+        let check = |path: PathBuf| {
+            let policy = &policy;
+            async move { policy.check_file_access(&path, PathPermission::Read).await.unwrap().decision }
+        };
+
+        // Default deny globs apply everywhere, even to reads
+        assert!(is_denied(&check(workspace.join(".env")).await));
+        assert!(is_denied(&check(workspace.join(".ssh")).await));
+        assert!(is_denied(&check(workspace.join(".ssh/id_ed25519")).await));
+        assert!(is_denied(&check(workspace.join("certs/server.pem")).await));
+        assert!(matches!(check(workspace.join(".envrc")).await, PolicyDecision::Allow));
+
+        // Zone deny globs only apply inside their zone
+        assert!(is_denied(&check(base.join("docs/intro.draft")).await));
+        assert!(matches!(check(workspace.join("intro.draft")).await, PolicyDecision::Allow));
+
+        // A symlink cannot reach a denied file under another name
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(workspace.join(".env"), workspace.join("settings.txt")).unwrap();
+            assert!(is_denied(&check(workspace.join("settings.txt")).await));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_git_and_command_cwd_use_zones() {
+        let (_dir, base, policy) = setup();
+        let workspace = base.join("workspace");
+        let vendor = workspace.join("vendor");
+
+        // Read-only git operations work in a read-only zone; writes do not
+        assert!(!is_denied(&policy.check_git_operation(&vendor, "status").await.unwrap().decision));
+        assert!(is_denied(&policy.check_git_operation(&vendor, "commit").await.unwrap().decision));
+        assert!(!is_denied(&policy.check_git_operation(&workspace, "commit").await.unwrap().decision));
+
+        // Commands may only run in zones that grant execute
+        let args: Vec<String> = vec![];
+        assert!(is_denied(&policy.check_command("ls", &args, Some(&vendor)).await.unwrap().decision));
+        assert!(!is_denied(&policy.check_command("ls", &args, Some(&base.join("docs"))).await.unwrap().decision));
+        assert!(!is_denied(&policy.check_command("ls", &args, Some(&workspace)).await.unwrap().decision));
+        assert!(is_denied(&policy.check_command("ls", &args, Some(&workspace.join(".ssh"))).await.unwrap().decision));
+    }
+
+    #[test]
+    fn test_config_defaults() {
+        let zones: Vec<PathZone> = serde_json::from_str(r#"[{ "path": "/srv/data" }]"#).unwrap();
+        assert_eq!(zones[0].permissions, vec![PathPermission::Read]);
+        assert!(zones[0].deny.is_empty());
+
+        let config = Config::default();
+        for glob in ["**/.env", "**/.ssh/**", "**/*.pem"] {
+            assert!(config.deny_globs.iter().any(|g| g == glob), "missing default deny glob {}", glob);
+        }
+    }
+}
@@ -0,0 +1,260 @@
+//! Unit tests for the declarative policy rule engine
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    use mcp_core::{
+        ApprovalAction, AuditLogger, Config, PathPermission, PolicyDecision, PolicyEngine, RuleInput, RuleSet,
+    };
+
+    const PROJECT_RULES: &str = r#"{
+      "rules": [
+        { "id": "cargo-test", "effect": "allow", "service": "command",
+          "argv": ["cargo", "test"], "cwd": "/home/dev/projects/*" },
+        { "id": "cargo-publish", "effect": "ask", "argv": ["cargo", "publish"] },
+        { "id": "npm-publish", "effect": "deny", "argv": ["npm", "publish", "**"],
+          "reason": "Publishing packages is not allowed" },
+        { "id": "no-secrets", "effect": "deny", "service": "file",
+          "paths": ["**/.env", "**/*.pem"] },
+        { "id": "python-scripts", "effect": "allow", "argv": ["python3", "re:^scripts/[a-z_]+\\.py$"] }
+      ]
+    }"#;
+
+    fn command<'a>(argv: &'a [String], cwd: Option<&'a Path>) -> RuleInput<'a> {
+        RuleInput { service: "command", operation: "execute", argv, paths: &[], cwd }
+    }
+
+    fn argv(tokens: &[&str]) -> Vec<String> {
+        tokens.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_first_matching_rule_wins() {
+        let rules = RuleSet::from_json(PROJECT_RULES).unwrap();
+        let cwd = Path::new("/home/dev/projects/app");
+
+        let test = argv(&["cargo", "test", "--release"]);
+        let matched = rules.evaluate(&command(&test, Some(cwd))).unwrap();
+        assert_eq!(matched.rule_id, "cargo-test");
+        assert!(matches!(matched.decision, PolicyDecision::Allow));
+
+        let publish = argv(&["cargo", "publish"]);
+        let matched = rules.evaluate(&command(&publish, Some(cwd))).unwrap();
+        assert_eq!(matched.rule_id, "cargo-publish");
+        assert!(matches!(matched.decision, PolicyDecision::RequireApproval(_)));
+
+        let npm = argv(&["npm", "publish"]);
+        let matched = rules.evaluate(&command(&npm, None)).unwrap();
+        assert_eq!(matched.rule_id, "npm-publish");
+        match matched.decision {
+            PolicyDecision::Deny(reason) => assert_eq!(reason, "Publishing packages is not allowed"),
+            other => panic!("Expected deny, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_cwd_condition() {
+        let rules = RuleSet::from_json(PROJECT_RULES).unwrap();
+        let test = argv(&["cargo", "test"]);
+
+        assert!(rules.evaluate(&command(&test, Some(Path::new("/tmp/elsewhere")))).is_none());
+        assert!(rules.evaluate(&command(&test, None)).is_none());
+
+        // `*` stays within one directory
+        assert!(rules.evaluate(&command(&test, Some(Path::new("/home/dev/projects/app")))).is_some());
+        assert!(rules.evaluate(&command(&test, Some(Path::new("/home/dev/projects/app/nested")))).is_none());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_rules_match_resolved_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        for sub in ["projects/app", "Documents"] {
+            std::fs::create_dir_all(root.join(sub)).unwrap();
+        }
+        std::os::unix::fs::symlink(root.join("Documents"), root.join("projects/docs")).unwrap();
+        let config = Config {
+            allowed_paths: vec![root.clone()],
+            audit_db_path: root.join("audit.db"),
+            ..Config::default()
+        };
+        let audit = Arc::new(AuditLogger::new(&config.audit_db_path).unwrap());
+        let rules = RuleSet::from_json(&format!(
+            r#"{{ "rules": [
+                {{ "id": "project-tests", "effect": "allow", "argv": ["cargo", "test"], "cwd": "{0}/projects/*" }},
+                {{ "id": "project-files", "effect": "allow", "service": "file", "paths": ["{0}/projects/**"] }}
+            ] }}"#,
+            root.display()
+        )).unwrap();
+        let policy = PolicyEngine::new(Arc::new(RwLock::new(config)), audit).with_rules(rules);
+
+        let test = argv(&["test"]);
+        let verdict = policy.check_command("cargo", &test, Some(&root.join("projects/app"))).await.unwrap();
+        assert_eq!(verdict.rule_id.as_deref(), Some("project-tests"));
+
+        // `..` and symlinks cannot lead a path out of the glob
+        for cwd in ["projects/app/../../Documents", "projects/docs"] {
+            let verdict = policy.check_command("cargo", &test, Some(&root.join(cwd))).await.unwrap();
+            assert_eq!(verdict.rule_id, None, "{}", cwd);
+        }
+        for path in ["projects/app/../../Documents/notes.txt", "projects/docs/notes.txt"] {
+            let verdict = policy.check_file_access(&root.join(path), PathPermission::Create).await.unwrap();
+            assert!(matches!(verdict.decision, PolicyDecision::RequireApproval(_)), "{}", path);
+        }
+        let verdict = policy.check_file_access(&root.join("projects/app/notes.txt"), PathPermission::Create).await.unwrap();
+        assert_eq!(verdict.rule_id.as_deref(), Some("project-files"));
+    }
+
+    #[test]
+    fn test_regex_and_path_patterns() {
+        let rules = RuleSet::from_json(PROJECT_RULES).unwrap();
+
+        let script = argv(&["python3", "scripts/build_docs.py"]);
+        assert_eq!(rules.evaluate(&command(&script, None)).unwrap().rule_id, "python-scripts");
+
+        let other = argv(&["python3", "-c", "import os"]);
+        assert!(rules.evaluate(&command(&other, None)).is_none());
+
+        let env_file = [PathBuf::from("/home/dev/projects/app/.env")];
+        let input = RuleInput { service: "file", operation: "read", argv: &[], paths: &env_file, cwd: None };
+        assert_eq!(rules.evaluate(&input).unwrap().rule_id, "no-secrets");
+
+        let source = [PathBuf::from("/home/dev/projects/app/src/main.rs")];
+        let input = RuleInput { service: "file", operation: "read", argv: &[], paths: &source, cwd: None };
+        assert!(rules.evaluate(&input).is_none());
+    }
+
+    #[test]
+    fn test_argv_matches_normalized_command_line() {
+        let rules = RuleSet::from_json(PROJECT_RULES).unwrap();
+
+        for line in [
+            &["/usr/bin/npm", "publish"][..],
+            &["npm", "--registry", "https://registry.example", "publish"],
+            &["npm", "--registry=https://registry.example", "publish", "--tag", "next"],
+            &["/usr/local/bin/npm", "--prefix", "/tmp", "publish"],
+        ] {
+            let npm = argv(line);
+            let matched = rules.evaluate(&command(&npm, None));
+            assert_eq!(matched.map(|m| m.rule_id).as_deref(), Some("npm-publish"), "{:?}", line);
+        }
+
+        let install = argv(&["npm", "--registry", "publish", "install"]);
+        assert!(rules.evaluate(&command(&install, None)).is_none());
+
+        // Tools without subcommands keep their flags in place
+        let other = argv(&["/usr/bin/python3", "-c", "import os"]);
+        assert!(rules.evaluate(&command(&other, None)).is_none());
+        let script = argv(&["/usr/bin/python3", "scripts/build_docs.py"]);
+        assert_eq!(rules.evaluate(&command(&script, None)).unwrap().rule_id, "python-scripts");
+    }
+
+    #[test]
+    fn test_command_patterns_match_flags_anywhere() {
+        let rules = RuleSet::from_json(r#"{ "rules": [
+            { "id": "no-force-push", "effect": "deny", "command": "git push --force" }
+        ] }"#).unwrap();
+
+        for line in [
+            &["git", "push", "--force"][..],
+            &["git", "push", "origin", "main", "-f"],
+            &["/usr/bin/git", "-C", "repo", "push", "origin", "--force"],
+        ] {
+            let git = argv(line);
+            assert!(rules.evaluate(&command(&git, None)).is_some(), "{:?}", line);
+        }
+
+        let push = argv(&["git", "push", "origin", "main"]);
+        assert!(rules.evaluate(&command(&push, None)).is_none());
+
+        let empty = r#"{ "rules": [ { "id": "bad", "effect": "deny", "command": " " } ] }"#;
+        assert!(RuleSet::from_json(empty).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_builtin_rules_deny_destructive_git_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let config = Config {
+            allowed_paths: vec![root.clone()],
+            audit_db_path: root.join("audit.db"),
+            ..Config::default()
+        };
+        let audit = Arc::new(AuditLogger::new(&config.audit_db_path).unwrap());
+        let policy = PolicyEngine::new(Arc::new(RwLock::new(config)), audit);
+
+        for args in [
+            &["push", "origin", "main", "--force"][..],
+            &["push", "-f"],
+            &["push", "--force-with-lease"],
+            &["push", "--force-with-lease=main:abc123", "origin", "main"],
+            &["push", "--force-if-includes", "origin", "main"],
+            &["push", "--mirror", "backup"],
+            &["push", "origin", "+main"],
+            &["push", "origin", "+refs/heads/main:refs/heads/main"],
+            &["-C", "repo", "reset", "--hard", "HEAD~1"],
+        ] {
+            let verdict = policy.check_command("git", &argv(args), Some(&root)).await.unwrap();
+            assert!(matches!(verdict.decision, PolicyDecision::Deny(_)), "{:?}", args);
+            assert_eq!(verdict.rule_id.as_deref(), Some("builtin:git-destructive"));
+        }
+
+        for args in [&["reset", "--soft", "HEAD~1"][..], &["push", "origin", "main"]] {
+            let verdict = policy.check_command("git", &argv(args), Some(&root)).await.unwrap();
+            assert!(!matches!(verdict.decision, PolicyDecision::Deny(_)), "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn test_invalid_rules_rejected() {
+        let bad_regex = r#"{ "rules": [ { "id": "bad", "effect": "deny", "argv": ["re:(unclosed"] } ] }"#;
+        assert!(RuleSet::from_json(bad_regex).is_err());
+
+        let bad_effect = r#"{ "rules": [ { "id": "bad", "effect": "maybe" } ] }"#;
+        assert!(RuleSet::from_json(bad_effect).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_engine_reports_rule_id() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let config = Config {
+            allowed_paths: vec![root.clone()],
+            audit_db_path: root.join("audit.db"),
+            ..Config::default()
+        };
+        let audit = Arc::new(AuditLogger::new(&config.audit_db_path).unwrap());
+        let config = Arc::new(RwLock::new(config));
+
+        let rules = RuleSet::from_json(&format!(
+            r#"{{ "rules": [
+                {{ "id": "local-cargo-test", "effect": "allow", "argv": ["cargo", "test"], "cwd": "{}" }},
+                {{ "id": "no-npm-publish", "effect": "deny", "argv": ["npm", "publish"] }}
+            ] }}"#,
+            root.display()
+        )).unwrap();
+        let policy = PolicyEngine::new(config, audit.clone()).with_rules(rules);
+
+        let verdict = policy.check_command("cargo", &argv(&["test"]), Some(&root)).await.unwrap();
+        assert!(matches!(verdict.decision, PolicyDecision::Allow));
+        assert_eq!(verdict.rule_id.as_deref(), Some("local-cargo-test"));
+
+        // Built-in rules replace the old hard-coded git operation table
+        let verdict = policy.check_git_operation(&root, "status").await.unwrap();
+        assert_eq!(verdict.rule_id.as_deref(), Some("builtin:git-read"));
+
+        // Denials are audited together with the rule that caused them
+        let verdict = policy.check_command("npm", &argv(&["publish"]), None).await.unwrap();
+        let action = ApprovalAction::new("command", "execute").with_arguments(["npm", "publish"]);
+        assert!(policy.enforce(verdict, &action, "").await.is_err());
+
+        let logs = audit.query(Some("command"), Some("execute"), None, None, 10).unwrap();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].rule_id.as_deref(), Some("no-npm-publish"));
+        assert_eq!(logs[0].result, "denied");
+    }
+}
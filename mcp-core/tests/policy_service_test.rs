@@ -0,0 +1,102 @@
+//! Tests for changing the configuration at runtime
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::{Arc, OnceLock};
+    use tempfile::TempDir;
+    use tokio::sync::RwLock;
+    use tonic::Request;
+
+    use mcp_core::services::policy_service::{policy_service_server::PolicyService, *};
+    use mcp_core::{AuditLogger, Config, PathPermission, PolicyEngine};
+
+    fn request(commands: &[&str]) -> SetConfigRequest {
+        SetConfigRequest {
+            allowed_paths: vec!["/tmp".to_string()],
+            whitelisted_commands: commands.iter().map(|c| c.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn service() -> (TempDir, PolicyServiceImpl, Arc<RwLock<Config>>) {
+        // The accepted config is saved under the home directory, which the
+        // tests share since it is set for the whole process
+        static HOME: OnceLock<TempDir> = OnceLock::new();
+        HOME.get_or_init(|| {
+            let home = tempfile::tempdir().unwrap();
+            std::env::set_var("HOME", home.path());
+            home
+        });
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config { audit_db_path: dir.path().join("audit.db"), ..Config::default() };
+        let audit = Arc::new(AuditLogger::new(&config.audit_db_path).unwrap());
+        let config = Arc::new(RwLock::new(config));
+        let policy = Arc::new(PolicyEngine::new(config.clone(), audit.clone()));
+        (dir, PolicyServiceImpl::new(config.clone(), audit, policy), config)
+    }
+
+    #[tokio::test]
+    async fn test_whitelisted_commands_are_names_or_absolute_paths() {
+        let (_dir, svc, config) = service();
+
+        svc.set_config(Request::new(request(&["git", "/usr/bin/python3"]))).await.unwrap();
+        assert_eq!(config.read().await.whitelisted_commands, ["git", "/usr/bin/python3"]);
+
+        for command in ["bin/python3", "./python3", "../python3", "python 3", ""] {
+            let err = svc.set_config(Request::new(request(&[command]))).await.unwrap_err();
+            assert_eq!(err.code(), tonic::Code::InvalidArgument, "{:?}", command);
+        }
+        assert_eq!(config.read().await.whitelisted_commands, ["git", "/usr/bin/python3"]);
+    }
+
+    #[tokio::test]
+    async fn test_path_zones_and_env_policy() {
+        let (_dir, svc, config) = service();
+        let env_policy = config.read().await.env_policy.clone();
+
+        let zone = PathZone {
+            path: "/srv/data".to_string(),
+            permissions: vec!["read".to_string(), "write".to_string()],
+            deny: vec!["*.key".to_string()],
+        };
+        let req = SetConfigRequest { path_zones: vec![zone.clone()], ..request(&["git"]) };
+        svc.set_config(Request::new(req)).await.unwrap();
+        {
+            let config = config.read().await;
+            assert_eq!(config.path_zones.len(), 1);
+            assert_eq!(config.path_zones[0].path, PathBuf::from("/srv/data"));
+            assert_eq!(config.path_zones[0].permissions, [PathPermission::Read, PathPermission::Write]);
+            // An env policy that is not given is left as it is
+            assert_eq!(config.env_policy, env_policy);
+        }
+
+        let current = svc.get_config(Request::new(GetConfigRequest {})).await.unwrap().into_inner();
+        assert_eq!(current.path_zones, [zone]);
+        let mut env = current.env_policy.unwrap();
+        env.denied.push("MY_SECRET_*".to_string());
+
+        // Zones are replaced like the allowed paths
+        let req = SetConfigRequest { env_policy: Some(env), ..request(&["git"]) };
+        svc.set_config(Request::new(req)).await.unwrap();
+        assert!(config.read().await.path_zones.is_empty());
+        assert!(config.read().await.env_policy.denied.contains(&"MY_SECRET_*".to_string()));
+
+        let bad_zones = [
+            PathZone { path: "data".to_string(), ..Default::default() },
+            PathZone { path: "/srv/data".to_string(), permissions: vec!["admin".to_string()], ..Default::default() },
+            PathZone { path: "/srv/data".to_string(), deny: vec!["[".to_string()], ..Default::default() },
+        ];
+        for zone in bad_zones {
+            let req = SetConfigRequest { path_zones: vec![zone.clone()], ..request(&["git"]) };
+            let err = svc.set_config(Request::new(req)).await.unwrap_err();
+            assert_eq!(err.code(), tonic::Code::InvalidArgument, "{:?}", zone);
+        }
+        let env = EnvPolicy { denied: vec!["[".to_string()], ..Default::default() };
+        let req = SetConfigRequest { env_policy: Some(env), ..request(&["git"]) };
+        let err = svc.set_config(Request::new(req)).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+        assert!(config.read().await.env_policy.denied.contains(&"MY_SECRET_*".to_string()));
+    }
+}
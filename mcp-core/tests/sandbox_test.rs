@@ -0,0 +1,167 @@
+//! Unit tests for the resource-limited sandbox
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use tokio::sync::mpsc;
+
+    use mcp_core::{CommandInput, OutputChunk, ResourceLimits, SandboxConfig, SandboxExecutor, TerminalSize};
+
+    async fn sh(script: &str, config: &SandboxConfig) -> mcp_core::SandboxOutput {
+        let args = vec!["-c".to_string(), script.to_string()];
+        SandboxExecutor::execute("sh", &args, config).await.expect("Failed to spawn sh")
+    }
+
+    #[tokio::test]
+    async fn test_memory_hog_is_stopped() {
+        let config = SandboxConfig::isolated(ResourceLimits {
+            max_memory: 256 * 1024 * 1024,
+            // Safety net in case the memory limit is not applied
+            max_cpu_time: 10,
+            max_file_size: 0,
+        });
+
+        // `tail` buffers an endless line from /dev/zero until allocation fails
+        let started = Instant::now();
+        let output = sh("exec tail /dev/zero", &config).await;
+
+        assert!(!output.success);
+        assert!(output.stderr.contains("memory exhausted"), "stderr: {}", output.stderr);
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[tokio::test]
+    async fn test_cpu_spinner_is_killed() {
+        let config = SandboxConfig::isolated(ResourceLimits {
+            max_cpu_time: 1,
+            ..Default::default()
+        });
+
+        let started = Instant::now();
+        let output = sh("while :; do :; done", &config).await;
+
+        assert!(!output.success);
+        assert!(
+            matches!(output.signal, Some(libc::SIGXCPU) | Some(libc::SIGKILL)),
+            "expected SIGXCPU or SIGKILL, got {:?}",
+            output.signal
+        );
+        assert!(started.elapsed() < Duration::from_secs(30));
+    }
+
+    #[tokio::test]
+    async fn test_file_size_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("big.bin");
+        let config = SandboxConfig::isolated(ResourceLimits {
+            max_file_size: 1024 * 1024,
+            ..Default::default()
+        });
+
+        let script = format!("head -c 4194304 /dev/zero > {}", out.display());
+        let output = sh(&script, &config).await;
+
+        assert!(!output.success);
+        assert!(std::fs::metadata(&out).unwrap().len() <= 1024 * 1024);
+    }
+
+    #[tokio::test]
+    async fn test_isolation_flags() {
+        let config = SandboxConfig::isolated(ResourceLimits::default());
+
+        // The server's environment is not inherited, apart from the basics
+        let output = sh("env", &config).await;
+        assert!(output.success);
+        assert!(output.stdout.lines().any(|l| l.starts_with("PATH=")));
+        assert!(!output.stdout.contains("CARGO_MANIFEST_DIR="), "env: {}", output.stdout);
+
+        let status = SandboxExecutor::execute("cat", &["/proc/self/status".to_string()], &config).await.unwrap();
+        assert!(status.stdout.lines().any(|l| l.split_whitespace().eq(["NoNewPrivs:", "1"])));
+
+        // The command leads its own process group: pgrp equals pid
+        let stat = SandboxExecutor::execute("cat", &["/proc/self/stat".to_string()], &config).await.unwrap();
+        let pid = stat.stdout.split_whitespace().next().unwrap().to_string();
+        let after_name = stat.stdout.rsplit_once(')').unwrap().1;
+        let pgrp = after_name.split_whitespace().nth(2).unwrap();
+        assert_eq!(pid, pgrp);
+
+        // Without isolation, the environment is inherited
+        let output = sh("env", &SandboxConfig::default()).await;
+        assert!(output.stdout.contains("CARGO_MANIFEST_DIR="));
+    }
+
+    #[tokio::test]
+    async fn test_timeout_kills_process_group() {
+        let dir = tempfile::tempdir().unwrap();
+        let pid_file = dir.path().join("bg.pid");
+        let config = SandboxConfig {
+            timeout_secs: 1,
+            process_group: true,
+            ..Default::default()
+        };
+
+        // A background grandchild that would outlive a plain kill of `sh`
+        let script = format!("sleep 60 & echo $! > {}; echo started; wait", pid_file.display());
+        let started = Instant::now();
+        let output = sh(&script, &config).await;
+
+        assert!(output.timed_out);
+        assert!(!output.success);
+        assert_eq!(output.signal, Some(libc::SIGKILL));
+        assert!(output.stdout.contains("started"), "partial output is kept");
+        assert!(started.elapsed() < Duration::from_secs(10));
+
+        let pid = std::fs::read_to_string(&pid_file).unwrap().trim().to_string();
+        std::thread::sleep(Duration::from_millis(200));
+        let alive = std::fs::read_to_string(format!("/proc/{}/stat", pid))
+            .map(|stat| {
+                let state = stat.rsplit_once(')').unwrap().1.split_whitespace().next().unwrap().to_string();
+                state != "Z"
+            })
+            .unwrap_or(false);
+        assert!(!alive, "background process {} survived the timeout", pid);
+    }
+
+    #[tokio::test]
+    async fn test_fast_command_is_not_timed_out() {
+        let config = SandboxConfig { timeout_secs: 5, ..Default::default() };
+        let output = sh("echo done", &config).await;
+
+        assert!(output.success);
+        assert!(!output.timed_out);
+        assert_eq!(output.stdout.trim(), "done");
+    }
+
+    #[tokio::test]
+    async fn test_pty_mode() {
+        let config = SandboxConfig {
+            pty: Some(TerminalSize { rows: 30, cols: 120 }),
+            process_group: true,
+            ..Default::default()
+        };
+        let args = vec!["-c".to_string(), "test -t 1 && echo tty; stty size; echo err >&2; sleep 60".to_string()];
+        let (input_tx, input_rx) = mpsc::channel(4);
+        let (output_tx, mut output_rx) = mpsc::channel(16);
+
+        let run = tokio::spawn(async move {
+            SandboxExecutor::execute_interactive("sh", &args, &config, input_rx, output_tx).await
+        });
+
+        // Stderr shares the terminal, so everything arrives as stdout
+        let mut output = Vec::new();
+        while !String::from_utf8_lossy(&output).contains("err") {
+            match output_rx.recv().await.unwrap() {
+                OutputChunk::Stdout(data) => output.extend_from_slice(&data),
+                OutputChunk::Stderr(data) => panic!("unexpected stderr {:?}", data),
+            }
+        }
+        assert_eq!(String::from_utf8_lossy(&output), "tty\r\n30 120\r\nerr\r\n");
+
+        // Ctrl-C through the terminal interrupts the session's foreground job
+        input_tx.send(CommandInput::Data(vec![0x03])).await.unwrap();
+        let output = run.await.unwrap().unwrap();
+        assert!(!output.success);
+        assert!(!output.timed_out);
+    }
+}
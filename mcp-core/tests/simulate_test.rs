@@ -0,0 +1,119 @@
+//! Tests for simulated command runs
+
+mod common;
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::path::Path;
+    use std::sync::Arc;
+    use tonic::Request;
+
+    use mcp_core::services::command_service::{command_service_server::CommandService, *};
+    use mcp_core::{AuditLogger, PolicyEngine};
+
+    use crate::common;
+
+    struct Harness {
+        svc: CommandServiceImpl,
+        audit: Arc<AuditLogger>,
+        policy: Arc<PolicyEngine>,
+    }
+
+    fn harness(root: &Path) -> Harness {
+        let base = common::Harness::new(root, &["sh", "touch"], |_| {});
+        Harness { svc: base.command_service(), audit: base.audit, policy: base.policy }
+    }
+
+    fn request(cwd: &Path, command: &str, args: &[&str]) -> RunCommandRequest {
+        RunCommandRequest { simulate: true, ..common::command(cwd, command, args) }
+    }
+
+    #[tokio::test]
+    async fn test_simulate_reports_changes_without_touching_cwd() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let project = root.join("project");
+        std::fs::create_dir(&project).unwrap();
+        std::fs::write(project.join("a.txt"), "one\n").unwrap();
+        std::fs::write(project.join("b.txt"), "gone\n").unwrap();
+        let harness = harness(&root);
+
+        let script = "echo two >> a.txt; rm b.txt; mkdir out; echo new > out/c.txt; pwd";
+        let mut req = request(&project, "sh", &["-c", script]);
+        req.approval_token = harness.policy.issue_approval(&req.approval_action()).await.token;
+        let response = harness.svc.run(Request::new(req)).await.unwrap().into_inner();
+
+        assert!(response.simulated);
+        assert!(response.success);
+        assert_ne!(response.stdout.trim(), project.to_string_lossy());
+
+        let changes: Vec<_> = response.changes.iter().map(|c| (c.path.as_str(), c.kind.as_str())).collect();
+        assert_eq!(changes, [("a.txt", "modified"), ("b.txt", "deleted"), ("out/c.txt", "created")]);
+        assert!(response.changes[0].diff.contains("--- a/a.txt\n+++ b/a.txt\n"), "diff: {}", response.changes[0].diff);
+        assert!(response.changes[0].diff.contains(" one\n+two\n"), "diff: {}", response.changes[0].diff);
+        assert!(response.changes[1].diff.contains("+++ /dev/null\n"));
+        assert!(response.changes[2].diff.contains("+new\n"));
+
+        // The real directory is untouched
+        assert_eq!(std::fs::read_to_string(project.join("a.txt")).unwrap(), "one\n");
+        assert!(project.join("b.txt").exists());
+        assert!(!project.join("out").exists());
+
+        let logs = harness.audit.query(Some("command"), Some("simulate"), None, None, 10).unwrap();
+        assert_eq!(logs.len(), 1);
+        assert!(logs[0].details.contains("3 files changed in a copy of"), "details: {}", logs[0].details);
+    }
+
+    #[tokio::test]
+    async fn test_simulate_redirects_absolute_symlinks_into_the_copy() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let project = root.join("project");
+        std::fs::create_dir_all(project.join("src")).unwrap();
+        std::fs::write(project.join("a.txt"), "one\n").unwrap();
+        std::os::unix::fs::symlink(project.join("a.txt"), project.join("a-link")).unwrap();
+        std::os::unix::fs::symlink(project.join("./src/.."), project.join("self")).unwrap();
+        let harness = harness(&root);
+
+        let mut req = request(&project, "sh", &["-c", "echo two >> a-link; echo new > self/src/b.txt"]);
+        req.approval_token = harness.policy.issue_approval(&req.approval_action()).await.token;
+        let response = harness.svc.run(Request::new(req)).await.unwrap().into_inner();
+        assert!(response.success, "{}", response.stderr);
+
+        // The writes land in the copy, and the links are not reported as changed
+        let changes: Vec<_> = response.changes.iter().map(|c| (c.path.as_str(), c.kind.as_str())).collect();
+        assert_eq!(changes, [("a.txt", "modified"), ("src/b.txt", "created")]);
+        assert_eq!(std::fs::read_to_string(project.join("a.txt")).unwrap(), "one\n");
+        assert!(!project.join("src/b.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn test_simulate_redirects_paths_and_needs_its_own_approval() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let project = root.join("project");
+        std::fs::create_dir(&project).unwrap();
+        let harness = harness(&root);
+
+        let target = project.join("new.txt");
+        let mut req = request(&project, "touch", &[target.to_str().unwrap()]);
+
+        // An approval to execute the command does not cover simulating it
+        let mut execute = req.clone();
+        execute.simulate = false;
+        req.approval_token = harness.policy.issue_approval(&execute.approval_action()).await.token;
+        let err = harness.svc.run(Request::new(req.clone())).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+
+        req.approval_token = harness.policy.issue_approval(&req.approval_action()).await.token;
+        let response = harness.svc.run(Request::new(req.clone())).await.unwrap().into_inner();
+        assert_eq!(response.changes.len(), 1);
+        assert_eq!(response.changes[0].path, "new.txt");
+        assert_eq!(response.changes[0].kind, "created");
+        assert!(!target.exists());
+
+        // Streaming calls cannot simulate
+        let err = harness.svc.run_stream(Request::new(req)).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    }
+}
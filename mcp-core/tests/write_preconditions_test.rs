@@ -0,0 +1,210 @@
+//! Tests for sha256 preconditions on file writes
+
+#[cfg(test)]
+mod tests {
+    use sha2::{Digest, Sha256};
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+    use tonic::{Code, Request};
+
+    use mcp_core::services::file_service::{file_service_server::FileService, *};
+    use mcp_core::{AuditLogger, Config, PolicyEngine, SnapshotManager};
+
+    fn sha256(content: &str) -> String {
+        hex::encode(Sha256::digest(content.as_bytes()))
+    }
+
+    struct Harness {
+        _dir: tempfile::TempDir,
+        root: PathBuf,
+        svc: FileServiceImpl,
+        policy: Arc<PolicyEngine>,
+        snapshots: Arc<SnapshotManager>,
+    }
+
+    impl Harness {
+        fn new() -> Self {
+            let dir = tempfile::tempdir().unwrap();
+            let root = dir.path().canonicalize().unwrap();
+            let config = Config {
+                allowed_paths: vec![root.clone()],
+                audit_db_path: root.join(".state/audit.db"),
+                snapshot_dir: root.join(".state/snapshots"),
+                ..Config::default()
+            };
+            let audit = Arc::new(AuditLogger::new(&config.audit_db_path).unwrap());
+            let snapshots = Arc::new(SnapshotManager::new(&config.snapshot_dir).unwrap());
+            let config = Arc::new(RwLock::new(config));
+            let policy = Arc::new(PolicyEngine::new(config.clone(), audit.clone()));
+            let svc = FileServiceImpl::new(config, audit, policy.clone(), snapshots.clone());
+            Self { _dir: dir, root, svc, policy, snapshots }
+        }
+
+        fn path(&self, name: &str) -> String {
+            self.root.join(name).to_string_lossy().to_string()
+        }
+
+        async fn create(&self, path: &str, content: &str, expected_sha256: String) -> Result<CreateFileResponse, tonic::Status> {
+            let mut req = CreateFileRequest {
+                path: path.to_string(),
+                content: content.to_string(),
+                expected_sha256,
+                ..Default::default()
+            };
+            req.approval_token = self.policy.issue_approval(&req.approval_action()).await.token;
+            self.svc.create_file(Request::new(req)).await.map(|r| r.into_inner())
+        }
+    }
+
+    fn assert_stale(result: Result<impl std::fmt::Debug, tonic::Status>, current: &str) {
+        let err = result.unwrap_err();
+        assert_eq!(err.code(), Code::FailedPrecondition);
+        assert!(err.message().contains(current), "{}", err.message());
+    }
+
+    #[tokio::test]
+    async fn test_create_checks_expected_sha256() {
+        let h = Harness::new();
+        let path = h.path("notes.txt");
+        std::fs::write(&path, "mine").unwrap();
+
+        // The user changed the file after it was read
+        std::fs::write(&path, "theirs").unwrap();
+        assert_stale(h.create(&path, "agent", sha256("mine")).await, &sha256("theirs"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "theirs");
+
+        let response = h.create(&path, "agent", sha256("theirs").to_uppercase()).await.unwrap();
+        assert_eq!(response.sha256, sha256("agent"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "agent");
+
+        // A file expected to exist must exist
+        let missing = h.path("missing.txt");
+        let err = h.create(&missing, "agent", sha256("")).await.unwrap_err();
+        assert_eq!(err.code(), Code::FailedPrecondition);
+        assert!(!Path::new(&missing).exists());
+
+        // Without a precondition the file is written as before
+        h.create(&path, "again", String::new()).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "again");
+    }
+
+    #[tokio::test]
+    async fn test_failed_precondition_keeps_approval() {
+        let h = Harness::new();
+        let path = h.path("notes.txt");
+        std::fs::write(&path, "theirs").unwrap();
+
+        let mut req = CreateFileRequest {
+            path: path.clone(),
+            content: "agent".to_string(),
+            expected_sha256: sha256("mine"),
+            ..Default::default()
+        };
+        req.approval_token = h.policy.issue_approval(&req.approval_action()).await.token;
+        assert_stale(h.svc.create_file(Request::new(req.clone())).await, &sha256("theirs"));
+
+        // Once the file is back to what was read, the same approval is used
+        std::fs::write(&path, "mine").unwrap();
+        h.svc.create_file(Request::new(req.clone())).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "agent");
+
+        // and only then used up
+        let err = h.svc.create_file(Request::new(req)).await.unwrap_err();
+        assert_eq!(err.code(), Code::PermissionDenied);
+
+        // The approval is checked before the precondition
+        let req = CreateFileRequest {
+            path: path.clone(),
+            content: "agent".to_string(),
+            expected_sha256: sha256("mine"),
+            ..Default::default()
+        };
+        let err = h.svc.create_file(Request::new(req)).await.unwrap_err();
+        assert!(!err.message().contains(&sha256("agent")), "{}", err.message());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_writes_with_the_same_precondition() {
+        let h = Harness::new();
+        let path = h.path("shared.txt");
+        std::fs::write(&path, "base").unwrap();
+
+        // Both writers read "base"; only the first may replace it
+        let (first, second) = tokio::join!(
+            h.create(&path, "first", sha256("base")),
+            h.create(&path, "second", sha256("base")),
+        );
+        assert_eq!(first.is_ok() as u8 + second.is_ok() as u8, 1, "{:?} {:?}", first, second);
+        let content = std::fs::read_to_string(&path).unwrap();
+        let winner = if first.is_ok() { "first" } else { "second" };
+        assert_eq!(content, winner);
+        assert_stale(if first.is_ok() { second } else { first }, &sha256(winner));
+    }
+
+    #[tokio::test]
+    async fn test_append_move_and_copy_check_expected_sha256() {
+        let h = Harness::new();
+        let log = h.path("log.txt");
+        let source = h.path("source.txt");
+        let dest = h.path("dest.txt");
+        std::fs::write(&log, "one\n").unwrap();
+        std::fs::write(&source, "source").unwrap();
+        std::fs::write(&dest, "dest").unwrap();
+
+        let append = |expected_sha256: String| {
+            let h = &h;
+            let req = AppendFileRequest {
+                path: log.clone(),
+                content: "two\n".to_string(),
+                expected_sha256,
+                ..Default::default()
+            };
+            async move {
+                let token = h.policy.issue_approval(&req.approval_action()).await.token;
+                h.svc.append_file(Request::new(AppendFileRequest { approval_token: token, ..req })).await
+            }
+        };
+        assert_stale(append(sha256("zero\n")).await, &sha256("one\n"));
+        append(sha256("one\n")).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&log).unwrap(), "one\ntwo\n");
+
+        // For copies and moves the precondition is on the file overwritten
+        let copy = |expected_sha256: String| {
+            let h = &h;
+            let req = CopyFileRequest {
+                from_path: source.clone(),
+                to_path: dest.clone(),
+                expected_sha256,
+                ..Default::default()
+            };
+            async move {
+                let token = h.policy.issue_approval(&req.approval_action()).await.token;
+                h.svc.copy_file(Request::new(CopyFileRequest { approval_token: token, ..req })).await
+            }
+        };
+        assert_stale(copy(sha256("source")).await, &sha256("dest"));
+        copy(sha256("dest")).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&dest).unwrap(), "source");
+
+        std::fs::write(&dest, "changed").unwrap();
+        let mut req = MoveFileRequest {
+            from_path: source.clone(),
+            to_path: dest.clone(),
+            expected_sha256: sha256("source"),
+            ..Default::default()
+        };
+        req.approval_token = h.policy.issue_approval(&req.approval_action()).await.token;
+        assert_stale(h.svc.move_file(Request::new(req.clone())).await, &sha256("changed"));
+        assert!(Path::new(&source).exists());
+
+        // The file a move replaces is snapshotted along with the one moved
+        req.expected_sha256 = sha256("changed");
+        req.approval_token = h.policy.issue_approval(&req.approval_action()).await.token;
+        let response = h.svc.move_file(Request::new(req)).await.unwrap().into_inner();
+        assert_eq!(std::fs::read_to_string(&dest).unwrap(), "source");
+        h.snapshots.restore(&response.snapshot_id, None).unwrap();
+        assert_eq!(std::fs::read_to_string(&source).unwrap(), "source");
+        assert_eq!(std::fs::read_to_string(&dest).unwrap(), "changed");
+    }
+}